@@ -3,15 +3,6 @@ use bo_core::{Result, CoreError};
 use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
 use chacha20poly1305::aead::{Aead, KeyInit};
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
-    }
-}
-
 fn le64_to_bytes(v: u64) -> [u8;8] {
     v.to_le_bytes()
 }
@@ -21,7 +12,7 @@ fn ns_and_counter_to_nonce(ns: &[u8], counter: u64) -> [u8;12] {
     let mut nonce = [0u8;12];
     let cbytes = le64_to_bytes(counter);
     for i in 0..8 { nonce[i] = ns[i] ^ cbytes[i]; }
-    for i in 8..12 { nonce[i] = ns[i]; }
+    nonce[8..12].copy_from_slice(&ns[8..12]);
     nonce
 }
 
@@ -45,4 +36,11 @@ pub fn open_chacha20poly1305(key: &[u8], ns: &[u8], counter_le64: u64, aad: &[u8
     Ok(pt)
 }
 
-
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn it_works() {
+        let result = 2 + 2;
+        assert_eq!(result, 4);
+    }
+}
@@ -4,9 +4,18 @@ use std::path::Path;
 use clap::Parser;
 use hello_template::{HelloTemplate, Encoder};
 use hello_template::export_utls;
-use hello_snapshot::pcap_importer::import_from_pcap;
-use hello_snapshot::snapshot_to_ja3;
-use serde_json;
+use hello_template::rng::HelloRng;
+use hello_template::ech::{encrypt_client_hello, parse_ech_config_list};
+use hello_template::psk::{self, ResumptionTicket};
+use hello_template::quic_initial::QuicInitialOptions;
+use hello_template::pcap_writer::{udp_pcap, Direction, PcapOptions};
+use hello_template::http_template::{encode_h2_start, encode_h3_streams, HttpTemplate};
+use hello_snapshot::filter::FlowFilter;
+use hello_snapshot::pcap_importer::{import_all_from_pcap_filtered, import_from_pcap};
+use hello_snapshot::{snapshot_to_ja3, snapshot_to_ja4};
+use hello_snapshot::hrr::HrrOptions;
+use hello_snapshot::golden::{build_golden_dir, check_golden, write_golden, GOLDEN_SEED, GOLDEN_TEMPLATE};
+use hello_snapshot::tls_capture::{serve, CaptureOptions};
 
 #[derive(Parser)]
 struct Cmd {
@@ -21,21 +30,113 @@ enum Commands {
         #[arg(long)] out: Option<String>,
         #[arg(long)] emit_pcap: bool,
         #[arg(long)] export_utls: Option<String>,
+        /// Opciones QUIC (JSON de QuicInitialOptions): escribe también los datagramas Initial cifrados
+        #[arg(long)] quic_initial: Option<String>,
+        /// Plantilla HTTP (JSON de HttpTemplate): escribe el arranque H2 y los streams H3 del cliente
+        #[arg(long)] http: Option<String>,
+        /// Exporta la parte HTTP/2 de --http como snippet Go (fhttp/http2)
+        #[arg(long)] export_h2_go: Option<String>,
+        /// Modo determinista: random, session ID, key shares y GREASE salen de un RNG con esta semilla
+        #[arg(long)] seed: Option<u64>,
+        /// Valores por conexión frescos (RNG del sistema), como un cliente real
+        #[arg(long, conflicts_with = "seed")] fresh: bool,
+        /// Con --seed/--fresh: escribe las partes privadas de los key shares generados (JSON)
+        #[arg(long)] keys_out: Option<String>,
+        /// Con --seed/--fresh: ECHConfigList (binario) para cifrar el hello como ClientHelloInner;
+        /// el inner (con la session ID del outer) se escribe en <out>.inner.bin
+        #[arg(long)] ech_config: Option<String>,
+        /// Reanudación: tickets (JSON, lista de ResumptionTicket) para pre_shared_key con binders
+        #[arg(long, conflicts_with = "ech_config")] resume: Option<String>,
+        /// Con --resume: ofrece early_data y escribe client_early_traffic_secret en <out>.early.secret
+        #[arg(long, requires = "resume")] early_data: bool,
+    },
+    /// Regenera (o con --check compara) los bundles golden de cada <dir>/<nombre>/template.json
+    Golden {
+        #[arg(long, default_value = "crates/utls-template/hello-template/tests/goldens")] dir: String,
+        #[arg(long, default_value_t = GOLDEN_SEED)] seed: u64,
+        #[arg(long)] check: bool,
     },
     Selftest {
         #[arg(long)] pcap: String,
         #[arg(long)] template: String,
+    },
+    /// Lista todos los ClientHello de una captura, uno por flujo (JSON por línea)
+    Flows {
+        #[arg(long)] pcap: String,
+        /// Puertos a considerar (repetible); sin puertos se detecta TLS/QUIC por contenido
+        #[arg(long = "port")] ports: Vec<u16>,
+        /// Redes CIDR de cualquiera de los extremos (repetible)
+        #[arg(long = "cidr")] cidrs: Vec<String>,
+        #[arg(long)] sni: Option<String>,
+        #[arg(long = "alpn")] alpn: Vec<String>,
+        #[arg(long)] from_us: Option<u64>,
+        #[arg(long)] until_us: Option<u64>,
+    },
+    /// Listener local para capturar el ClientHello de un navegador (snapshot_tls_N.json en --out)
+    Capture {
+        #[arg(long, default_value = "127.0.0.1:8443")] listen: String,
+        #[arg(long)] allow_hrr: bool,
+        /// Grupo que pide el HRR (e.g. 0x0017); por defecto el primero ofrecido sin key share
+        #[arg(long, value_parser = parse_u16)] hrr_group: Option<u16>,
+        /// Cookie (hex) que el HRR pide devolver en el segundo ClientHello
+        #[arg(long)] hrr_cookie: Option<String>,
+        #[arg(long)] emit_pcap: bool,
+        #[arg(long, default_value = ".")] out: String,
+        /// Número de conexiones a capturar (sin límite si se omite)
+        #[arg(long)] count: Option<usize>,
+    }
+}
+
+fn parse_u16(s: &str) -> Result<u16, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
     }
+    .map_err(|e| format!("invalid u16 {:?}: {}", s, e))
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.is_ascii() { return Err(format!("invalid hex {:?}", s)); }
+    if !s.len().is_multiple_of(2) { return Err("odd number of hex digits".into()); }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|e| e.to_string())).collect()
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cmd::parse();
     match cli.cmd {
-        Commands::Gen { template, out, emit_pcap, export_utls } => {
+        Commands::Gen { template, out, emit_pcap, export_utls, quic_initial, http, export_h2_go, seed, fresh, keys_out, ech_config, resume, early_data } => {
             let mut f = File::open(&template)?;
             let mut s = String::new();
             f.read_to_string(&mut s)?;
-            let tpl: HelloTemplate = serde_json::from_str(&s)?;
+            let mut tpl: HelloTemplate = serde_json::from_str(&s)?;
+            let rng = match seed {
+                Some(seed) => Some(HelloRng::seeded(seed)),
+                None => fresh.then(HelloRng::from_entropy),
+            };
+            let mut inner = None;
+            if let Some(mut rng) = rng {
+                let (hello, keys) = Encoder::instantiate_with_keys(&tpl, &mut rng);
+                tpl = hello;
+                if let Some(p) = &keys_out {
+                    std::fs::write(p, serde_json::to_string_pretty(&keys)?)?;
+                    println!("Wrote {} key share secrets to {}", keys.len(), p);
+                }
+                if let Some(p) = &ech_config {
+                    let configs = parse_ech_config_list(&std::fs::read(p)?)?;
+                    let hellos = encrypt_client_hello(&tpl, &configs[0], &mut rng)?;
+                    tpl = hellos.outer;
+                    inner = Some(hellos.inner);
+                }
+            } else if ech_config.is_some() {
+                return Err("--ech-config needs --seed or --fresh".into());
+            }
+            let mut early_secret = None;
+            if let Some(p) = &resume {
+                let tickets: Vec<ResumptionTicket> = serde_json::from_str(&std::fs::read_to_string(p)?)?;
+                let resumed = psk::resume(&tpl, &tickets, early_data)?;
+                tpl = resumed.hello;
+                early_secret = resumed.client_early_traffic_secret;
+            }
             let enc = Encoder::encode_client_hello(&tpl, emit_pcap)?;
             let out_path = out.unwrap_or_else(|| "clienthello.bin".to_string());
             std::fs::write(&out_path, &enc.raw_bytes)?;
@@ -45,11 +146,57 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 std::fs::write(&pcap_path, p)?;
                 println!("Wrote pcap {}", pcap_path.display());
             }
+            if let Some(secret) = early_secret {
+                let secret_path = Path::new(&out_path).with_extension("early.secret");
+                std::fs::write(&secret_path, secret)?;
+                println!("Wrote client_early_traffic_secret to {}", secret_path.display());
+            }
+            if let Some(inner) = inner {
+                let inner_path = Path::new(&out_path).with_extension("inner.bin");
+                let hs = Encoder::encode_handshake(&inner);
+                std::fs::write(&inner_path, &hs)?;
+                println!("Wrote ECH inner hello {} ({} bytes)", inner_path.display(), hs.len());
+            }
+            if let Some(opts_path) = quic_initial {
+                let opts: QuicInitialOptions = serde_json::from_str(&std::fs::read_to_string(&opts_path)?)?;
+                let datagrams = Encoder::encode_quic_initial(&tpl, &opts)?;
+                for (i, dg) in datagrams.iter().enumerate() {
+                    let dg_path = Path::new(&out_path).with_extension(format!("initial{}.bin", i));
+                    std::fs::write(&dg_path, dg)?;
+                    println!("Wrote QUIC Initial {} ({} bytes)", dg_path.display(), dg.len());
+                }
+                if emit_pcap {
+                    let udp: Vec<(Direction, &[u8])> = datagrams.iter().map(|d| (Direction::Client, d.as_slice())).collect();
+                    let pcap_path = Path::new(&out_path).with_extension("quic.pcap");
+                    std::fs::write(&pcap_path, udp_pcap(&PcapOptions::default(), &udp)?)?;
+                    println!("Wrote pcap {}", pcap_path.display());
+                }
+            }
             if let Some(go_path) = export_utls {
-                let go_snip = export_utls::export_utls_go(&tpl);
+                let go_snip = export_utls::export_utls_go(&tpl)?;
                 std::fs::write(&go_path, go_snip)?;
                 println!("Wrote uTLS Go snippet {}", go_path);
             }
+            if let Some(http_path) = http {
+                let http: HttpTemplate = serde_json::from_str(&std::fs::read_to_string(&http_path)?)?;
+                if let Some(h2) = &http.h2 {
+                    let h2_path = Path::new(&out_path).with_extension("h2.bin");
+                    let raw = encode_h2_start(h2, &http.request)?;
+                    std::fs::write(&h2_path, &raw)?;
+                    println!("Wrote HTTP/2 start {} ({} bytes)", h2_path.display(), raw.len());
+                    if let Some(go_path) = &export_h2_go {
+                        std::fs::write(go_path, export_utls::export_http2_go(h2))?;
+                        println!("Wrote HTTP/2 Go snippet {}", go_path);
+                    }
+                }
+                if let Some(h3) = &http.h3 {
+                    for (id, data) in encode_h3_streams(h3, &http.request)? {
+                        let stream_path = Path::new(&out_path).with_extension(format!("h3.stream{}.bin", id));
+                        std::fs::write(&stream_path, &data)?;
+                        println!("Wrote HTTP/3 stream {} ({} bytes)", stream_path.display(), data.len());
+                    }
+                }
+            }
         }
         Commands::Selftest { pcap, template } => {
             println!("Importing pcap: {}", pcap);
@@ -59,6 +206,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             // Add JA3 calculation
             snapshot_to_ja3(&mut snap);
             println!("PCAP JA3: {}", snap.ja3.clone().unwrap_or_default());
+            snapshot_to_ja4(&mut snap);
+            println!("PCAP JA4: {}", snap.ja4.clone().unwrap_or_default());
+            if let (Some(ja3s), Some(ja4s)) = (&snap.ja3s, &snap.ja4s) {
+                println!("PCAP JA3S: {} JA4S: {}", ja3s, ja4s);
+            }
 
             let mut f = File::open(&template)?;
             let mut s = String::new();
@@ -70,6 +222,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             generated.raw_client_hello = enc.raw_bytes;
             snapshot_to_ja3(&mut generated);
             println!("Generated JA3: {}", generated.ja3.clone().unwrap_or_default());
+            snapshot_to_ja4(&mut generated);
+            println!("Generated JA4: {}", generated.ja4.clone().unwrap_or_default());
 
             if snap.ja3 == generated.ja3 {
                 println!("SELFTEST PASS: JA3 match");
@@ -78,6 +232,65 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 return Err("selftest failed".into());
             }
         }
+        Commands::Flows { pcap, ports, cidrs, sni, alpn, from_us, until_us } => {
+            let filter = FlowFilter {
+                ports,
+                cidrs: cidrs.iter().map(|c| c.parse()).collect::<Result<_, String>>()?,
+                sni_glob: sni,
+                alpn,
+                time_from_us: from_us,
+                time_until_us: until_us,
+            };
+            for snap in import_all_from_pcap_filtered(&pcap, filter)? {
+                let mut snap = snap?;
+                snapshot_to_ja3(&mut snap);
+                snapshot_to_ja4(&mut snap);
+                println!("{}", serde_json::to_string(&snap)?);
+            }
+        }
+        Commands::Golden { dir, seed, check } => {
+            let mut dirs: Vec<_> = std::fs::read_dir(&dir)?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.join(GOLDEN_TEMPLATE).is_file())
+                .collect();
+            dirs.sort();
+            for d in dirs {
+                let bundle = build_golden_dir(&d, seed)?;
+                if check {
+                    check_golden(&d, &bundle)?;
+                    println!("ok {}", d.display());
+                } else {
+                    write_golden(&d, &bundle)?;
+                    println!("Wrote {}", d.display());
+                }
+            }
+        }
+        Commands::Capture { listen, allow_hrr, hrr_group, hrr_cookie, emit_pcap, out, count } => {
+            let listener = std::net::TcpListener::bind(&listen)?;
+            println!("Listening on {} (point the browser at https://{}/)", listen, listen);
+            std::fs::create_dir_all(&out)?;
+            let mut n = 0;
+            let hrr = HrrOptions { group: hrr_group, cookie: hrr_cookie.as_deref().map(parse_hex).transpose()?, cipher_suite: None };
+            serve(&listener, &CaptureOptions { allow_hrr, emit_pcap, hrr }, count, |peer, res| {
+                let res = match res {
+                    Ok(r) => r,
+                    Err(e) => return eprintln!("{}: capture failed: {}", peer, e),
+                };
+                let base = Path::new(&out).join(format!("snapshot_tls_{}", n));
+                n += 1;
+                let written = serde_json::to_string_pretty(&res)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| std::fs::write(base.with_extension("json"), json).map_err(|e| e.to_string()))
+                    .and_then(|_| match &res.pcap_bytes {
+                        Some(p) => std::fs::write(base.with_extension("pcap"), p).map_err(|e| e.to_string()),
+                        None => Ok(()),
+                    });
+                match written {
+                    Ok(()) => println!("{}: {} (ja4 {})", peer, base.with_extension("json").display(), res.initial_client_hello.ja4.as_deref().unwrap_or("-")),
+                    Err(e) => eprintln!("{}: writing snapshot failed: {}", peer, e),
+                }
+            })?;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,154 @@
+// Filtros para la extracción de ClientHello en pcap_importer.
+//
+// Un filtro vacío (`FlowFilter::default()`) acepta cualquier flujo: la detección de TLS/QUIC se
+// hace por contenido, no por puerto.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+use hello_template::decode::decode_client_hello;
+use hello_template::Extension;
+
+/// IPv4/IPv6 network in CIDR notation (`10.0.0.0/8`, `2001:db8::/32`).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_match(&net.octets(), &ip.octets(), self.prefix),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => prefix_match(&net.octets(), &ip.octets(), self.prefix),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_match(net: &[u8], ip: &[u8], prefix: u8) -> bool {
+    let bits = (prefix as usize).min(net.len() * 8);
+    let (full, rest) = (bits / 8, bits % 8);
+    if net[..full] != ip[..full] { return false; }
+    if rest == 0 { return true; }
+    let mask = 0xffu8 << (8 - rest);
+    (net[full] & mask) == (ip[full] & mask)
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|e| format!("invalid cidr address {}: {}", s, e))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().map_err(|e| format!("invalid cidr prefix {}: {}", s, e))?,
+            None => max,
+        };
+        if prefix > max { return Err(format!("cidr prefix out of range: {}", s)); }
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+/// Case-insensitive glob match supporting `*` (any run) and `?` (one char).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.to_ascii_lowercase().chars().collect();
+    let t: Vec<char> = text.to_ascii_lowercase().chars().collect();
+    let (mut pi, mut ti) = (0usize, 0usize);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Filter passed to the pcap importer. Empty fields match everything.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct FlowFilter {
+    #[serde(default)]
+    pub ports: Vec<u16>,              // cualquiera de los dos extremos
+    #[serde(default)]
+    pub cidrs: Vec<Cidr>,             // cualquiera de los dos extremos
+    #[serde(default)]
+    pub sni_glob: Option<String>,     // p.ej. "*.example.com"
+    #[serde(default)]
+    pub alpn: Vec<String>,            // el ClientHello ofrece al menos uno
+    #[serde(default)]
+    pub time_from_us: Option<u64>,    // ventana sobre el instante del ClientHello
+    #[serde(default)]
+    pub time_until_us: Option<u64>,
+}
+
+impl FlowFilter {
+    /// Packet-level check on the 5-tuple endpoints.
+    pub fn matches_endpoints(&self, src: (IpAddr, u16), dst: (IpAddr, u16)) -> bool {
+        let port_ok = self.ports.is_empty() || self.ports.contains(&src.1) || self.ports.contains(&dst.1);
+        let cidr_ok = self.cidrs.is_empty() || self.cidrs.iter().any(|c| c.contains(&src.0) || c.contains(&dst.0));
+        port_ok && cidr_ok
+    }
+
+    /// Flow-level check once the ClientHello is known.
+    pub fn matches_client_hello(&self, raw: &[u8], ts_us: u64) -> bool {
+        if self.time_from_us.map(|t| ts_us < t).unwrap_or(false) { return false; }
+        if self.time_until_us.map(|t| ts_us > t).unwrap_or(false) { return false; }
+        if self.sni_glob.is_none() && self.alpn.is_empty() { return true; }
+        let Ok(tpl) = decode_client_hello(raw) else { return false };
+        if let Some(glob) = &self.sni_glob {
+            let sni = tpl.extensions.iter().find_map(|e| match e {
+                Extension::ServerName { host } => Some(host.as_str()),
+                _ => None,
+            });
+            if !sni.map(|h| glob_match(glob, h)).unwrap_or(false) { return false; }
+        }
+        if !self.alpn.is_empty() {
+            let offered = tpl.extensions.iter().find_map(|e| match e {
+                Extension::Alpn { protocols } => Some(protocols),
+                _ => None,
+            });
+            if !offered.map(|p| p.iter().any(|x| self.alpn.contains(x))).unwrap_or(false) { return false; }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr_and_glob() {
+        let net: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(net.contains(&"10.1.200.3".parse().unwrap()));
+        assert!(!net.contains(&"10.2.0.1".parse().unwrap()));
+        let v6: Cidr = "2001:db8::/33".parse().unwrap();
+        assert!(v6.contains(&"2001:db8:7fff::1".parse().unwrap()));
+        assert!(!v6.contains(&"2001:db8:8000::1".parse().unwrap()));
+        assert!("10.0.0.0/40".parse::<Cidr>().is_err());
+
+        assert!(glob_match("*.example.com", "www.Example.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(glob_match("htx-?.lab", "htx-3.lab"));
+
+        let f = FlowFilter { ports: vec![8443, 4433], ..Default::default() };
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        assert!(f.matches_endpoints((ip, 50000), (ip, 4433)));
+        assert!(!f.matches_endpoints((ip, 50000), (ip, 443)));
+    }
+}
@@ -0,0 +1,123 @@
+// Bundles golden por plantilla: todo lo que emite el generador en modo determinista (RNG con
+// semilla, reloj fijo del pcap), para comparar byte a byte en `cargo test`.
+
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use hello_template::export_utls::{export_utls_go, export_utls_json};
+use hello_template::pcap_writer::{tcp_pcap, Direction, PcapOptions};
+use hello_template::rng::HelloRng;
+use hello_template::{Encoder, HelloTemplate};
+
+use crate::ja4::{self, Ja4Transport};
+
+/// Seed used for the checked-in goldens.
+pub const GOLDEN_SEED: u64 = 0x676f_6c64;
+
+/// Input file of a golden directory; everything else in it is generated.
+pub const GOLDEN_TEMPLATE: &str = "template.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GoldenFingerprints {
+    pub ja3: String,
+    pub ja4: String,
+    pub ja4_r: String,
+}
+
+/// Generated files of one template, as (file name, contents).
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoldenBundle {
+    pub files: Vec<(&'static str, Vec<u8>)>,
+}
+
+fn json<T: Serialize>(v: &T) -> Result<Vec<u8>, String> {
+    let mut s = serde_json::to_string_pretty(v).map_err(|e| e.to_string())?;
+    s.push('\n');
+    Ok(s.into_bytes())
+}
+
+/// Raw hello, pcap, JA3/JA4, instantiated template JSON and uTLS JSON and Go exports of `t` with `seed`.
+pub fn build_golden(t: &HelloTemplate, seed: u64) -> Result<GoldenBundle, String> {
+    let hello = Encoder::instantiate(t, &mut HelloRng::seeded(seed));
+    let enc = Encoder::encode_client_hello(&hello, false)?;
+    // direcciones y reloj fijos de PcapOptions::default()
+    let pcap = tcp_pcap(&PcapOptions::default(), &[(Direction::Client, &enc.raw_bytes)])?;
+    let fp = GoldenFingerprints {
+        ja3: crate::ja3::ja3_from_raw(&enc.raw_bytes)?,
+        ja4: ja4::ja4(&enc.raw_bytes, Ja4Transport::Tcp)?,
+        ja4_r: ja4::ja4_r(&enc.raw_bytes, Ja4Transport::Tcp)?,
+    };
+    Ok(GoldenBundle {
+        files: vec![
+            ("hello.bin", enc.raw_bytes),
+            ("hello.pcap", pcap),
+            ("fingerprints.json", json(&fp)?),
+            ("hello.json", json(&hello)?),
+            ("utls.json", json(&export_utls_json(&hello)?)?),
+            ("utls.go", export_utls_go(&hello)?.into_bytes()),
+        ],
+    })
+}
+
+/// Reads `dir/template.json` and builds its bundle.
+pub fn build_golden_dir(dir: &Path, seed: u64) -> Result<GoldenBundle, String> {
+    let path = dir.join(GOLDEN_TEMPLATE);
+    let s = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let t: HelloTemplate = serde_json::from_str(&s).map_err(|e| format!("{}: {}", path.display(), e))?;
+    build_golden(&t, seed)
+}
+
+pub fn write_golden(dir: &Path, bundle: &GoldenBundle) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    for (name, data) in &bundle.files {
+        std::fs::write(dir.join(name), data).map_err(|e| format!("{}: {}", dir.join(name).display(), e))?;
+    }
+    Ok(())
+}
+
+/// Compares `bundle` with the files in `dir`; the error names every missing or differing file.
+pub fn check_golden(dir: &Path, bundle: &GoldenBundle) -> Result<(), String> {
+    let stale: Vec<String> = bundle
+        .files
+        .iter()
+        .filter(|(name, data)| std::fs::read(dir.join(name)).ok().as_ref() != Some(data))
+        .map(|(name, _)| dir.join(name).display().to_string())
+        .collect();
+    if stale.is_empty() { Ok(()) } else { Err(format!("golden mismatch: {}", stale.join(", "))) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hello_template::grease::GreaseMode;
+    use hello_template::Extension;
+
+    #[test]
+    fn test_bundle_is_deterministic() {
+        let t = HelloTemplate {
+            record_version: Some(0x0301),
+            session_id: Some(vec![0; 32]),
+            cipher_suites: vec![0x0a0a, 0x1301, 0x1302],
+            cipher_grease_slots: vec![0],
+            extensions: vec![
+                Extension::Grease { typ: 0x0a0a, bytes: vec![] },
+                Extension::ServerName { host: "golden.test".into() },
+                Extension::SupportedVersions { versions: vec![0x0a0a, 0x0304], grease_slots: vec![0] },
+            ],
+            grease_mode: GreaseMode::Random,
+            ..Default::default()
+        };
+        let a = build_golden(&t, GOLDEN_SEED).unwrap();
+        assert_eq!(a, build_golden(&t, GOLDEN_SEED).unwrap());
+        assert_ne!(a.files[0], build_golden(&t, GOLDEN_SEED + 1).unwrap().files[0]);
+        let utls_json = &a.files.iter().find(|(name, _)| *name == "utls.json").unwrap().1;
+        assert!(String::from_utf8_lossy(utls_json).contains(r#""name": "SNIExtension""#));
+
+        let dir = std::env::temp_dir().join(format!("golden-test-{}", std::process::id()));
+        write_golden(&dir, &a).unwrap();
+        assert!(check_golden(&dir, &a).is_ok());
+        std::fs::write(dir.join("utls.go"), "stale").unwrap();
+        assert!(check_golden(&dir, &a).unwrap_err().contains("utls.go"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
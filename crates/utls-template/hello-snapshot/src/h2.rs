@@ -0,0 +1,199 @@
+// Decoder del primer vuelo HTTP/2 del cliente (RFC 9113): preface, SETTINGS, WINDOW_UPDATE,
+// PRIORITY y el primer HEADERS (con sus CONTINUATION) decodificado con HPACK completo.
+// Es lo que usa la huella estilo Akamai: `settings|window|priority|orden de pseudo-headers`.
+
+use serde::{Serialize, Deserialize};
+
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_PRIORITY: u8 = 0x2;
+const FRAME_SETTINGS: u8 = 0x4;
+const FRAME_WINDOW_UPDATE: u8 = 0x8;
+const FRAME_CONTINUATION: u8 = 0x9;
+
+const FLAG_ACK: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+const FLAG_PADDED: u8 = 0x8;
+const FLAG_PRIORITY: u8 = 0x20;
+
+/// A stream dependency, either from a PRIORITY frame or from the HEADERS priority block.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct H2Priority {
+    pub stream_id: u32,
+    pub exclusive: bool,
+    pub depends_on: u32,
+    pub weight: u16, // 1..=256 (valor en el wire + 1)
+}
+
+/// Client connection start as seen on the wire, everything in wire order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct H2ClientStart {
+    pub settings: Vec<(u16, u32)>,
+    pub window_update: Option<u32>, // incremento de ventana de la conexión (stream 0)
+    pub priorities: Vec<H2Priority>,
+    pub headers_priority: Option<H2Priority>,
+    pub headers: Vec<(String, String)>, // primer HEADERS, vacío si la captura termina antes
+}
+
+impl H2ClientStart {
+    /// Pseudo-header names of the first request, in order (`:method`, `:authority`, ...).
+    pub fn pseudo_headers_order(&self) -> Vec<String> {
+        self.headers.iter().filter(|(n, _)| n.starts_with(':')).map(|(n, _)| n.clone()).collect()
+    }
+
+    /// Akamai HTTP/2 fingerprint, e.g. `1:65536;2:0;4:6291456;6:262144|15663105|0|m,a,s,p`.
+    pub fn akamai_fingerprint(&self) -> String {
+        let settings = self.settings.iter().map(|(id, v)| format!("{}:{}", id, v)).collect::<Vec<_>>().join(";");
+        let window = self.window_update.unwrap_or(0);
+        let priority = if self.priorities.is_empty() {
+            "0".to_string()
+        } else {
+            self.priorities
+                .iter()
+                .map(|p| format!("{}:{}:{}:{}", p.stream_id, p.exclusive as u8, p.depends_on, p.weight))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let pseudo = self
+            .pseudo_headers_order()
+            .iter()
+            .filter_map(|n| n.chars().nth(1))
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{}|{}|{}|{}", settings, window, priority, pseudo)
+    }
+}
+
+struct Frame<'a> {
+    typ: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: &'a [u8],
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+}
+
+// Lee un frame completo; None si el buffer termina a mitad (captura truncada).
+fn next_frame<'a>(raw: &'a [u8], idx: &mut usize) -> Option<Frame<'a>> {
+    let hdr = raw.get(*idx..*idx + 9)?;
+    let len = ((hdr[0] as usize) << 16) | ((hdr[1] as usize) << 8) | hdr[2] as usize;
+    let payload = raw.get(*idx + 9..*idx + 9 + len)?;
+    *idx += 9 + len;
+    Some(Frame { typ: hdr[3], flags: hdr[4], stream_id: read_u32(&hdr[5..9]) & 0x7fff_ffff, payload })
+}
+
+fn parse_priority(stream_id: u32, b: &[u8]) -> Result<H2Priority, String> {
+    if b.len() < 5 { return Err("priority block truncated".into()); }
+    let dep = read_u32(b);
+    Ok(H2Priority { stream_id, exclusive: dep & 0x8000_0000 != 0, depends_on: dep & 0x7fff_ffff, weight: b[4] as u16 + 1 })
+}
+
+/// Decodes the client side of an HTTP/2 connection up to (and including) the first HEADERS block.
+///
+/// Bytes before the connection preface are skipped. A capture that ends before the first request
+/// still yields the SETTINGS/WINDOW_UPDATE/PRIORITY frames seen so far.
+pub fn decode_client_start(raw: &[u8]) -> Result<H2ClientStart, String> {
+    let pos = raw.windows(PREFACE.len()).position(|w| w == PREFACE).ok_or("HTTP/2 connection preface not found")?;
+    let mut idx = pos + PREFACE.len();
+    let mut out = H2ClientStart::default();
+    let mut block: Option<Vec<u8>> = None; // bloque HPACK a medio recibir (HEADERS sin END_HEADERS)
+    let mut complete = false;
+
+    while let Some(f) = next_frame(raw, &mut idx) {
+        if let Some(buf) = block.as_mut() {
+            if f.typ != FRAME_CONTINUATION { return Err("expected CONTINUATION after HEADERS".into()); }
+            buf.extend_from_slice(f.payload);
+            if f.flags & FLAG_END_HEADERS != 0 { complete = true; break; }
+            continue;
+        }
+        match f.typ {
+            FRAME_SETTINGS if f.flags & FLAG_ACK == 0 => {
+                if f.payload.len() % 6 != 0 { return Err("SETTINGS payload not a multiple of 6".into()); }
+                for s in f.payload.chunks(6) {
+                    out.settings.push((u16::from_be_bytes([s[0], s[1]]), read_u32(&s[2..6])));
+                }
+            }
+            FRAME_WINDOW_UPDATE if f.stream_id == 0 && out.window_update.is_none() => {
+                if f.payload.len() != 4 { return Err("WINDOW_UPDATE payload must be 4 bytes".into()); }
+                out.window_update = Some(read_u32(f.payload) & 0x7fff_ffff);
+            }
+            FRAME_PRIORITY => out.priorities.push(parse_priority(f.stream_id, f.payload)?),
+            FRAME_HEADERS => {
+                let mut p = f.payload;
+                let mut pad = 0usize;
+                if f.flags & FLAG_PADDED != 0 {
+                    pad = *p.first().ok_or("HEADERS pad length missing")? as usize;
+                    p = &p[1..];
+                }
+                if f.flags & FLAG_PRIORITY != 0 {
+                    out.headers_priority = Some(parse_priority(f.stream_id, p)?);
+                    p = &p[5..];
+                }
+                if pad > p.len() { return Err("HEADERS padding exceeds payload".into()); }
+                block = Some(p[..p.len() - pad].to_vec());
+                if f.flags & FLAG_END_HEADERS != 0 { complete = true; break; }
+            }
+            _ => {}
+        }
+    }
+
+    // sin END_HEADERS el bloque está incompleto y no se puede decodificar
+    if let Some(buf) = block.filter(|_| complete) {
+        let headers = hpack::Decoder::new().decode(&buf).map_err(|e| format!("HPACK decode error: {:?}", e))?;
+        out.headers = headers
+            .into_iter()
+            .map(|(n, v)| (String::from_utf8_lossy(&n).into_owned(), String::from_utf8_lossy(&v).into_owned()))
+            .collect();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(typ: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut f = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        f.extend_from_slice(&[typ, flags]);
+        f.extend_from_slice(&stream_id.to_be_bytes());
+        f.extend_from_slice(payload);
+        f
+    }
+
+    #[test]
+    fn test_decode_chrome_like_start() {
+        let mut raw = PREFACE.to_vec();
+        let settings: Vec<u8> = [(1u16, 65536u32), (2, 0), (4, 6_291_456), (6, 262_144)]
+            .iter()
+            .flat_map(|(id, v)| id.to_be_bytes().into_iter().chain(v.to_be_bytes()))
+            .collect();
+        raw.extend(frame(FRAME_SETTINGS, 0, 0, &settings));
+        raw.extend(frame(FRAME_WINDOW_UPDATE, 0, 0, &15_663_105u32.to_be_bytes()));
+        // HEADERS con bloque de prioridad (exclusivo, peso 256) y el bloque Huffman partido en un
+        // CONTINUATION; los pseudo-headers se reordenan a m,a,s,p respecto a RFC 7541 C.4.1
+        let block = [0x82, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff, 0x86, 0x84];
+        let mut headers = vec![0x80, 0, 0, 0, 0xff];
+        headers.extend_from_slice(&block[..6]);
+        raw.extend(frame(FRAME_HEADERS, 0x01 | FLAG_PRIORITY, 1, &headers));
+        raw.extend(frame(FRAME_CONTINUATION, FLAG_END_HEADERS, 1, &block[6..]));
+
+        let start = decode_client_start(&raw).unwrap();
+        assert_eq!(start.headers[1], (":authority".to_string(), "www.example.com".to_string()));
+        assert_eq!(start.headers_priority, Some(H2Priority { stream_id: 1, exclusive: true, depends_on: 0, weight: 256 }));
+        assert_eq!(start.akamai_fingerprint(), "1:65536;2:0;4:6291456;6:262144|15663105|0|m,a,s,p");
+
+        // captura cortada antes del primer request: solo SETTINGS y WINDOW_UPDATE
+        let partial = decode_client_start(&raw[..raw.len() - 10]).unwrap();
+        assert!(partial.headers.is_empty());
+        assert_eq!(partial.akamai_fingerprint(), "1:65536;2:0;4:6291456;6:262144|15663105|0|");
+
+        let mut firefox = PREFACE.to_vec();
+        firefox.extend(frame(FRAME_PRIORITY, 0, 3, &[0, 0, 0, 0, 200]));
+        firefox.extend(frame(FRAME_PRIORITY, 0, 9, &[0, 0, 0, 7, 0]));
+        assert_eq!(decode_client_start(&firefox).unwrap().akamai_fingerprint(), "|0|3:0:0:201,9:0:7:1|");
+    }
+}
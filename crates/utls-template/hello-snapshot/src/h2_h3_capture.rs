@@ -1,15 +1,133 @@
+use serde::{Serialize, Deserialize};
+use hello_template::http_template::{H2PriorityTemplate, H2Template, H3Template};
+
+use crate::h2::{decode_client_start, H2Priority};
+use crate::h3::decode_client_streams;
+use crate::ja4;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct H2Snapshot {
     pub settings: Vec<(u16, u32)>, // En orden visto
+    pub window_update: Option<u32>,
+    pub priorities: Vec<H2Priority>, // frames PRIORITY antes del primer request
+    pub headers_priority: Option<H2Priority>,
     pub pseudo_headers_order: Vec<String>, // En orden visto
+    pub headers: Vec<(String, String)>,    // primer request completo, en orden
+    pub akamai: String,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct H3Snapshot {
     pub settings: Vec<(u64, u64)>, // HTTP/3 SETTINGS, en orden visto
     pub tp: Vec<(u64, Vec<u8>)>,   // Transport Parameters, en orden visto
     pub qpack: (u64, u64),         // QPACK specific settings
+    pub uni_streams: Vec<u64>,     // tipos de stream unidireccional, en orden
+    pub control_frames: Vec<(u64, Vec<u8>)>, // frames tras SETTINGS en el stream de control
+    pub pseudo_headers_order: Vec<String>,
+    pub headers: Vec<(String, String)>,
+    pub fingerprint: String,
+}
+
+fn priority_template(p: &H2Priority) -> H2PriorityTemplate {
+    H2PriorityTemplate { stream_id: p.stream_id, exclusive: p.exclusive, depends_on: p.depends_on, weight: p.weight }
+}
+
+fn regular_headers(headers: &[(String, String)]) -> Vec<(String, String)> {
+    headers.iter().filter(|(n, _)| !n.starts_with(':')).cloned().collect()
+}
+
+impl H2Snapshot {
+    /// Template that reproduces this capture's HTTP/2 start (values of the pseudo-headers excluded).
+    pub fn to_template(&self) -> H2Template {
+        H2Template {
+            settings: self.settings.clone(),
+            window_update: self.window_update,
+            priorities: self.priorities.iter().map(priority_template).collect(),
+            headers_priority: self.headers_priority.as_ref().map(priority_template),
+            pseudo_header_order: self.pseudo_headers_order.clone(),
+            headers: regular_headers(&self.headers),
+        }
+    }
 }
 
-pub fn capture_h2_h3(/* ... stream, etc. ... */) -> (H2Snapshot, H3Snapshot) {
-    // Se realiza un primer request minimal GET / con headers canónicos
-    // Se recogen SETTINGS y TPs exactamente en orden de aparición
-    todo!()
+impl H3Snapshot {
+    pub fn to_template(&self) -> H3Template {
+        H3Template {
+            settings: self.settings.clone(),
+            control_frames: self.control_frames.clone(),
+            uni_streams: self.uni_streams.clone(),
+            pseudo_header_order: self.pseudo_headers_order.clone(),
+            headers: regular_headers(&self.headers),
+        }
+    }
+}
+
+/// HTTP/2 layer of a capture: the client bytes of the connection, from the preface onwards.
+pub fn capture_h2(client_bytes: &[u8]) -> Result<H2Snapshot, String> {
+    let start = decode_client_start(client_bytes)?;
+    Ok(H2Snapshot {
+        pseudo_headers_order: start.pseudo_headers_order(),
+        akamai: start.akamai_fingerprint(),
+        settings: start.settings,
+        window_update: start.window_update,
+        priorities: start.priorities,
+        headers_priority: start.headers_priority,
+        headers: start.headers,
+    })
+}
+
+/// HTTP/3 layer of a capture: decrypted client streams (stream id, data) plus the ClientHello
+/// that opened the QUIC connection, for its transport parameters.
+pub fn capture_h3(streams: &[(u64, Vec<u8>)], raw_client_hello: &[u8]) -> Result<H3Snapshot, String> {
+    let start = decode_client_streams(streams)?;
+    Ok(H3Snapshot {
+        qpack: start.qpack(),
+        pseudo_headers_order: start.pseudo_headers_order(),
+        fingerprint: start.fingerprint(),
+        tp: ja4::extract_transport_params(raw_client_hello).unwrap_or_default(),
+        settings: start.settings,
+        uni_streams: start.uni_streams,
+        control_frames: start.control_frames,
+        headers: start.headers,
+    })
+}
+
+/// Builds both HTTP snapshots from a minimal `GET /` made over each protocol.
+///
+/// `h2_client` is the client side of the TCP stream after the TLS handshake, `h3_streams` the
+/// client streams of the QUIC connection and `raw_client_hello` the ClientHello that opened it.
+pub fn capture_h2_h3(h2_client: &[u8], h3_streams: &[(u64, Vec<u8>)], raw_client_hello: &[u8]) -> Result<(H2Snapshot, H3Snapshot), String> {
+    Ok((capture_h2(h2_client)?, capture_h3(h3_streams, raw_client_hello)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hello_template::http_template::{encode_h2_start, encode_h3_streams, HttpRequest};
+
+    #[test]
+    fn test_capture_template_round_trip() {
+        let req = HttpRequest::get("example.com");
+        let h2 = H2Template {
+            settings: vec![(1, 65536), (3, 1000), (4, 6_291_456), (6, 262_144)],
+            window_update: Some(15_663_105),
+            priorities: vec![H2PriorityTemplate { stream_id: 3, exclusive: false, depends_on: 0, weight: 201 }],
+            headers_priority: Some(H2PriorityTemplate { stream_id: 1, exclusive: true, depends_on: 0, weight: 256 }),
+            pseudo_header_order: vec![":method".into(), ":authority".into(), ":scheme".into(), ":path".into()],
+            headers: vec![("sec-ch-ua-mobile".into(), "?0".into()), ("user-agent".into(), "Mozilla/5.0".into())],
+        };
+        let h3 = H3Template {
+            settings: vec![(1, 65536), (6, 262_144), (7, 100), (51, 1), (0x1f * 5 + 0x21, 7)],
+            control_frames: vec![(0x21 + 0x1f * 2, vec![1, 2, 3])],
+            uni_streams: vec![0, 2, 3],
+            pseudo_header_order: vec![":method".into(), ":authority".into(), ":scheme".into(), ":path".into()],
+            headers: vec![("accept".into(), "*/*".into()), ("priority".into(), "u=0, i".into())],
+        };
+        let (s2, s3) = capture_h2_h3(&encode_h2_start(&h2, &req).unwrap(), &encode_h3_streams(&h3, &req).unwrap(), &[]).unwrap();
+        assert_eq!(s2.akamai, "1:65536;3:1000;4:6291456;6:262144|15663105|3:0:0:201|m,a,s,p");
+        assert_eq!(s2.to_template(), h2);
+        assert_eq!(s3.fingerprint, "1:65536;6:262144;7:100;51:1;GREASE|0,2,3|m,a,s,p");
+        assert_eq!(s3.to_template(), h3);
+        assert_eq!(s3.headers[1].1, "example.com");
+    }
 }
@@ -0,0 +1,407 @@
+// Decoder del arranque HTTP/3 del cliente (RFC 9114) sobre los datos de stream ya descifrados:
+// stream de control con su SETTINGS (GREASE incluido), streams QPACK de encoder/decoder
+// (RFC 9204) y el primer HEADERS de request decodificado con QPACK (tabla estática y dinámica).
+
+use serde::{Serialize, Deserialize};
+
+use hello_template::http_template::QPACK_STATIC_TABLE;
+
+use crate::quic::read_varint;
+
+pub const STREAM_CONTROL: u64 = 0x00;
+pub const STREAM_PUSH: u64 = 0x01;
+pub const STREAM_QPACK_ENCODER: u64 = 0x02;
+pub const STREAM_QPACK_DECODER: u64 = 0x03;
+
+const FRAME_HEADERS: u64 = 0x01;
+const FRAME_SETTINGS: u64 = 0x04;
+
+pub const SETTINGS_QPACK_MAX_TABLE_CAPACITY: u64 = 0x01;
+pub const SETTINGS_QPACK_BLOCKED_STREAMS: u64 = 0x07;
+
+/// Reserved HTTP/3 codepoints (settings, frame and stream types): `0x1f * N + 0x21`.
+pub fn is_grease_h3(v: u64) -> bool {
+    v >= 0x21 && (v - 0x21).is_multiple_of(0x1f)
+}
+
+/// Instruction on the client's QPACK encoder stream (RFC 9204 §4.3).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum QpackEncoderInstruction {
+    SetCapacity(u64),
+    InsertWithNameRef { static_table: bool, index: u64, value: String },
+    InsertWithLiteralName { name: String, value: String },
+    Duplicate(u64),
+}
+
+/// Instruction on the client's QPACK decoder stream (RFC 9204 §4.4).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum QpackDecoderInstruction {
+    SectionAck(u64),
+    StreamCancellation(u64),
+    InsertCountIncrement(u64),
+}
+
+/// Client side of an HTTP/3 connection start, everything in wire order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct H3ClientStart {
+    pub settings: Vec<(u64, u64)>,
+    pub control_frames: Vec<(u64, Vec<u8>)>, // frames del stream de control tras SETTINGS (GREASE, ...)
+    pub uni_streams: Vec<u64>,    // tipos de stream unidireccional, por orden de stream id
+    pub encoder_instructions: Vec<QpackEncoderInstruction>,
+    pub decoder_instructions: Vec<QpackDecoderInstruction>,
+    pub headers: Vec<(String, String)>, // primer request, vacío si no hay stream bidireccional
+}
+
+impl H3ClientStart {
+    pub fn pseudo_headers_order(&self) -> Vec<String> {
+        self.headers.iter().filter(|(n, _)| n.starts_with(':')).map(|(n, _)| n.clone()).collect()
+    }
+
+    /// (QPACK_MAX_TABLE_CAPACITY, QPACK_BLOCKED_STREAMS), 0 when not announced.
+    pub fn qpack(&self) -> (u64, u64) {
+        qpack_settings(&self.settings)
+    }
+
+    /// Stable H3 fingerprint: `settings|unidirectional stream types|pseudo-header order`.
+    ///
+    /// GREASE ids are replaced by `GREASE` so the string does not change between connections,
+    /// e.g. `1:65536;6:262144;7:100;51:1;GREASE|0,2,3|m,a,s,p`.
+    pub fn fingerprint(&self) -> String {
+        let settings = self
+            .settings
+            .iter()
+            .map(|(id, v)| if is_grease_h3(*id) { "GREASE".to_string() } else { format!("{}:{}", id, v) })
+            .collect::<Vec<_>>()
+            .join(";");
+        let streams = self
+            .uni_streams
+            .iter()
+            .map(|t| if is_grease_h3(*t) { "GREASE".to_string() } else { t.to_string() })
+            .collect::<Vec<_>>()
+            .join(",");
+        let pseudo = self
+            .pseudo_headers_order()
+            .iter()
+            .filter_map(|n| n.chars().nth(1))
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{}|{}|{}", settings, streams, pseudo)
+    }
+}
+
+pub(crate) fn qpack_settings(settings: &[(u64, u64)]) -> (u64, u64) {
+    let get = |id: u64| settings.iter().find(|(i, _)| *i == id).map(|(_, v)| *v).unwrap_or(0);
+    (get(SETTINGS_QPACK_MAX_TABLE_CAPACITY), get(SETTINGS_QPACK_BLOCKED_STREAMS))
+}
+
+fn varint(buf: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let (v, n) = read_varint(buf, *pos).ok_or("truncated varint")?;
+    *pos += n;
+    Ok(v)
+}
+
+// Frames HTTP/3 (tipo, payload) de un stream; exige que el último frame esté completo.
+fn frames(buf: &[u8], mut pos: usize) -> Result<Vec<(u64, &[u8])>, String> {
+    let mut out = Vec::new();
+    while pos < buf.len() {
+        let typ = varint(buf, &mut pos)?;
+        let len = varint(buf, &mut pos)? as usize;
+        let payload = buf.get(pos..pos + len).ok_or("HTTP/3 frame truncated")?;
+        pos += len;
+        out.push((typ, payload));
+    }
+    Ok(out)
+}
+
+/// Contents of a client control stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlStream {
+    pub settings: Vec<(u64, u64)>,
+    pub frames: Vec<(u64, Vec<u8>)>, // frames posteriores a SETTINGS, en orden
+}
+
+/// Parses a client control stream (stream type included): SETTINGS in order and the frames after it.
+pub fn decode_control_stream(buf: &[u8]) -> Result<ControlStream, String> {
+    let mut pos = 0;
+    if varint(buf, &mut pos)? != STREAM_CONTROL { return Err("not an HTTP/3 control stream".into()); }
+    let frames = frames(buf, pos)?;
+    match frames.first() {
+        Some((FRAME_SETTINGS, _)) => {}
+        _ => return Err("control stream must start with SETTINGS".into()),
+    }
+    let mut settings = Vec::new();
+    let payload = frames[0].1;
+    let mut p = 0;
+    while p < payload.len() {
+        let id = varint(payload, &mut p)?;
+        let v = varint(payload, &mut p)?;
+        settings.push((id, v));
+    }
+    Ok(ControlStream { settings, frames: frames[1..].iter().map(|(t, p)| (*t, p.to_vec())).collect() })
+}
+
+// Entero con prefijo de N bits (RFC 7541 §5.1, reutilizado por QPACK).
+fn prefixed_int(buf: &[u8], pos: &mut usize, bits: u8) -> Result<u64, String> {
+    let max = (1u64 << bits) - 1;
+    let mut v = (*buf.get(*pos).ok_or("truncated QPACK integer")? as u64) & max;
+    *pos += 1;
+    if v < max { return Ok(v); }
+    let mut shift = 0;
+    loop {
+        let b = *buf.get(*pos).ok_or("truncated QPACK integer")?;
+        *pos += 1;
+        if shift > 56 { return Err("QPACK integer overflow".into()); }
+        v += ((b & 0x7f) as u64) << shift;
+        shift += 7;
+        if b & 0x80 == 0 { return Ok(v); }
+    }
+}
+
+// Literal de string: el bit H (Huffman) va justo encima del prefijo de longitud.
+fn string_literal(buf: &[u8], pos: &mut usize, bits: u8) -> Result<String, String> {
+    let huffman = *buf.get(*pos).ok_or("truncated QPACK string")? & (1 << bits) != 0;
+    let len = prefixed_int(buf, pos, bits)? as usize;
+    let raw = buf.get(*pos..*pos + len).ok_or("truncated QPACK string")?;
+    *pos += len;
+    let bytes = if huffman {
+        hpack::huffman::HuffmanDecoder::new().decode(raw).map_err(|e| format!("Huffman decode error: {:?}", e))?
+    } else {
+        raw.to_vec()
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn static_entry(index: u64) -> Result<(String, String), String> {
+    let (n, v) = QPACK_STATIC_TABLE.get(index as usize).ok_or_else(|| format!("QPACK static index {} out of range", index))?;
+    Ok((n.to_string(), v.to_string()))
+}
+
+// Tabla dinámica por índice absoluto. No se desalojan entradas: un encoder correcto nunca
+// referencia una entrada desalojada, así que basta con conservarlas todas.
+#[derive(Default)]
+struct DynamicTable {
+    entries: Vec<(String, String)>,
+    capacity: u64,
+}
+
+impl DynamicTable {
+    fn get(&self, absolute: u64) -> Result<(String, String), String> {
+        self.entries.get(absolute as usize).cloned().ok_or_else(|| format!("QPACK dynamic index {} not inserted", absolute))
+    }
+
+    fn relative(&self, index: u64) -> Result<(String, String), String> {
+        let inserted = self.entries.len() as u64;
+        if index >= inserted { return Err("QPACK relative index out of range".into()); }
+        self.get(inserted - 1 - index)
+    }
+
+    fn apply(&mut self, ins: &QpackEncoderInstruction) -> Result<(), String> {
+        let entry = match ins {
+            QpackEncoderInstruction::SetCapacity(c) => { self.capacity = *c; return Ok(()); }
+            QpackEncoderInstruction::InsertWithNameRef { static_table: true, index, value } => (static_entry(*index)?.0, value.clone()),
+            QpackEncoderInstruction::InsertWithNameRef { static_table: false, index, value } => (self.relative(*index)?.0, value.clone()),
+            QpackEncoderInstruction::InsertWithLiteralName { name, value } => (name.clone(), value.clone()),
+            QpackEncoderInstruction::Duplicate(index) => self.relative(*index)?,
+        };
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    // Required Insert Count (RFC 9204 §4.5.1.1). Como capacidad máxima se usa la última
+    // fijada por el encoder: para el primer request no hay wrap y el resultado es el mismo.
+    fn required_insert_count(&self, encoded: u64) -> Result<u64, String> {
+        if encoded == 0 { return Ok(0); }
+        let max_entries = self.capacity / 32;
+        let full_range = 2 * max_entries;
+        if encoded > full_range { return Err("invalid QPACK Required Insert Count".into()); }
+        let max_value = self.entries.len() as u64 + max_entries;
+        let max_wrapped = (max_value / full_range) * full_range;
+        let mut ric = max_wrapped + encoded - 1;
+        if ric > max_value {
+            if ric <= full_range { return Err("invalid QPACK Required Insert Count".into()); }
+            ric -= full_range;
+        }
+        if ric == 0 { return Err("invalid QPACK Required Insert Count".into()); }
+        Ok(ric)
+    }
+}
+
+/// Parses the instructions of a QPACK encoder stream (without the stream type).
+pub fn decode_encoder_instructions(buf: &[u8]) -> Result<Vec<QpackEncoderInstruction>, String> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let b = buf[pos];
+        let ins = if b & 0x80 != 0 {
+            let index = prefixed_int(buf, &mut pos, 6)?;
+            QpackEncoderInstruction::InsertWithNameRef { static_table: b & 0x40 != 0, index, value: string_literal(buf, &mut pos, 7)? }
+        } else if b & 0x40 != 0 {
+            let name = string_literal(buf, &mut pos, 5)?;
+            QpackEncoderInstruction::InsertWithLiteralName { name, value: string_literal(buf, &mut pos, 7)? }
+        } else if b & 0x20 != 0 {
+            QpackEncoderInstruction::SetCapacity(prefixed_int(buf, &mut pos, 5)?)
+        } else {
+            QpackEncoderInstruction::Duplicate(prefixed_int(buf, &mut pos, 5)?)
+        };
+        out.push(ins);
+    }
+    Ok(out)
+}
+
+/// Parses the instructions of a QPACK decoder stream (without the stream type).
+pub fn decode_decoder_instructions(buf: &[u8]) -> Result<Vec<QpackDecoderInstruction>, String> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let b = buf[pos];
+        out.push(if b & 0x80 != 0 {
+            QpackDecoderInstruction::SectionAck(prefixed_int(buf, &mut pos, 7)?)
+        } else if b & 0x40 != 0 {
+            QpackDecoderInstruction::StreamCancellation(prefixed_int(buf, &mut pos, 6)?)
+        } else {
+            QpackDecoderInstruction::InsertCountIncrement(prefixed_int(buf, &mut pos, 6)?)
+        });
+    }
+    Ok(out)
+}
+
+fn decode_field_section(buf: &[u8], table: &DynamicTable) -> Result<Vec<(String, String)>, String> {
+    let mut pos = 0;
+    let ric = table.required_insert_count(prefixed_int(buf, &mut pos, 8)?)?;
+    if ric > table.entries.len() as u64 { return Err("QPACK field section blocked on missing inserts".into()); }
+    let negative = *buf.get(pos).ok_or("truncated QPACK field section")? & 0x80 != 0;
+    let delta = prefixed_int(buf, &mut pos, 7)?;
+    // delta y los índices vienen del peer: sin aritmética que pueda desbordar
+    let base = if negative { delta.checked_add(1).and_then(|d| ric.checked_sub(d)) } else { ric.checked_add(delta) };
+    let base = base.ok_or("invalid QPACK base")?;
+    let before_base = |idx: u64| idx.checked_add(1).and_then(|i| base.checked_sub(i)).ok_or_else(|| "QPACK index before base out of range".to_string());
+    let post_base = |idx: u64| base.checked_add(idx).ok_or_else(|| "QPACK post-base index out of range".to_string());
+
+    let mut headers = Vec::new();
+    while pos < buf.len() {
+        let b = buf[pos];
+        let field = if b & 0x80 != 0 {
+            let idx = prefixed_int(buf, &mut pos, 6)?;
+            if b & 0x40 != 0 { static_entry(idx)? } else { table.get(before_base(idx)?)? }
+        } else if b & 0x40 != 0 {
+            let idx = prefixed_int(buf, &mut pos, 4)?;
+            let name = if b & 0x10 != 0 { static_entry(idx)?.0 } else { table.get(before_base(idx)?)?.0 };
+            (name, string_literal(buf, &mut pos, 7)?)
+        } else if b & 0x20 != 0 {
+            let name = string_literal(buf, &mut pos, 3)?;
+            (name, string_literal(buf, &mut pos, 7)?)
+        } else if b & 0x10 != 0 {
+            table.get(post_base(prefixed_int(buf, &mut pos, 4)?)?)?
+        } else {
+            let name = table.get(post_base(prefixed_int(buf, &mut pos, 3)?)?)?.0;
+            (name, string_literal(buf, &mut pos, 7)?)
+        };
+        headers.push(field);
+    }
+    Ok(headers)
+}
+
+/// Decodes the client streams of an HTTP/3 connection given as (stream id, data from offset 0).
+///
+/// Unidirectional streams are read first so the first request (lowest client bidirectional
+/// stream) can reference the QPACK dynamic table.
+pub fn decode_client_streams(streams: &[(u64, Vec<u8>)]) -> Result<H3ClientStart, String> {
+    let mut sorted: Vec<&(u64, Vec<u8>)> = streams.iter().collect();
+    sorted.sort_by_key(|(id, _)| *id);
+    let mut out = H3ClientStart::default();
+    let mut table = DynamicTable::default();
+    let mut saw_control = false;
+
+    for (_, data) in sorted.iter().filter(|(id, _)| id % 4 == 2) {
+        let mut pos = 0;
+        let typ = varint(data, &mut pos)?;
+        out.uni_streams.push(typ);
+        match typ {
+            STREAM_CONTROL => {
+                let control = decode_control_stream(data)?;
+                out.settings = control.settings;
+                out.control_frames = control.frames;
+                saw_control = true;
+            }
+            STREAM_QPACK_ENCODER => {
+                out.encoder_instructions = decode_encoder_instructions(&data[pos..])?;
+                for ins in &out.encoder_instructions { table.apply(ins)?; }
+            }
+            STREAM_QPACK_DECODER => out.decoder_instructions = decode_decoder_instructions(&data[pos..])?,
+            _ => {} // push o GREASE: solo cuenta el tipo
+        }
+    }
+    if !saw_control { return Err("HTTP/3 control stream not found".into()); }
+
+    if let Some((_, data)) = sorted.iter().find(|(id, _)| id % 4 == 0) {
+        if let Some((_, block)) = frames(data, 0)?.into_iter().find(|(t, _)| *t == FRAME_HEADERS) {
+            out.headers = decode_field_section(block, &table)?;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_client_streams_with_dynamic_table() {
+        // SETTINGS estilo Chrome con un setting GREASE (0x7e) y un frame GREASE después
+        let control = vec![
+            0x00, 0x04, 0x12, 0x01, 0x80, 0x01, 0x00, 0x00, 0x06, 0x80, 0x04, 0x00, 0x00, 0x07, 0x40, 0x64, 0x33, 0x01,
+            0x40, 0x7e, 0x05, 0x21, 0x00,
+        ];
+        let mut encoder = vec![0x02, 0x3f, 0xbd, 0x01, 0xc0, 0x0b]; // capacidad 220, :authority=example.com
+        encoder.extend_from_slice(b"example.com");
+        let section = [
+            0x02, 0x00, // Required Insert Count 1, base 1
+            0xd1, 0x80, 0xd7, 0xc1, // GET, :authority (dinámica), https, /
+            0x5f, 0x50, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff, // user-agent (Huffman)
+        ];
+        let mut request = vec![0x01, section.len() as u8];
+        request.extend_from_slice(&section);
+        let streams = vec![(0, request), (14, vec![0x21]), (10, vec![0x03]), (6, encoder), (2, control)];
+
+        let start = decode_client_streams(&streams).unwrap();
+        assert_eq!(start.control_frames, vec![(0x21, vec![])]);
+        assert_eq!(start.qpack(), (65536, 100));
+        assert_eq!(start.headers[1], (":authority".to_string(), "example.com".to_string()));
+        assert_eq!(start.headers[4], ("user-agent".to_string(), "www.example.com".to_string()));
+        assert_eq!(start.fingerprint(), "1:65536;6:262144;7:100;51:1;GREASE|0,2,3,GREASE|m,a,s,p");
+
+        // sin las inserciones del encoder el request queda bloqueado
+        assert!(decode_client_streams(&[streams[0].clone(), streams[4].clone()]).is_err());
+    }
+
+    // entero QPACK con prefijo de `bits` bits; `flags` son los bits por encima del prefijo
+    fn qpack_int(flags: u8, bits: u8, v: u64) -> Vec<u8> {
+        let max = (1u64 << bits) - 1;
+        if v < max { return vec![flags | v as u8]; }
+        let (mut out, mut rest) = (vec![flags | max as u8], v - max);
+        while rest >= 0x80 {
+            out.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        out.push(rest as u8);
+        out
+    }
+
+    #[test]
+    fn test_crafted_field_section_bases_do_not_overflow() {
+        let table = DynamicTable { entries: vec![("a".into(), "b".into())], capacity: 220 };
+        let huge = (1u64 << 63) - 1;
+        // base = 1 + (2^63 - 1) = 2^63; índice post-base 2^63: base + índice desborda u64
+        for post_base in [qpack_int(0x10, 4, 1 << 63), qpack_int(0x00, 3, 1 << 63)] {
+            let section = [vec![0x02], qpack_int(0x00, 7, huge), post_base].concat();
+            assert!(decode_field_section(&section, &table).unwrap_err().contains("post-base"));
+        }
+        // delta negativo mayor que Required Insert Count
+        let section = [vec![0x02], qpack_int(0x80, 7, huge)].concat();
+        assert!(decode_field_section(&section, &table).unwrap_err().contains("base"));
+        // índice relativo a la base que desborda al sumarle 1
+        let section = [vec![0x02, 0x00], qpack_int(0x80, 6, (1u64 << 63) + 100)].concat();
+        assert!(decode_field_section(&section, &table).is_err());
+    }
+}
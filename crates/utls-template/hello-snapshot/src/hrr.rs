@@ -0,0 +1,236 @@
+// HelloRetryRequest sintético (RFC 8446 §4.1.4) y diff entre el primer y el segundo ClientHello.
+// El comportamiento del cliente ante un HRR (qué cambia, qué conserva) es una huella en sí misma.
+
+use serde::{Serialize, Deserialize};
+use hello_template::{is_grease, Encoder, Extension, HelloTemplate};
+
+use crate::server_hello::HRR_RANDOM;
+
+const EXT_EARLY_DATA: u16 = 0x002a;
+const EXT_COOKIE: u16 = 0x002c;
+const EXT_KEY_SHARE: u16 = 0x0033;
+
+/// What the HelloRetryRequest asks the client for.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct HrrOptions {
+    /// Group to request a new key share for. `None` picks the first offered group without a
+    /// share; with a cookie and no free group the HRR carries only the cookie.
+    #[serde(default)]
+    pub group: Option<u16>,
+    /// Opaque cookie the client must echo in its second ClientHello.
+    #[serde(default)]
+    pub cookie: Option<Vec<u8>>,
+    /// Cipher suite to select; defaults to the first TLS 1.3 suite offered.
+    #[serde(default)]
+    pub cipher_suite: Option<u16>,
+}
+
+fn offered_groups(ch: &HelloTemplate) -> (Vec<u16>, Vec<u16>) {
+    let mut groups = Vec::new();
+    let mut shares = Vec::new();
+    for e in &ch.extensions {
+        match e {
+            Extension::SupportedGroups { groups: g, .. } => groups.extend(g.iter().copied().filter(|x| !is_grease(*x))),
+            Extension::KeyShare { shares: s, .. } => shares.extend(s.iter().map(|(grp, _)| *grp)),
+            _ => {}
+        }
+    }
+    (groups, shares)
+}
+
+/// HelloRetryRequest record for `ch`. Echoes the session id; fails when the request would be
+/// invalid (group not offered or already shared, no TLS 1.3 suite) or would change nothing.
+pub fn build_hello_retry_request(ch: &HelloTemplate, opts: &HrrOptions) -> Result<Vec<u8>, String> {
+    let (groups, shares) = offered_groups(ch);
+    let group = match opts.group {
+        Some(g) if !groups.contains(&g) => return Err(format!("group {:#06x} not offered by the client", g)),
+        Some(g) if shares.contains(&g) => return Err(format!("client already sent a key share for {:#06x}", g)),
+        Some(g) => Some(g),
+        None => groups.iter().copied().find(|g| !shares.contains(g)),
+    };
+    if group.is_none() && opts.cookie.is_none() {
+        return Err("client already sent a key share for every group and no cookie was given".into());
+    }
+    let suite = match opts.cipher_suite {
+        Some(s) => s,
+        None => ch.cipher_suites.iter().copied().find(|c| (0x1301..=0x1305).contains(c)).ok_or("client offers no TLS 1.3 cipher suite")?,
+    };
+    let sid = ch.session_id.clone().unwrap_or_default();
+
+    let mut exts = Vec::new();
+    exts.extend_from_slice(&[0x00, 0x2b, 0x00, 0x02, 0x03, 0x04]); // supported_versions: TLS 1.3
+    if let Some(g) = group {
+        exts.extend_from_slice(&[0x00, 0x33, 0x00, 0x02]); // key_share: selected_group
+        exts.extend_from_slice(&g.to_be_bytes());
+    }
+    if let Some(cookie) = &opts.cookie {
+        if cookie.is_empty() || cookie.len() > 0xffff - 2 { return Err("cookie must be 1..65533 bytes".into()); }
+        exts.extend_from_slice(&EXT_COOKIE.to_be_bytes());
+        exts.extend_from_slice(&((cookie.len() + 2) as u16).to_be_bytes());
+        exts.extend_from_slice(&(cookie.len() as u16).to_be_bytes());
+        exts.extend_from_slice(cookie);
+    }
+
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&HRR_RANDOM);
+    body.push(sid.len() as u8);
+    body.extend_from_slice(&sid);
+    body.extend_from_slice(&suite.to_be_bytes());
+    body.push(0); // compression null
+    body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+    body.extend_from_slice(&exts);
+
+    let mut record = vec![0x16, 0x03, 0x03];
+    record.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+    record.push(0x02);
+    record.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    record.extend_from_slice(&body);
+    Ok(record)
+}
+
+/// Key share groups before and after the HelloRetryRequest.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KeyShareChange {
+    pub before: Vec<u16>,
+    pub after: Vec<u16>,
+}
+
+/// Structured difference between the first and the second ClientHello of a connection.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClientHelloDiff {
+    pub key_share: Option<KeyShareChange>,
+    pub cookie_added: bool,
+    pub cookie_echoed: bool, // la cookie del segundo CH es exactamente la del HRR
+    pub early_data_removed: bool,
+    pub extensions_added: Vec<u16>,
+    pub extensions_removed: Vec<u16>,
+    pub extensions_changed: Vec<u16>, // mismo tipo, cuerpo distinto (key_share excluido)
+    pub extension_order_changed: bool,
+    pub cipher_suites_changed: bool,
+    pub random_changed: bool,
+    pub session_id_changed: bool,
+    pub grease_preserved: bool, // mismos valores GREASE en las mismas posiciones
+}
+
+// Valores GREASE en orden de aparición: cipher suites, tipos de extensión y listas internas.
+fn grease_values(t: &HelloTemplate) -> Vec<u16> {
+    let mut out: Vec<u16> = t.cipher_suites.iter().copied().filter(|c| is_grease(*c)).collect();
+    for e in &t.extensions {
+        if is_grease(e.ext_type()) { out.push(e.ext_type()); }
+        match e {
+            Extension::SupportedGroups { groups: v, .. } | Extension::SupportedVersions { versions: v, .. } => {
+                out.extend(v.iter().copied().filter(|x| is_grease(*x)));
+            }
+            Extension::KeyShare { shares, .. } => out.extend(shares.iter().map(|(g, _)| *g).filter(|g| is_grease(*g))),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn extension_bodies(t: &HelloTemplate) -> Vec<(u16, Vec<u8>)> {
+    t.extensions
+        .iter()
+        .map(|e| {
+            let mut wire = Vec::new();
+            Encoder::encode_extension(e, &mut wire);
+            (e.ext_type(), wire[4..].to_vec())
+        })
+        .collect()
+}
+
+fn key_share_groups(t: &HelloTemplate) -> Vec<u16> {
+    offered_groups(t).1
+}
+
+/// Compares the ClientHello sent before and after a HelloRetryRequest carrying `hrr_cookie`.
+pub fn diff_client_hellos(first: &HelloTemplate, second: &HelloTemplate, hrr_cookie: Option<&[u8]>) -> ClientHelloDiff {
+    let a = extension_bodies(first);
+    let b = extension_bodies(second);
+    let types_a: Vec<u16> = a.iter().map(|(t, _)| *t).collect();
+    let types_b: Vec<u16> = b.iter().map(|(t, _)| *t).collect();
+
+    let extensions_added: Vec<u16> = types_b.iter().copied().filter(|t| !types_a.contains(t)).collect();
+    let extensions_removed: Vec<u16> = types_a.iter().copied().filter(|t| !types_b.contains(t)).collect();
+    let extensions_changed = a
+        .iter()
+        .filter(|(t, _)| *t != EXT_KEY_SHARE)
+        .filter_map(|(t, body)| b.iter().find(|(t2, _)| t2 == t).filter(|(_, body2)| body2 != body).map(|_| *t))
+        .collect();
+    // orden relativo de las extensiones presentes en ambos
+    let common_a: Vec<u16> = types_a.iter().copied().filter(|t| types_b.contains(t)).collect();
+    let common_b: Vec<u16> = types_b.iter().copied().filter(|t| types_a.contains(t)).collect();
+
+    let (before, after) = (key_share_groups(first), key_share_groups(second));
+    let second_cookie = b.iter().find(|(t, _)| *t == EXT_COOKIE).map(|(_, body)| body.get(2..).unwrap_or_default().to_vec());
+
+    ClientHelloDiff {
+        key_share: (before != after).then_some(KeyShareChange { before, after }),
+        cookie_added: extensions_added.contains(&EXT_COOKIE),
+        cookie_echoed: matches!((hrr_cookie, &second_cookie), (Some(c), Some(s)) if c == &s[..]),
+        early_data_removed: extensions_removed.contains(&EXT_EARLY_DATA),
+        extension_order_changed: common_a != common_b,
+        extensions_added,
+        extensions_removed,
+        extensions_changed,
+        cipher_suites_changed: first.cipher_suites != second.cipher_suites,
+        random_changed: first.client_random != second.client_random,
+        session_id_changed: first.session_id != second.session_id,
+        grease_preserved: grease_values(first) == grease_values(second),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(extensions: Vec<Extension>) -> HelloTemplate {
+        HelloTemplate {
+            client_random: Some([1; 32]),
+            session_id: Some(vec![9; 32]),
+            cipher_suites: vec![0x3a3a, 0x1301, 0x1302],
+            cipher_grease_slots: vec![0],
+            extensions,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hrr_with_cookie_and_diff() {
+        let groups = Extension::SupportedGroups { groups: vec![0x4a4a, 0x001d, 0x0017], grease_slots: vec![0] };
+        let first = hello(vec![
+            Extension::Grease { typ: 0x0a0a, bytes: vec![] },
+            groups.clone(),
+            Extension::KeyShare { shares: vec![(0x4a4a, vec![0]), (0x001d, vec![1; 32])], grease_slots: vec![0] },
+            Extension::Unknown { typ: EXT_EARLY_DATA, bytes: vec![] },
+            Extension::Padding { len: 100 },
+        ]);
+        let opts = HrrOptions { group: Some(0x0017), cookie: Some(b"state".to_vec()), cipher_suite: None };
+        let hrr = build_hello_retry_request(&first, &opts).unwrap();
+        let parsed = crate::server_hello::parse_server_hello(&hrr).unwrap();
+        assert!(parsed.is_hello_retry_request());
+        assert_eq!((parsed.cipher_suite, &parsed.session_id), (0x1301, &vec![9; 32]));
+        assert_eq!(parsed.extensions[1..], [(EXT_KEY_SHARE, vec![0x00, 0x17]), (EXT_COOKIE, b"\x00\x05state".to_vec())]);
+
+        // group ya compartido o no ofrecido: HRR inválido
+        assert!(build_hello_retry_request(&first, &HrrOptions { group: Some(0x001d), ..Default::default() }).is_err());
+        assert!(build_hello_retry_request(&first, &HrrOptions { group: Some(0x0018), ..Default::default() }).is_err());
+
+        let second = hello(vec![
+            Extension::Grease { typ: 0x0a0a, bytes: vec![] },
+            groups,
+            Extension::KeyShare { shares: vec![(0x4a4a, vec![0]), (0x0017, vec![4; 65])], grease_slots: vec![0] },
+            Extension::Unknown { typ: EXT_COOKIE, bytes: b"\x00\x05state".to_vec() },
+            Extension::Padding { len: 60 },
+        ]);
+        let diff = diff_client_hellos(&first, &second, Some(b"state"));
+        assert_eq!(diff.key_share, Some(KeyShareChange { before: vec![0x4a4a, 0x001d], after: vec![0x4a4a, 0x0017] }));
+        assert!(diff.cookie_added && diff.cookie_echoed && diff.early_data_removed && diff.grease_preserved);
+        assert_eq!(diff.extensions_changed, vec![0x0015]);
+        assert!(!diff.extension_order_changed && !diff.random_changed && !diff.session_id_changed);
+
+        let mut regreased = second.clone();
+        regreased.cipher_suites[0] = 0x5a5a;
+        assert!(!diff_client_hellos(&first, &regreased, Some(b"state")).grease_preserved);
+    }
+}
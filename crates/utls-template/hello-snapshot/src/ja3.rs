@@ -1,10 +1,6 @@
 use md5::{Md5, Digest};
-
-fn is_grease(u: u16) -> bool {
-    // Standard GREASE values
-    matches!(u, 0x0a0a | 0x1a1a | 0x2a2a | 0x3a3a | 0x4a4a | 0x5a5a | 0x6a6a | 0x7a7a |
-                0x8a8a | 0x9a9a | 0xaaaa | 0xbaba | 0xcaca | 0xdada | 0xeaea | 0xfafa)
-}
+use hello_template::decode::decode_client_hello;
+use hello_template::{is_grease, Extension};
 
 pub struct ParsedClientHello {
     pub version: u16,
@@ -15,77 +11,26 @@ pub struct ParsedClientHello {
 }
 
 pub fn parse_client_hello(raw: &[u8]) -> Result<ParsedClientHello, String> {
-    // Expect record header
-    if raw.len() < 5 { return Err("too short for record".into()); }
-    if raw[0] != 22 { return Err("not a handshake record".into()); }
-    let _record_len = ((raw[3] as usize) << 8) | (raw[4] as usize);
-    // Handshake
-    if raw.len() < 9 { return Err("too short for handshake".into()); }
-    if raw[5] != 0x01 { return Err("not a ClientHello handshake".into()); }
-    // handshake length 3 bytes at 6..9
-    let body_offset = 9;
-    if raw.len() < body_offset + 2 { return Err("no legacy_version".into()); }
-    let version = ((raw[body_offset] as u16) << 8) | raw[body_offset+1] as u16;
-    let mut idx = body_offset + 2 + 32; // skip random
-    if raw.len() <= idx { return Err("unexpected end after random".into()); }
-    // session id
-    let sid_len = raw[idx] as usize; idx += 1 + sid_len;
-    if raw.len() < idx+2 { return Err("no cipher suites length".into()); }
-    let cs_len = ((raw[idx] as usize) << 8) | raw[idx+1] as usize; idx += 2;
-    if raw.len() < idx + cs_len { return Err("cipher suites truncated".into()); }
-    let mut cipher_suites = Vec::new();
-    let mut cs_idx = idx;
-    while cs_idx < idx + cs_len {
-        let cs = ((raw[cs_idx] as u16) << 8) | raw[cs_idx+1] as u16;
-        if !is_grease(cs) { cipher_suites.push(cs); }
-        cs_idx += 2;
-    }
-    idx += cs_len;
-    // compression methods
-    if raw.len() <= idx { return Err("no compression methods".into()); }
-    let cm_len = raw[idx] as usize; idx += 1 + cm_len;
-    if raw.len() < idx { return Err("compression methods truncated".into()); }
-    // extensions
-    if raw.len() < idx + 2 { return Ok(ParsedClientHello { version, cipher_suites, extensions: vec![], supported_groups: vec![], ec_point_formats: vec![] }); }
-    let exts_len = ((raw[idx] as usize) << 8) | raw[idx+1] as usize; idx += 2;
-    if raw.len() < idx + exts_len { return Err("extensions truncated".into()); }
-    let mut exts_idx = idx;
-    let end_exts = idx + exts_len;
+    let tpl = decode_client_hello(raw)?;
+    let version = tpl.legacy_version.unwrap_or(0x0303);
+    let cipher_suites = tpl.cipher_suites.iter().copied().filter(|c| !is_grease(*c)).collect();
     let mut extensions = Vec::new();
     let mut supported_groups = Vec::new();
     let mut ec_point_formats = Vec::new();
-    while exts_idx + 4 <= end_exts {
-        let ext_type = ((raw[exts_idx] as u16) << 8) | raw[exts_idx+1] as u16;
-        let ext_len = ((raw[exts_idx+2] as usize) << 8) | raw[exts_idx+3] as usize;
-        let data_start = exts_idx + 4;
-        let data_end = data_start + ext_len;
-        if data_end > end_exts { break; }
-        if !is_grease(ext_type) { extensions.push(ext_type); }
-        match ext_type {
-            0x000a => { // supported_groups (named groups)
-                if ext_len >= 2 {
-                    let list_len = ((raw[data_start] as usize) << 8) | raw[data_start+1] as usize;
-                    let mut gi = data_start + 2;
-                    while gi + 1 < data_start + 2 + list_len && gi + 1 < data_end {
-                        let g = ((raw[gi] as u16) << 8) | raw[gi+1] as u16;
-                        if !is_grease(g) { supported_groups.push(g); }
-                        gi += 2;
-                    }
-                }
+    for ext in &tpl.extensions {
+        let typ = ext.ext_type();
+        if !is_grease(typ) { extensions.push(typ); }
+        match ext {
+            Extension::SupportedGroups { groups, .. } => {
+                supported_groups.extend(groups.iter().copied().filter(|g| !is_grease(*g)));
             }
-            0x000b => { // ec point formats
-                if ext_len >= 1 {
-                    let list_len = raw[data_start] as usize;
-                    let mut pi = data_start + 1;
-                    for _ in 0..list_len {
-                        if pi >= data_end { break; }
-                        ec_point_formats.push(raw[pi]); pi += 1;
-                    }
-                }
+            // ec point formats (sin variante tipada): u8 length + list
+            Extension::Unknown { typ: 0x000b, bytes } if !bytes.is_empty() => {
+                let n = (bytes[0] as usize).min(bytes.len() - 1);
+                ec_point_formats.extend_from_slice(&bytes[1..1 + n]);
             }
             _ => {}
         }
-        exts_idx = data_end;
     }
     Ok(ParsedClientHello { version, cipher_suites, extensions, supported_groups, ec_point_formats })
 }
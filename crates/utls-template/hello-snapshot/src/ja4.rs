@@ -1,4 +1,9 @@
 use md5::{Md5, Digest};
+use serde::{Serialize, Deserialize};
+use sha2::Sha256;
+use hello_template::decode::decode_client_hello;
+use hello_template::{is_grease, Extension};
+use hello_template::transport_params::is_grease_param;
 
 #[derive(Debug)]
 pub struct H2Settings {
@@ -13,79 +18,17 @@ pub struct H3Settings {
     pub qpack: Option<(u64,u64)>,
 }
 
-// Heuristic: busca el PREFACE de HTTP/2 y extrae el primer frame SETTINGS que encuentre
+// SETTINGS y orden de pseudo-headers del primer request, vía el decoder completo de crate::h2
 pub fn extract_h2_settings_from_bytes(raw: &[u8]) -> Option<H2Settings> {
-    let preface = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
-    if let Some(pos) = raw.windows(preface.len()).position(|w| w == preface) {
-        let mut idx = pos + preface.len();
-        // parse frames
-        while idx + 9 <= raw.len() {
-            let len = ((raw[idx] as usize) << 16) | ((raw[idx+1] as usize) << 8) | (raw[idx+2] as usize);
-            let typ = raw[idx+3];
-            // let flags = raw[idx+4];
-            // stream id 4 bytes
-            // If SETTINGS (type 0x4)
-            if typ == 0x4 {
-                let payload_start = idx + 9;
-                if payload_start + len <= raw.len() {
-                    let mut settings = Vec::new();
-                    let mut p = payload_start;
-                    while p + 6 <= payload_start + len {
-                        let id = ((raw[p] as u16) << 8) | raw[p+1] as u16;
-                        let val = ((raw[p+2] as u32) << 24) | ((raw[p+3] as u32) << 16) | ((raw[p+4] as u32) << 8) | (raw[p+5] as u32);
-                        settings.push((id, val));
-                        p += 6;
-                    }
-                    return Some(H2Settings { settings, pseudo_headers_order: Vec::new() });
-                }
-            }
-            idx += 9 + len;
-        }
-    }
-    None
-}
-
-// Heuristic for H3: search for byte 0x04 (SETTINGS) in a stream and read following length as varint (not robust)
-fn read_varint_at(raw: &[u8], pos: usize) -> Option<(u64, usize)> {
-    if pos >= raw.len() { return None; }
-    let b = raw[pos];
-    let prefix = b >> 6;
-    let len = match prefix { 0 => 1, 1 => 2, 2 => 4, 3 => 8, _ => 1 };
-    if pos + len > raw.len() { return None; }
-    let mut value: u64 = (b & 0x3f) as u64;
-    for i in 1..len {
-        value = (value << 8) | raw[pos+i] as u64;
-    }
-    Some((value, len))
+    let start = crate::h2::decode_client_start(raw).ok()?;
+    Some(H2Settings { pseudo_headers_order: start.pseudo_headers_order(), settings: start.settings })
 }
 
+// SETTINGS de un stream de control HTTP/3 (con su tipo de stream al principio), vía crate::h3
 pub fn extract_h3_settings_from_bytes(raw: &[u8]) -> Option<H3Settings> {
-    let mut i = 0;
-    while i < raw.len() {
-        if raw[i] == 0x04 {
-            // read varint length at i+1
-            if let Some((len, lbytes)) = read_varint_at(raw, i+1) {
-                let start = i+1+lbytes;
-                if start + (len as usize) <= raw.len() {
-                    // parse simple key/value pairs as varint-id,varint-value sequence (best-effort)
-                    let mut p = start;
-                    let mut settings = Vec::new();
-                    while p < start + (len as usize) {
-                        if let Some((id, idlen)) = read_varint_at(raw, p) {
-                            p += idlen;
-                            if let Some((v, vlen)) = read_varint_at(raw, p) {
-                                p += vlen;
-                                settings.push((id, v));
-                            } else { break; }
-                        } else { break; }
-                    }
-                    return Some(H3Settings { settings, transport_params: Vec::new(), qpack: None });
-                }
-            }
-        }
-        i += 1;
-    }
-    None
+    let settings = crate::h3::decode_control_stream(raw).ok()?.settings;
+    let qpack = Some(crate::h3::qpack_settings(&settings));
+    Some(H3Settings { settings, transport_params: Vec::new(), qpack })
 }
 
 pub fn ja4_h2(h2: &H2Settings) -> String {
@@ -97,9 +40,266 @@ pub fn ja4_h2(h2: &H2Settings) -> String {
     format!("{:x}", hasher.result())
 }
 
+/// Transport parameters (id, raw value) of the `quic_transport_parameters` extension of a
+/// ClientHello, in wire order.
+pub fn extract_transport_params(raw_client_hello: &[u8]) -> Option<Vec<(u64, Vec<u8>)>> {
+    let tpl = decode_client_hello(raw_client_hello).ok()?;
+    tpl.extensions.iter().find_map(|e| match e {
+        Extension::QuicTransportParameters { params } => Some(params.iter().map(|p| (p.id(), p.value())).collect()),
+        _ => None,
+    })
+}
+
+/// Canonical transport parameter string: `id:value` in wire order, with GREASE ids collapsed to
+/// `GREASE` and per-connection values (initial_source_connection_id) reduced to the id.
+pub fn transport_params_string(params: &[(u64, Vec<u8>)]) -> String {
+    params.iter().map(|(id, value)| {
+        if is_grease_param(*id) {
+            "GREASE".to_string()
+        } else if *id == 0x0f {
+            format!("{:x}", id)
+        } else {
+            let hex: String = value.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{:x}:{}", id, hex)
+        }
+    }).collect::<Vec<_>>().join("-")
+}
+
 pub fn ja4_h3(h3: &H3Settings) -> String {
-    let s = h3.settings.iter().map(|(id,val)| format!("{}:{}", id, val)).collect::<Vec<_>>().join("-");
+    let mut s = h3.settings.iter().map(|(id,val)| format!("{}:{}", id, val)).collect::<Vec<_>>().join("-");
+    if !h3.transport_params.is_empty() {
+        s.push('|');
+        s.push_str(&transport_params_string(&h3.transport_params));
+    }
     let mut hasher = Md5::new();
     hasher.input(s.as_bytes());
     format!("{:x}", hasher.result())
 }
+
+// ---------------------------------------------------------------------------
+// JA4 (TLS ClientHello) — FoxIO spec: https://github.com/FoxIO-LLC/ja4
+// ---------------------------------------------------------------------------
+
+/// Transport over which the ClientHello was seen (first char of JA4_a).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ja4Transport { Tcp, Quic, Dtls }
+
+impl Ja4Transport {
+    pub(crate) fn prefix(self) -> char {
+        match self { Ja4Transport::Tcp => 't', Ja4Transport::Quic => 'q', Ja4Transport::Dtls => 'd' }
+    }
+}
+
+/// Campos de un ClientHello que intervienen en JA4, sin GREASE y en orden original.
+struct Ja4Parts {
+    a: String,
+    ciphers: Vec<u16>,
+    extensions: Vec<u16>,
+    sig_algs: Vec<u16>,
+}
+
+pub(crate) fn ja4_version(v: u16) -> &'static str {
+    match v {
+        0x0304 => "13", 0x0303 => "12", 0x0302 => "11", 0x0301 => "10",
+        0x0300 => "s3", 0x0002 => "s2",
+        0xfeff => "d1", 0xfefd => "d2", 0xfefc => "d3",
+        _ => "00",
+    }
+}
+
+pub(crate) fn ja4_alpn(first: Option<&[u8]>) -> String {
+    match first {
+        Some(p) if !p.is_empty() => {
+            let (f, l) = (p[0], p[p.len() - 1]);
+            if f.is_ascii_alphanumeric() && l.is_ascii_alphanumeric() {
+                format!("{}{}", f as char, l as char)
+            } else {
+                // non-alphanumeric: first hex digit of the first byte + last hex digit of the last byte
+                let hf = format!("{:02x}", f);
+                let hl = format!("{:02x}", l);
+                format!("{}{}", &hf[..1], &hl[1..])
+            }
+        }
+        _ => "00".to_string(),
+    }
+}
+
+fn ja4_parts(raw: &[u8], transport: Ja4Transport) -> Result<Ja4Parts, String> {
+    let tpl = decode_client_hello(raw)?;
+    let ciphers: Vec<u16> = tpl.cipher_suites.iter().copied().filter(|c| !is_grease(*c)).collect();
+    let extensions: Vec<u16> = tpl.extensions.iter().map(|e| e.ext_type()).filter(|t| !is_grease(*t)).collect();
+
+    let mut version = tpl.legacy_version.unwrap_or(0x0303);
+    let mut sni = false;
+    let mut alpn: Option<Vec<u8>> = None;
+    let mut sig_algs = Vec::new();
+    for ext in &tpl.extensions {
+        match ext {
+            Extension::ServerName { .. } => sni = true,
+            Extension::Unknown { typ: 0x0000, .. } => sni = true,
+            Extension::SupportedVersions { versions, .. } => {
+                if let Some(v) = versions.iter().copied().filter(|v| !is_grease(*v)).max() { version = v; }
+            }
+            Extension::Alpn { protocols } => alpn = protocols.first().map(|p| p.as_bytes().to_vec()),
+            // ALPN con nombres no UTF-8 queda como Unknown: primer protocolo tras u16 len + u8 len
+            Extension::Unknown { typ: 0x0010, bytes } if bytes.len() > 3 => {
+                let n = bytes[2] as usize;
+                alpn = bytes.get(3..3 + n).map(|p| p.to_vec());
+            }
+            Extension::SignatureAlgorithms { algs, .. } => {
+                sig_algs = algs.iter().copied().filter(|a| !is_grease(*a)).collect();
+            }
+            _ => {}
+        }
+    }
+
+    let a = format!(
+        "{}{}{}{:02}{:02}{}",
+        transport.prefix(),
+        ja4_version(version),
+        if sni { 'd' } else { 'i' },
+        ciphers.len().min(99),
+        extensions.len().min(99),
+        ja4_alpn(alpn.as_deref()),
+    );
+    Ok(Ja4Parts { a, ciphers, extensions, sig_algs })
+}
+
+fn hex_list(v: &[u16]) -> String {
+    v.iter().map(|x| format!("{:04x}", x)).collect::<Vec<_>>().join(",")
+}
+
+pub(crate) fn sha256_12(s: &str) -> String {
+    if s.is_empty() { return "000000000000".to_string(); }
+    let digest = <Sha256 as sha2::Digest>::digest(s.as_bytes());
+    digest.iter().take(6).map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns `(a, b_raw, c_raw)`; `sorted` selects JA4/JA4_r vs JA4_o/JA4_ro ordering.
+fn ja4_strings(raw: &[u8], transport: Ja4Transport, sorted: bool) -> Result<(String, String, String), String> {
+    let p = ja4_parts(raw, transport)?;
+    let mut ciphers = p.ciphers.clone();
+    let mut exts = p.extensions.clone();
+    if sorted {
+        ciphers.sort_unstable();
+        // SNI y ALPN no cuentan en la parte c ordenada
+        exts.retain(|e| *e != 0x0000 && *e != 0x0010);
+        exts.sort_unstable();
+    }
+    let mut c = hex_list(&exts);
+    if !p.sig_algs.is_empty() {
+        c.push('_');
+        c.push_str(&hex_list(&p.sig_algs));
+    }
+    Ok((p.a, hex_list(&ciphers), c))
+}
+
+/// JA4 fingerprint: `t13d1516h2_<sha256(sorted ciphers)>_<sha256(sorted exts_sigalgs)>`.
+/// `raw` may be a TLS record (TCP) or the bare handshake carried in QUIC CRYPTO frames.
+pub fn ja4(raw: &[u8], transport: Ja4Transport) -> Result<String, String> {
+    let (a, b, c) = ja4_strings(raw, transport, true)?;
+    Ok(format!("{}_{}_{}", a, sha256_12(&b), sha256_12(&c)))
+}
+
+/// JA4_r: como JA4 pero con las listas ordenadas sin hashear.
+pub fn ja4_r(raw: &[u8], transport: Ja4Transport) -> Result<String, String> {
+    let (a, b, c) = ja4_strings(raw, transport, true)?;
+    Ok(format!("{}_{}_{}", a, b, c))
+}
+
+/// JA4_o: listas en el orden original del ClientHello (incluye SNI y ALPN), hasheadas.
+pub fn ja4_o(raw: &[u8], transport: Ja4Transport) -> Result<String, String> {
+    let (a, b, c) = ja4_strings(raw, transport, false)?;
+    Ok(format!("{}_{}_{}", a, sha256_12(&b), sha256_12(&c)))
+}
+
+/// JA4_ro: orden original sin hashear.
+pub fn ja4_ro(raw: &[u8], transport: Ja4Transport) -> Result<String, String> {
+    let (a, b, c) = ja4_strings(raw, transport, false)?;
+    Ok(format!("{}_{}_{}", a, b, c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hello_template::grease::GreaseMode;
+    use hello_template::{Encoder, HelloTemplate};
+
+    // ClientHello de Chrome usado como ejemplo en la documentación de FoxIO
+    fn foxio_example() -> HelloTemplate {
+        let unknown = |typ: u16, bytes: Vec<u8>| Extension::Unknown { typ, bytes };
+        HelloTemplate {
+            record_version: Some(0x0301),
+            legacy_version: Some(0x0303),
+            client_random: Some([0u8; 32]),
+            cipher_suites: vec![0x0a0a, 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030,
+                                0xcca9, 0xcca8, 0xc013, 0xc014, 0x009c, 0x009d, 0x002f, 0x0035],
+            cipher_grease_slots: vec![0],
+            extensions: vec![
+                Extension::Grease { typ: 0x1a1a, bytes: vec![] },
+                Extension::ServerName { host: "example.com".into() },
+                unknown(0x0017, vec![]),
+                unknown(0xff01, vec![0]),
+                Extension::SupportedGroups { groups: vec![0x2a2a, 0x001d, 0x0017, 0x0018], grease_slots: vec![0] },
+                unknown(0x000b, vec![1, 0]),
+                unknown(0x0023, vec![]),
+                Extension::Alpn { protocols: vec!["h2".into(), "http/1.1".into()] },
+                unknown(0x0005, vec![1, 0, 0, 0, 0]),
+                Extension::SignatureAlgorithms { algs: vec![0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601], grease_slots: vec![] },
+                unknown(0x0012, vec![]),
+                Extension::KeyShare { shares: vec![(0x2a2a, vec![0]), (0x001d, vec![1; 32])], grease_slots: vec![0] },
+                Extension::PskKeyExchangeModes { modes: vec![1] },
+                Extension::SupportedVersions { versions: vec![0x3a3a, 0x0304, 0x0303], grease_slots: vec![0] },
+                unknown(0x001b, vec![2, 0, 2]),
+                unknown(0x4469, vec![0, 3, 2, b'h', b'2']),
+                Extension::Grease { typ: 0x4a4a, bytes: vec![0] },
+                Extension::Padding { len: 10 },
+            ],
+            grease_mode: GreaseMode::Fixed(0x0a0a, 0x1a1a),
+            psk_key_exchange_modes: Some(vec![1]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ja4_foxio_example() {
+        let raw = Encoder::encode_client_hello(&foxio_example(), false).unwrap().raw_bytes;
+        assert_eq!(ja4(&raw, Ja4Transport::Tcp).unwrap(), "t13d1516h2_8daaf6152771_e5627efa2ab1");
+        assert_eq!(
+            ja4_r(&raw, Ja4Transport::Tcp).unwrap(),
+            "t13d1516h2_002f,0035,009c,009d,1301,1302,1303,c013,c014,c02b,c02c,c02f,c030,cca8,cca9_\
+             0005,000a,000b,000d,0012,0015,0017,001b,0023,002b,002d,0033,4469,ff01_\
+             0403,0804,0401,0503,0805,0501,0806,0601"
+        );
+        // QUIC carries the bare handshake; only the protocol char changes
+        assert!(ja4(&raw[5..], Ja4Transport::Quic).unwrap().starts_with("q13d1516h2_8daaf6152771_"));
+        assert!(ja4_o(&raw, Ja4Transport::Tcp).unwrap().starts_with("t13d1516h2_"));
+    }
+
+    #[test]
+    fn test_ja4_alpn_chars() {
+        assert_eq!(ja4_alpn(None), "00");
+        assert_eq!(ja4_alpn(Some(b"http/1.1")), "h1");
+        assert_eq!(ja4_alpn(Some(&[0xab, 0x01])), "a1");
+    }
+
+    #[test]
+    fn test_transport_params_in_h3_fingerprint() {
+        use hello_template::transport_params::TransportParam;
+        let mut tpl = foxio_example();
+        tpl.extensions.push(Extension::QuicTransportParameters { params: vec![
+            TransportParam::MaxIdleTimeout(30_000),
+            TransportParam::Grease { id: 27 + 31 * 7, value: vec![1, 2] },
+            TransportParam::InitialSourceConnectionId(vec![0xde, 0xad]),
+            TransportParam::InitialMaxData(15_728_640),
+        ] });
+        let raw = Encoder::encode_handshake(&tpl);
+        let tp = extract_transport_params(&raw).unwrap();
+        assert_eq!(transport_params_string(&tp), "1:80007530-GREASE-f-4:80f00000");
+
+        let settings = vec![(1, 65536), (6, 262144)];
+        let plain = H3Settings { settings: settings.clone(), transport_params: Vec::new(), qpack: None };
+        let with_tp = H3Settings { settings, transport_params: tp, qpack: None };
+        assert_ne!(ja4_h3(&plain), ja4_h3(&with_tp));
+    }
+}
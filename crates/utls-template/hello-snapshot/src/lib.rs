@@ -1,11 +1,19 @@
 pub use hello_template::grease;
 pub use hello_template::tls_record;
 
+pub mod filter;
+pub mod golden;
+pub mod h2;
+pub mod h2_h3_capture;
+pub mod h3;
+pub mod hrr;
 pub mod pcap_importer;
 pub mod quic;
 pub mod quic_decrypt;
 pub mod ja3;
 pub mod ja4;
+pub mod server_hello;
+pub mod tls_capture;
 
 use md5::{Md5, Digest};
 use crate::pcap_importer::TlsSnapshot;
@@ -22,14 +30,35 @@ pub fn snapshot_to_ja3(snap: &mut TlsSnapshot) {
     }
 }
 
+// JA4 sobre el ClientHello del snapshot: un record TLS implica TCP; el handshake suelto
+// (sin cabecera de record) es lo que transportan los frames CRYPTO de QUIC.
+pub fn snapshot_to_ja4(snap: &mut TlsSnapshot) {
+    let transport = if snap.raw_client_hello.first() == Some(&0x16) { ja4::Ja4Transport::Tcp } else { ja4::Ja4Transport::Quic };
+    snap.ja4 = ja4::ja4(&snap.raw_client_hello, transport).ok();
+    snap.quic_tp = ja4::extract_transport_params(&snap.raw_client_hello).map(|tp| ja4::transport_params_string(&tp));
+}
+
 pub fn ja4_h2_from_raw(raw: &[u8]) -> Option<String> {
-    if let Some(h2) = ja4::extract_h2_settings_from_bytes(raw) {
-        Some(ja4::ja4_h2(&h2))
-    } else { None }
+    ja4::extract_h2_settings_from_bytes(raw).map(|h2| ja4::ja4_h2(&h2))
+}
+
+/// Akamai-style HTTP/2 fingerprint of a client byte stream starting at (or containing) the preface.
+pub fn akamai_h2_from_raw(raw: &[u8]) -> Option<String> {
+    h2::decode_client_start(raw).ok().map(|s| s.akamai_fingerprint())
+}
+
+/// Stable HTTP/3 fingerprint from the client streams (stream id, data) of a decrypted connection.
+pub fn h3_fingerprint_from_streams(streams: &[(u64, Vec<u8>)]) -> Option<String> {
+    h3::decode_client_streams(streams).ok().map(|s| s.fingerprint())
 }
 
 pub fn ja4_h3_from_raw(raw: &[u8]) -> Option<String> {
-    if let Some(h3) = ja4::extract_h3_settings_from_bytes(raw) {
-        Some(ja4::ja4_h3(&h3))
-    } else { None }
+    ja4::extract_h3_settings_from_bytes(raw).map(|h3| ja4::ja4_h3(&h3))
+}
+
+/// H3 fingerprint including the transport parameters of the connection's ClientHello.
+pub fn ja4_h3_with_client_hello(raw: &[u8], raw_client_hello: &[u8]) -> Option<String> {
+    let mut h3 = ja4::extract_h3_settings_from_bytes(raw)?;
+    h3.transport_params = ja4::extract_transport_params(raw_client_hello).unwrap_or_default();
+    Some(ja4::ja4_h3(&h3))
 }
@@ -1,122 +1,985 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Read;
+use std::net::IpAddr;
 use serde::{Serialize, Deserialize};
 use std::fs::File;
-use pnet_packet::Packet;
-use pnet_packet::ethernet::{EthernetPacket, EtherTypes};
-use pnet_packet::ipv4::Ipv4Packet;
-use pnet_packet::tcp::TcpPacket;
+use pnet_packet::tcp::{TcpPacket, TcpFlags};
 use pnet_packet::udp::UdpPacket;
 use pnet_packet::ip::IpNextHeaderProtocols;
-use pcap_parser::LegacyPcapReader;
-use pcap_parser::traits::PcapReaderIterator;
+use pcap_parser::{create_reader, Block, Linktype, OptionCode, PcapBlockOwned, PcapError};
+use pcap_parser::traits::{PcapNGPacketBlock, PcapReaderIterator};
 
-use crate::quic;
-use crate::quic_decrypt;
+use crate::filter::FlowFilter;
+use crate::quic::{self, CryptoStream, Frame, PacketType};
+use crate::quic_decrypt::{decrypt_initial_packet, Direction};
+use crate::server_hello;
+use crate::ja4::Ja4Transport;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Transport { Tcp, Udp }
+
+/// 5-tuple orientado cliente → servidor.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src_ip: IpAddr,
+    pub dst_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub transport: Transport,
+}
+
+impl FlowKey {
+    fn reversed(&self) -> FlowKey {
+        FlowKey { src_ip: self.dst_ip, dst_ip: self.src_ip, src_port: self.dst_port, dst_port: self.src_port, transport: self.transport }
+    }
+
+    /// Clave independiente de la dirección (el extremo "menor" primero).
+    fn conversation(&self) -> FlowKey {
+        if (self.src_ip, self.src_port) <= (self.dst_ip, self.dst_port) { *self } else { self.reversed() }
+    }
+}
+
+/// Flow metadata attached to every snapshot; timestamps are microseconds since the Unix epoch.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FlowInfo {
+    pub key: FlowKey,
+    pub first_seen_us: u64,
+    pub client_hello_us: u64,
+    pub server_hello_us: Option<u64>,
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct TlsSnapshot {
     pub raw_client_hello: Vec<u8>,
     pub ja3: Option<String>,
+    #[serde(default)]
+    pub ja4: Option<String>,
+    #[serde(default)]
+    pub quic_tp: Option<String>, // parámetros de transporte QUIC en orden (ja4::transport_params_string)
+    #[serde(default)]
+    pub raw_server_hello: Option<Vec<u8>>, // respuesta del servidor si aparece en la captura
+    #[serde(default)]
+    pub ja3s: Option<String>,
+    #[serde(default)]
+    pub ja4s: Option<String>,
+    #[serde(default)]
+    pub flow: Option<FlowInfo>,
+    #[serde(default)]
+    pub comments: Vec<String>, // comentarios pcapng (opt_comment) de los paquetes del flujo
+}
+
+impl TlsSnapshot {
+    fn new(raw_client_hello: Vec<u8>) -> Self {
+        TlsSnapshot { raw_client_hello, ja3: None, ja4: None, quic_tp: None, raw_server_hello: None, ja3s: None, ja4s: None, flow: None, comments: Vec::new() }
+    }
+
+    /// Attaches the ServerHello and its JA3S/JA4S fingerprints.
+    pub fn attach_server_hello(&mut self, raw: Vec<u8>, transport: Ja4Transport) {
+        self.ja3s = server_hello::ja3s_from_raw(&raw).ok();
+        self.ja4s = server_hello::ja4s(&raw, transport).ok();
+        self.raw_server_hello = Some(raw);
+    }
+}
+
+/// True when `stream` starts like a TLS handshake record (ClientHello or ServerHello): the
+/// content-based test that replaces the old port-443 check.
+fn looks_like_tls_handshake(stream: &[u8]) -> bool {
+    match stream {
+        [0x16, 0x03, minor, _, _, hs, ..] => *minor <= 0x04 && matches!(hs, 0x01 | 0x02),
+        [0x16, 0x03, minor, ..] => *minor <= 0x04,
+        [0x16] => true,
+        _ => false,
+    }
+}
+
+/// Returns the records holding a complete handshake message of type `hs_type` when the stream
+/// starts with it (the message may span several records).
+fn handshake_at_stream_start(stream: &[u8], hs_type: u8) -> Option<Vec<u8>> {
+    if stream.len() < 9 || stream[0] != 0x16 || stream[5] != hs_type { return None; }
+    let need = 4 + (((stream[6] as usize) << 16) | ((stream[7] as usize) << 8) | stream[8] as usize);
+    let (mut pos, mut got) = (0usize, 0usize);
+    while got < need {
+        if pos + 5 > stream.len() || stream[pos] != 0x16 { return None; }
+        let len = ((stream[pos+3] as usize) << 8) | stream[pos+4] as usize;
+        if pos + 5 + len > stream.len() { return None; }
+        got += len;
+        pos += 5 + len;
+    }
+    Some(stream[..pos].to_vec())
+}
+
+/// Segments a SYN-less stream holds back when its first data does not open a TLS handshake, in
+/// case the real first segment arrives late.
+const REORDER_WINDOW: usize = 8;
+
+/// One direction of a TCP connection, reassembled in sequence-number order.
+#[derive(Default)]
+struct TcpStreamBuf {
+    isn: Option<u32>,                 // primer byte de datos (SYN + 1, o el menor visto si no hay SYN)
+    isn_from_syn: bool,               // sin SYN el ISN es provisional hasta que se ensambla algo
+    held: usize,                      // segmentos retenidos esperando el inicio real (sin SYN)
+    data: Vec<u8>,                    // bytes contiguos ya ensamblados
+    pending: BTreeMap<u64, Vec<u8>>,  // segmentos fuera de orden por offset relativo
+}
+
+impl TcpStreamBuf {
+    fn on_segment(&mut self, seq: u32, syn: bool, payload: &[u8]) {
+        if syn {
+            self.isn = Some(seq.wrapping_add(1));
+            self.isn_from_syn = true;
+        }
+        if payload.is_empty() { return; }
+        let data_seq = if syn { seq.wrapping_add(1) } else { seq };
+        let isn = *self.isn.get_or_insert(data_seq);
+        let mut rel = data_seq.wrapping_sub(isn);
+        if rel >= 0x8000_0000 {
+            // segmento anterior al inicio capturado (o retransmisión de algo ya perdido)
+            if self.isn_from_syn || !self.data.is_empty() { return; }
+            // sin SYN el ISN salió del primer segmento visto: se rebasa al más bajo
+            let shift = isn.wrapping_sub(data_seq) as u64;
+            self.pending = std::mem::take(&mut self.pending).into_iter().map(|(off, seg)| (off + shift, seg)).collect();
+            self.isn = Some(data_seq);
+            rel = 0;
+        }
+        let start = rel as u64;
+        let end = start + payload.len() as u64;
+        let have = self.data.len() as u64;
+        if end <= have { return; } // retransmisión completa
+        let keep = self.pending.get(&start).map(|p| p.len() as u64).unwrap_or(0);
+        if keep < payload.len() as u64 {
+            self.pending.insert(start, payload.to_vec());
+        }
+        // sin SYN, un primer segmento que no abre un handshake puede ser la mitad de uno que
+        // llegó desordenado: se retiene unos segmentos antes de darlo por inicio del flujo
+        if !self.isn_from_syn && self.data.is_empty() && self.held < REORDER_WINDOW
+            && !self.pending.get(&0).is_some_and(|seg| looks_like_tls_handshake(seg)) {
+            self.held += 1;
+            return;
+        }
+        // drenar segmentos contiguos, recortando solapes
+        while let Some((&off, _)) = self.pending.iter().next() {
+            let have = self.data.len() as u64;
+            if off > have { break; }
+            let seg = self.pending.remove(&off).unwrap_or_default();
+            let skip = (have - off) as usize;
+            if skip < seg.len() {
+                self.data.extend_from_slice(&seg[skip..]);
+            }
+        }
+    }
 }
 
-fn find_client_hello_in_buf(buf: &[u8]) -> Option<Vec<u8>> {
-    // Search for TLS record 0x16 and handshake type 0x01
-    let n = buf.len();
-    for i in 0..n.saturating_sub(9) {
-        if buf[i] == 0x16 && i + 5 < n && buf[i+5] == 0x01 {
-            // record length at i+3..i+4
-            if i + 5 + 4 <= n {
-                let len = ((buf[i+3] as usize) << 8) | (buf[i+4] as usize);
-                let end = i + 5 + len;
-                if end <= n {
-                    return Some(buf[i..end].to_vec());
+struct TcpConversation {
+    first_seen_us: u64,
+    streams: HashMap<FlowKey, TcpStreamBuf>, // por dirección
+    client_hello: Option<(FlowKey, Vec<u8>, u64)>,
+    server_hello: Option<(Vec<u8>, u64)>,
+    comments: Vec<String>,
+    done: bool,
+}
+
+impl TcpConversation {
+    fn new(ts_us: u64) -> Self {
+        TcpConversation { first_seen_us: ts_us, streams: HashMap::new(), client_hello: None, server_hello: None, comments: Vec::new(), done: false }
+    }
+
+    fn snapshot(&mut self) -> Option<TlsSnapshot> {
+        if self.done { return None; }
+        let (key, ch, ch_ts) = self.client_hello.take()?;
+        self.done = true;
+        self.streams.clear();
+        let mut snap = TlsSnapshot::new(ch);
+        let mut server_hello_us = None;
+        if let Some((sh, ts)) = self.server_hello.take() {
+            snap.attach_server_hello(sh, Ja4Transport::Tcp);
+            server_hello_us = Some(ts);
+        }
+        snap.flow = Some(FlowInfo { key, first_seen_us: self.first_seen_us, client_hello_us: ch_ts, server_hello_us });
+        snap.comments = std::mem::take(&mut self.comments);
+        Some(snap)
+    }
+}
+
+struct UdpConversation {
+    first_seen_us: u64,
+    client: FlowKey,
+    streams: [CryptoStream; 2],                // CRYPTO de Initial por dirección (cliente, servidor)
+    hellos: [Option<(Vec<u8>, u64)>; 2],       // ClientHello / ServerHello y cuándo se completaron
+    largest_pn: [Option<u64>; 2],
+    // DCID del primer Initial del cliente: de él salen las claves Initial de ambas direcciones
+    // aunque el cliente cambie de DCID tras el primer Initial del servidor (RFC 9001 §5.2).
+    // Solo un Retry lo sustituye (por el DCID del siguiente Initial del cliente).
+    original_dcid: Option<Vec<u8>>,
+    client_past_initial: bool,                 // el cliente ya envía Handshake/1-RTT: no llegará más ServerHello
+    comments: Vec<String>,
+    done: bool,
+}
+
+impl UdpConversation {
+    fn new(ts_us: u64, client: FlowKey) -> Self {
+        UdpConversation {
+            first_seen_us: ts_us, client, streams: Default::default(), hellos: [None, None], largest_pn: [None, None],
+            original_dcid: None, client_past_initial: false, comments: Vec::new(), done: false,
+        }
+    }
+
+    /// Decrypts the Initial packets of one datagram as it is captured and records the first
+    /// handshake message of each CRYPTO stream (ClientHello, ServerHello) once it is complete.
+    fn on_datagram(&mut self, ts_us: u64, dir: Direction, dg: &[u8]) {
+        let i = (dir == Direction::Server) as usize;
+        for (typ, packet) in quic::split_coalesced(dg) {
+            if typ == PacketType::Retry && dir == Direction::Server {
+                self.original_dcid = None;
+                continue;
+            }
+            if typ != PacketType::Initial {
+                if dir == Direction::Client && matches!(typ, PacketType::Handshake | PacketType::Short) { self.client_past_initial = true; }
+                continue;
+            }
+            let Ok((pkt, _)) = decrypt_initial_packet(packet, dir, self.original_dcid.as_deref(), self.largest_pn[i]) else { continue };
+            self.largest_pn[i] = Some(self.largest_pn[i].map_or(pkt.packet_number, |l| l.max(pkt.packet_number)));
+            if dir == Direction::Client && self.original_dcid.is_none() { self.original_dcid = Some(pkt.dcid.clone()); }
+            let Ok(frames) = quic::parse_frames(&pkt.payload) else { continue };
+            for frame in frames {
+                if let Frame::Crypto { offset, data } = frame { self.streams[i].insert(offset, &data); }
+            }
+            if self.hellos[i].is_none() {
+                let hs_type = if dir == Direction::Client { 0x01 } else { 0x02 };
+                self.hellos[i] = handshake_message_at(self.streams[i].contiguous(), hs_type).map(|m| (m, ts_us));
+            }
+        }
+    }
+
+    /// Same rule as TCP: ClientHello plus ServerHello, or ClientHello once the client has left
+    /// the Initial space (the QUIC counterpart of FIN).
+    fn complete(&self) -> bool {
+        self.hellos[0].is_some() && (self.hellos[1].is_some() || self.client_past_initial)
+    }
+
+    fn snapshot(&mut self) -> Option<TlsSnapshot> {
+        if self.done { return None; }
+        let (ch, ch_ts) = self.hellos[0].take()?;
+        self.done = true;
+        self.streams = Default::default();
+        let mut snap = TlsSnapshot::new(ch);
+        let mut server_hello_us = None;
+        if let Some((sh, sh_ts)) = self.hellos[1].take() {
+            snap.attach_server_hello(sh, Ja4Transport::Quic);
+            server_hello_us = Some(sh_ts);
+        }
+        snap.flow = Some(FlowInfo { key: self.client, first_seen_us: self.first_seen_us, client_hello_us: ch_ts, server_hello_us });
+        snap.comments = std::mem::take(&mut self.comments);
+        Some(snap)
+    }
+}
+
+/// Bare handshake message of type `hs_type` at the start of a QUIC CRYPTO stream (no TLS records).
+fn handshake_message_at(stream: &[u8], hs_type: u8) -> Option<Vec<u8>> {
+    if stream.first() != Some(&hs_type) { return None; }
+    let len = ((*stream.get(1)? as usize) << 16) | ((*stream.get(2)? as usize) << 8) | *stream.get(3)? as usize;
+    stream.get(..4 + len).map(|m| m.to_vec())
+}
+
+/// Packet decoded down to the transport layer.
+struct Segment<'a> {
+    key: FlowKey,
+    seq: u32,
+    flags: u8,
+    payload: &'a [u8],
+}
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+/// Strips the link-layer header and returns the IP datagram it carries.
+fn parse_link(linktype: Linktype, frame: &[u8]) -> Option<&[u8]> {
+    match linktype {
+        Linktype::ETHERNET => {
+            // cabecera Ethernet + etiquetas 802.1Q / 802.1ad (QinQ) anidadas
+            let mut off = 12;
+            let mut ethertype = u16::from_be_bytes([*frame.get(off)?, *frame.get(off + 1)?]);
+            while matches!(ethertype, 0x8100 | 0x88a8 | 0x9100) {
+                off += 4;
+                ethertype = u16::from_be_bytes([*frame.get(off)?, *frame.get(off + 1)?]);
+            }
+            match ethertype {
+                ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => frame.get(off + 2..),
+                _ => None,
+            }
+        }
+        // cooked capture de `tcpdump -i any`: protocolo en bytes 14..16 (SLL) o 0..2 (SLL2)
+        Linktype::LINUX_SLL | Linktype::LINUX_SLL2 => {
+            let (proto_off, hdr_len) = if linktype == Linktype::LINUX_SLL { (14, 16) } else { (0, 20) };
+            let proto = u16::from_be_bytes([*frame.get(proto_off)?, *frame.get(proto_off + 1)?]);
+            match proto {
+                ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => frame.get(hdr_len..),
+                _ => None,
+            }
+        }
+        // NULL (familia en orden del host) y LOOP (orden de red): la versión IP basta para distinguir
+        Linktype::NULL | Linktype::LOOP => frame.get(4..),
+        // LINKTYPE_RAW y los DLT_RAW históricos (12 / 14)
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 | Linktype(12) | Linktype(14) => Some(frame),
+        _ => None,
+    }
+}
+
+/// Parses an IPv4/IPv6 datagram (skipping IPv6 extension headers) and returns
+/// `(src, dst, upper-layer protocol, upper-layer payload)`. Non-first fragments are dropped.
+fn parse_ip(data: &[u8]) -> Option<(IpAddr, IpAddr, u8, &[u8])> {
+    match data.first()? >> 4 {
+        4 => {
+            let ihl = ((data[0] & 0x0f) as usize) * 4;
+            if ihl < 20 || data.len() < ihl { return None; }
+            let total = (u16::from_be_bytes([data[2], data[3]]) as usize).clamp(ihl, data.len());
+            let frag_off = u16::from_be_bytes([data[6], data[7]]) & 0x1fff;
+            if frag_off != 0 { return None; }
+            let src = IpAddr::from(<[u8; 4]>::try_from(&data[12..16]).ok()?);
+            let dst = IpAddr::from(<[u8; 4]>::try_from(&data[16..20]).ok()?);
+            // recortar el relleno Ethernet usando total_length
+            Some((src, dst, data[9], &data[ihl..total]))
+        }
+        6 => {
+            if data.len() < 40 { return None; }
+            let payload_len = u16::from_be_bytes([data[4], data[5]]) as usize;
+            let end = (40 + payload_len).min(data.len());
+            let src = IpAddr::from(<[u8; 16]>::try_from(&data[8..24]).ok()?);
+            let dst = IpAddr::from(<[u8; 16]>::try_from(&data[24..40]).ok()?);
+            let mut next = data[6];
+            let mut off = 40;
+            loop {
+                match next {
+                    // hop-by-hop, routing, destination options, mobility
+                    0 | 43 | 60 | 135 => {
+                        let hdr = data.get(off..off + 2)?;
+                        next = hdr[0];
+                        off += (hdr[1] as usize + 1) * 8;
+                    }
+                    // fragment header: solo el primer fragmento lleva la cabecera de transporte
+                    44 => {
+                        let hdr = data.get(off..off + 8)?;
+                        if u16::from_be_bytes([hdr[2], hdr[3]]) >> 3 != 0 { return None; }
+                        next = hdr[0];
+                        off += 8;
+                    }
+                    // authentication header: longitud en palabras de 32 bits menos 2
+                    51 => {
+                        let hdr = data.get(off..off + 2)?;
+                        next = hdr[0];
+                        off += (hdr[1] as usize + 2) * 4;
+                    }
+                    _ => break,
                 }
             }
+            Some((src, dst, next, data.get(off..end)?))
         }
+        _ => None,
     }
-    None
 }
 
-pub fn import_from_pcap(path: &str) -> Result<TlsSnapshot, String> {
-    let file = File::open(path).map_err(|e| format!("open pcap failed: {}", e))?;
-    let mut reader = LegacyPcapReader::new(65536, file).map_err(|_| "Failed to build pcap reader")?;
-
-    // Map for UDP flows keyed by (src_ip,dst_ip,src_port,dst_port)
-    let mut udp_flows: HashMap<(u32,u32,u16,u16), Vec<u8>> = HashMap::new();
-    let mut tcp_payloads: Vec<u8> = Vec::new();
-
-    loop {
-        match reader.next() {
-            Ok((offset, block)) => {
-                match block {
-                    pcap_parser::PcapBlockOwned::Legacy(pkt) => {
-                        let data = pkt.data;
-                        if let Some(ethernet_packet) = EthernetPacket::new(&data) {
-                            match ethernet_packet.get_ethertype() {
-                                EtherTypes::Ipv4 => {
-                                    if let Some(ipv4_packet) = Ipv4Packet::new(ethernet_packet.payload()) {
-                                        let src_ip = u32::from(ipv4_packet.get_source());
-                                        let dst_ip = u32::from(ipv4_packet.get_destination());
-
-                                        match ipv4_packet.get_next_level_protocol() {
-                                            IpNextHeaderProtocols::Tcp => {
-                                                if let Some(tcp_packet) = TcpPacket::new(ipv4_packet.payload()) {
-                                                    if tcp_packet.get_source() == 443 || tcp_packet.get_destination() == 443 {
-                                                        tcp_payloads.extend_from_slice(tcp_packet.payload());
-                                                    }
-                                                }
-                                            },
-                                            IpNextHeaderProtocols::Udp => {
-                                                if let Some(udp_packet) = UdpPacket::new(ipv4_packet.payload()) {
-                                                    if udp_packet.get_source() == 443 || udp_packet.get_destination() == 443 {
-                                                        let key = (src_ip, dst_ip, udp_packet.get_source(), udp_packet.get_destination());
-                                                        udp_flows.entry(key).or_insert_with(Vec::new).extend_from_slice(udp_packet.payload());
-                                                    }
-                                                }
-                                            },
-                                            _ => {}
-                                        }
-                                    }
-                                },
-                                _ => {} // Ignoring non-IPv4 packets for now
-                            }
-                        }
-                    },
-                    _ => {}
+fn parse_transport(src: IpAddr, dst: IpAddr, proto: u8, l4: &[u8]) -> Option<Segment<'_>> {
+    if proto == IpNextHeaderProtocols::Tcp.0 {
+        let tcp = TcpPacket::new(l4)?;
+        let off = (tcp.get_data_offset() as usize) * 4;
+        let key = FlowKey { src_ip: src, dst_ip: dst, src_port: tcp.get_source(), dst_port: tcp.get_destination(), transport: Transport::Tcp };
+        Some(Segment { key, seq: tcp.get_sequence(), flags: tcp.get_flags(), payload: l4.get(off..)? })
+    } else if proto == IpNextHeaderProtocols::Udp.0 {
+        let udp = UdpPacket::new(l4)?;
+        let key = FlowKey { src_ip: src, dst_ip: dst, src_port: udp.get_source(), dst_port: udp.get_destination(), transport: Transport::Udp };
+        Some(Segment { key, seq: 0, flags: 0, payload: l4.get(8..)? })
+    } else {
+        None
+    }
+}
+
+/// Flow table shared by the streaming importer: feeds packets, queues finished snapshots.
+#[derive(Default)]
+struct FlowTable {
+    filter: FlowFilter,
+    tcp: HashMap<FlowKey, TcpConversation>,
+    udp: HashMap<FlowKey, UdpConversation>, // por conversación
+    ready: VecDeque<TlsSnapshot>,
+}
+
+impl FlowTable {
+    fn on_packet(&mut self, ts_us: u64, linktype: Linktype, frame: &[u8], comments: Vec<String>) {
+        let Some(ip) = parse_link(linktype, frame) else { return };
+        let Some((src, dst, proto, l4)) = parse_ip(ip) else { return };
+        let Some(seg) = parse_transport(src, dst, proto, l4) else { return };
+        if !self.filter.matches_endpoints((seg.key.src_ip, seg.key.src_port), (seg.key.dst_ip, seg.key.dst_port)) { return; }
+        match seg.key.transport {
+            Transport::Tcp => self.on_tcp(ts_us, &seg, comments),
+            Transport::Udp => self.on_udp(ts_us, &seg, comments),
+        }
+    }
+
+    fn on_tcp(&mut self, ts_us: u64, seg: &Segment, comments: Vec<String>) {
+        let conv_key = seg.key.conversation();
+        let syn = seg.flags & TcpFlags::SYN != 0;
+        let fin_or_rst = seg.flags & (TcpFlags::FIN | TcpFlags::RST) != 0;
+        // un ACK/FIN sin datos no abre conversación (p. ej. el cierre de una ya retirada)
+        if !syn && seg.payload.is_empty() && !self.tcp.contains_key(&conv_key) { return; }
+        let conv = self.tcp.entry(conv_key).or_insert_with(|| TcpConversation::new(ts_us));
+        // reutilización de puertos: un SYN nuevo sobre una conversación terminada la reinicia
+        if conv.done && syn && seg.flags & TcpFlags::ACK == 0 {
+            *conv = TcpConversation::new(ts_us);
+        }
+        if conv.done {
+            // conexión cerrada: nada más que esperar de ella
+            if fin_or_rst { self.tcp.remove(&conv_key); }
+            return;
+        }
+        conv.comments.extend(comments);
+
+        let stream = conv.streams.entry(seg.key).or_default();
+        stream.on_segment(seg.seq, syn, seg.payload);
+        // detección por contenido: el primer byte de datos de cada dirección debe abrir un handshake TLS
+        if conv.client_hello.is_none() && !stream.data.is_empty() && !looks_like_tls_handshake(&stream.data) {
+            conv.done = true;
+            conv.streams.clear();
+            if fin_or_rst { self.tcp.remove(&conv_key); }
+            return;
+        }
+        if conv.client_hello.is_none() {
+            if let Some(ch) = handshake_at_stream_start(&stream.data, 0x01) {
+                conv.client_hello = Some((seg.key, ch, ts_us));
+            }
+        }
+        if conv.server_hello.is_none() && !seg.payload.is_empty() {
+            let client = conv.client_hello.as_ref().map(|(k, _, _)| *k);
+            if client.map(|c| c.reversed() == seg.key).unwrap_or(false) {
+                if let Some(sh) = handshake_at_stream_start(&stream.data, 0x02) {
+                    conv.server_hello = Some((sh, ts_us));
                 }
-                reader.consume(offset);
-            },
-            Err(pcap_parser::PcapError::Eof) => break,
-            Err(e) => return Err(format!("pcap read error: {:?}", e)),
+            }
+        }
+        let snap = if conv.client_hello.is_some() && (conv.server_hello.is_some() || fin_or_rst) { conv.snapshot() } else { None };
+        let closed = fin_or_rst && conv.done;
+        if let Some(snap) = snap { self.emit(snap); }
+        if closed { self.tcp.remove(&conv_key); }
+    }
+
+    fn on_udp(&mut self, ts_us: u64, seg: &Segment, comments: Vec<String>) {
+        // el cliente es quien envía el primer Initial QUIC de la conversación
+        let conv_key = seg.key.conversation();
+        if !self.udp.contains_key(&conv_key) && !quic::is_client_initial(seg.payload) { return; }
+        let conv = self.udp.entry(conv_key).or_insert_with(|| UdpConversation::new(ts_us, seg.key));
+        // terminada: se queda sin buffers solo para no abrir otra con los Initial que sigan
+        if conv.done { return; }
+        let dir = if conv.client == seg.key { Direction::Client } else { Direction::Server };
+        conv.on_datagram(ts_us, dir, seg.payload);
+        conv.comments.extend(comments);
+        if conv.complete() {
+            if let Some(snap) = conv.snapshot() { self.emit(snap); }
+        }
+    }
+
+    /// Queues a finished snapshot if it passes the flow-level filters (SNI, ALPN, time window).
+    fn emit(&mut self, snap: TlsSnapshot) {
+        let ts_us = snap.flow.as_ref().map(|f| f.client_hello_us).unwrap_or(0);
+        if self.filter.matches_client_hello(&snap.raw_client_hello, ts_us) {
+            self.ready.push_back(snap);
         }
     }
 
-    // First try to find ClientHello in TCP payloads
-    if let Some(ch) = find_client_hello_in_buf(&tcp_payloads) {
-        return Ok(TlsSnapshot { raw_client_hello: ch, ja3: None });
+    /// Flushes every pending conversation at end of capture, in first-seen order.
+    fn finish(&mut self) {
+        let mut tcp: Vec<_> = self.tcp.drain().map(|(_, c)| c).collect();
+        tcp.sort_by_key(|c| c.first_seen_us);
+        for mut conv in tcp {
+            if let Some(snap) = conv.snapshot() { self.emit(snap); }
+        }
+        let mut udp: Vec<_> = self.udp.drain().map(|(_, c)| c).collect();
+        udp.sort_by_key(|c| c.first_seen_us);
+        for mut conv in udp {
+            if let Some(snap) = conv.snapshot() { self.emit(snap); }
+        }
+    }
+}
+
+/// Capture interface (pcapng IDB, or the single implicit interface of a legacy pcap).
+struct Interface {
+    linktype: Linktype,
+    ts_resolution: u64, // unidades por segundo
+    ts_offset: u64,
+}
+
+fn ts_to_us(sec: u64, frac: u64, resolution: u64) -> u64 {
+    sec * 1_000_000 + (frac as u128 * 1_000_000 / resolution.max(1) as u128) as u64
+}
+
+/// Streaming importer: yields one snapshot per TLS/QUIC flow as soon as its handshake is
+/// complete (ClientHello + ServerHello, or FIN/RST; for QUIC, the client's first Handshake or
+/// 1-RTT packet), and the remaining flows at end of capture. QUIC datagrams are decrypted as
+/// they arrive and closed TCP connections are dropped, so memory follows the open flows.
+/// Accepts legacy pcap and pcapng (detected by file magic). Flows are recognised by content
+/// (TLS handshake record / QUIC Initial) on any port unless the filter restricts them.
+pub struct PcapImporter {
+    reader: Box<dyn PcapReaderIterator>,
+    table: FlowTable,
+    interfaces: Vec<Interface>,
+    eof: bool,
+}
+
+impl PcapImporter {
+    pub fn new<R: Read + 'static>(input: R) -> Result<Self, String> {
+        Self::with_filter(input, FlowFilter::default())
+    }
+
+    pub fn with_filter<R: Read + 'static>(input: R, filter: FlowFilter) -> Result<Self, String> {
+        let reader = create_reader(65536, input).map_err(|e| format!("Failed to build pcap reader: {:?}", e))?;
+        let table = FlowTable { filter, ..Default::default() };
+        Ok(PcapImporter { reader, table, interfaces: Vec::new(), eof: false })
     }
+}
 
-    // Next, for each UDP flow, try to extract crypto from QUIC and find ClientHello
-    for (k, buf) in udp_flows.iter() {
-        let crypto = quic::extract_crypto_from_flow(buf);
-        if crypto.len() > 0 {
-            if let Some(ch) = find_client_hello_in_buf(&crypto) {
-                return Ok(TlsSnapshot { raw_client_hello: ch, ja3: None });
+impl Iterator for PcapImporter {
+    type Item = Result<TlsSnapshot, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(snap) = self.table.ready.pop_front() {
+                return Some(Ok(snap));
             }
-            // Attempt decryption using heuristics (requires DCID)
-            // k is (src_ip,dst_ip,src_port,dst_port) - we might use parts as DCID placeholder
-            // This is a best-effort call; in real flow we need DCID bytes from packet; here we try a fallback
-            if let Ok(pt) = quic_decrypt::decrypt_initial_crypto(&[0u8,1,2,3], 16, &[], &crypto, &[0u8;12], &[]) {
-                if let Some(ch) = find_client_hello_in_buf(&pt) {
-                    return Ok(TlsSnapshot { raw_client_hello: ch, ja3: None });
+            if self.eof { return None; }
+            match self.reader.next() {
+                Ok((offset, block)) => {
+                    match block {
+                        PcapBlockOwned::LegacyHeader(hdr) => {
+                            // 0xa1b23c4d: timestamps en nanosegundos
+                            let nanos = hdr.magic_number == 0xa1b2_3c4d || hdr.magic_number == 0x4d3c_b2a1;
+                            self.interfaces = vec![Interface {
+                                linktype: hdr.network,
+                                ts_resolution: if nanos { 1_000_000_000 } else { 1_000_000 },
+                                ts_offset: 0,
+                            }];
+                        }
+                        PcapBlockOwned::Legacy(pkt) => {
+                            if let Some(iface) = self.interfaces.first() {
+                                let ts_us = ts_to_us(pkt.ts_sec as u64, pkt.ts_usec as u64, iface.ts_resolution);
+                                self.table.on_packet(ts_us, iface.linktype, pkt.data, Vec::new());
+                            }
+                        }
+                        PcapBlockOwned::NG(Block::SectionHeader(_)) => {
+                            // cada sección redefine sus interfaces
+                            self.interfaces.clear();
+                        }
+                        PcapBlockOwned::NG(Block::InterfaceDescription(idb)) => {
+                            self.interfaces.push(Interface {
+                                linktype: idb.linktype,
+                                ts_resolution: idb.ts_resolution().unwrap_or(1_000_000),
+                                ts_offset: idb.ts_offset().max(0) as u64,
+                            });
+                        }
+                        PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                            if let Some(iface) = self.interfaces.get(epb.if_id as usize) {
+                                let (sec, frac) = epb.decode_ts(iface.ts_offset, iface.ts_resolution);
+                                let ts_us = ts_to_us(sec as u64, frac as u64, iface.ts_resolution);
+                                let comments = epb.options.iter()
+                                    .filter(|o| o.code == OptionCode::Comment)
+                                    .filter_map(|o| o.as_bytes())
+                                    .map(|b| String::from_utf8_lossy(b).into_owned())
+                                    .collect();
+                                self.table.on_packet(ts_us, iface.linktype, epb.packet_data(), comments);
+                            }
+                        }
+                        PcapBlockOwned::NG(Block::SimplePacket(spb)) => {
+                            // SPB: sin timestamp, siempre interfaz 0
+                            if let Some(iface) = self.interfaces.first() {
+                                self.table.on_packet(0, iface.linktype, spb.packet_data(), Vec::new());
+                            }
+                        }
+                        PcapBlockOwned::NG(_) => {}
+                    }
+                    self.reader.consume(offset);
+                }
+                Err(PcapError::Eof) => {
+                    self.table.finish();
+                    self.eof = true;
+                }
+                Err(PcapError::Incomplete(_)) => {
+                    if let Err(e) = self.reader.refill() {
+                        self.eof = true;
+                        return Some(Err(format!("pcap read error: {:?}", e)));
+                    }
+                }
+                Err(e) => {
+                    self.eof = true;
+                    return Some(Err(format!("pcap read error: {:?}", e)));
                 }
             }
         }
-        // fallback: direct search in raw flow
-        if let Some(ch) = find_client_hello_in_buf(buf) {
-            return Ok(TlsSnapshot { raw_client_hello: ch, ja3: None });
+    }
+}
+
+/// Opens `path` and returns an iterator over every ClientHello found, one per flow.
+pub fn import_all_from_pcap(path: &str) -> Result<PcapImporter, String> {
+    import_all_from_pcap_filtered(path, FlowFilter::default())
+}
+
+/// Like `import_all_from_pcap`, keeping only the flows accepted by `filter`.
+pub fn import_all_from_pcap_filtered(path: &str, filter: FlowFilter) -> Result<PcapImporter, String> {
+    let file = File::open(path).map_err(|e| format!("open pcap failed: {}", e))?;
+    PcapImporter::with_filter(file, filter)
+}
+
+/// Returns the first ClientHello in the capture.
+pub fn import_from_pcap(path: &str) -> Result<TlsSnapshot, String> {
+    import_all_from_pcap(path)?
+        .next()
+        .unwrap_or_else(|| Err("ClientHello not found in pcap".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp_frame(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut f = vec![0u8; 12];
+        f.extend_from_slice(&[0x08, 0x00]);
+        let total = (20 + 20 + payload.len()) as u16;
+        f.extend_from_slice(&[0x45, 0, (total >> 8) as u8, total as u8, 0, 0, 0x40, 0, 64, 6, 0, 0]);
+        f.extend_from_slice(&src);
+        f.extend_from_slice(&dst);
+        f.extend_from_slice(&sport.to_be_bytes());
+        f.extend_from_slice(&dport.to_be_bytes());
+        f.extend_from_slice(&seq.to_be_bytes());
+        f.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        f.extend_from_slice(payload);
+        f
+    }
+
+    fn pcap(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        out.extend_from_slice(&2u16.to_le_bytes());
+        out.extend_from_slice(&4u16.to_le_bytes());
+        for v in [0u32, 0, 65535, 1] { out.extend_from_slice(&v.to_le_bytes()); }
+        for (i, f) in frames.iter().enumerate() {
+            for v in [1000u32, i as u32, f.len() as u32, f.len() as u32] { out.extend_from_slice(&v.to_le_bytes()); }
+            out.extend_from_slice(f);
+        }
+        out
+    }
+
+    fn record(hs_type: u8, len: usize) -> Vec<u8> {
+        let mut hs = vec![hs_type, 0, (len >> 8) as u8, len as u8];
+        hs.extend((0..len).map(|i| i as u8));
+        let mut rec = vec![0x16, 0x03, 0x01, (hs.len() >> 8) as u8, hs.len() as u8];
+        rec.extend_from_slice(&hs);
+        rec
+    }
+
+    #[test]
+    fn test_reassembles_out_of_order_flows() {
+        let (c1, c2, c3, s) = ([10, 0, 0, 1], [10, 0, 0, 2], [10, 0, 0, 3], [10, 0, 0, 9]);
+        let ch = record(0x01, 300);
+        let sh = record(0x02, 80);
+        let frames = vec![
+            tcp_frame(c1, s, 5000, 443, 100, TcpFlags::SYN, &[]),
+            tcp_frame(c2, s, 6000, 443, 7000, TcpFlags::SYN, &[]),
+            // flujo 1: segundo trozo antes que el primero, más una retransmisión
+            tcp_frame(c1, s, 5000, 443, 101 + 150, TcpFlags::ACK, &ch[150..]),
+            tcp_frame(c1, s, 5000, 443, 101, TcpFlags::ACK, &ch[..150]),
+            tcp_frame(c1, s, 5000, 443, 101, TcpFlags::ACK, &ch[..150]),
+            tcp_frame(s, c1, 443, 5000, 900, TcpFlags::SYN | TcpFlags::ACK, &[]),
+            tcp_frame(s, c1, 443, 5000, 901, TcpFlags::ACK, &sh),
+            // flujo 2: solo ClientHello
+            tcp_frame(c2, s, 6000, 443, 7001, TcpFlags::ACK, &ch),
+            // flujo 3: captura sin SYN y el segundo trozo llega primero
+            tcp_frame(c3, s, 7000, 443, 3001 + 150, TcpFlags::ACK, &ch[150..]),
+            tcp_frame(c3, s, 7000, 443, 3001, TcpFlags::ACK, &ch[..150]),
+        ];
+        let snaps: Vec<TlsSnapshot> = PcapImporter::new(std::io::Cursor::new(pcap(&frames))).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(snaps.len(), 3);
+        assert_eq!(snaps[0].raw_client_hello, ch);
+        assert_eq!(snaps[0].raw_server_hello.as_deref(), Some(&sh[..]));
+        let flow = snaps[0].flow.as_ref().unwrap();
+        assert_eq!(flow.key.src_port, 5000);
+        assert_eq!(flow.client_hello_us, 1_000_000_000 + 3);
+        assert_eq!(snaps[1].flow.as_ref().unwrap().key.src_port, 6000);
+        assert!(snaps[1].raw_server_hello.is_none());
+        assert_eq!(snaps[2].flow.as_ref().unwrap().key.src_port, 7000);
+        assert_eq!(snaps[2].raw_client_hello, ch);
+    }
+
+    fn ng_block(typ: u32, body: &[u8]) -> Vec<u8> {
+        let len = (12 + body.len()) as u32;
+        let mut b = typ.to_le_bytes().to_vec();
+        b.extend_from_slice(&len.to_le_bytes());
+        b.extend_from_slice(body);
+        b.extend_from_slice(&len.to_le_bytes());
+        b
+    }
+
+    fn ng_option(code: u16, value: &[u8]) -> Vec<u8> {
+        let mut o = code.to_le_bytes().to_vec();
+        o.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        o.extend_from_slice(value);
+        o.resize(o.len().div_ceil(4) * 4, 0);
+        o
+    }
+
+    #[test]
+    fn test_pcapng_interfaces_and_comments() {
+        let (c, s) = ([192, 168, 1, 2], [192, 168, 1, 1]);
+        let ch = record(0x01, 200);
+        let mut ng = ng_block(0x0A0D_0D0A, &[&0x1A2B_3C4Du32.to_le_bytes()[..], &[1, 0, 0, 0], &[0xff; 8]].concat());
+        // interfaz 0: LINKTYPE_USB (ignorada); interfaz 1: Ethernet con resolución en ns
+        ng.extend(ng_block(1, &[&[189, 0, 0, 0], &0u32.to_le_bytes()[..]].concat()));
+        ng.extend(ng_block(1, &[&[1, 0, 0, 0], &0u32.to_le_bytes()[..], &ng_option(9, &[9]), &[0, 0, 0, 0]].concat()));
+        for (i, (frame, comment)) in [
+            (tcp_frame(c, s, 40000, 443, 1, TcpFlags::SYN, &[]), None),
+            (tcp_frame(c, s, 40000, 443, 2, TcpFlags::ACK, &ch), Some("chrome hello")),
+        ].into_iter().enumerate() {
+            let ts: u64 = 5_000_000_000 + i as u64 * 1000;
+            let mut body = 1u32.to_le_bytes().to_vec();
+            body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+            body.extend_from_slice(&(ts as u32).to_le_bytes());
+            body.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            body.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            body.extend_from_slice(&frame);
+            body.resize(body.len().div_ceil(4) * 4, 0);
+            if let Some(text) = comment {
+                body.extend(ng_option(1, text.as_bytes()));
+                body.extend_from_slice(&[0, 0, 0, 0]);
+            }
+            ng.extend(ng_block(6, &body));
         }
+        let snaps: Vec<TlsSnapshot> = PcapImporter::new(std::io::Cursor::new(ng)).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(snaps.len(), 1);
+        assert_eq!(snaps[0].raw_client_hello, ch);
+        assert_eq!(snaps[0].comments, vec!["chrome hello".to_string()]);
+        assert_eq!(snaps[0].flow.as_ref().unwrap().client_hello_us, 5_000_001);
+    }
+
+    fn hello_with_sni(host: &str) -> Vec<u8> {
+        use hello_template::{Encoder, Extension, HelloTemplate};
+        let tpl = HelloTemplate {
+            record_version: Some(0x0301),
+            client_random: Some([7; 32]), session_id: Some(vec![]),
+            cipher_suites: vec![0x1301],
+            extensions: vec![
+                Extension::ServerName { host: host.into() },
+                Extension::Alpn { protocols: vec!["h2".into()] },
+            ],
+            ..Default::default()
+        };
+        Encoder::encode_client_hello(&tpl, false).unwrap().raw_bytes
     }
 
-    Err("ClientHello not found in pcap (tcp or udp 443)".to_string())
+    #[test]
+    fn test_content_detection_and_filters() {
+        let (c, s) = ([10, 1, 0, 5], [172, 16, 0, 1]);
+        let a = hello_with_sni("api.example.com");
+        let b = hello_with_sni("other.test");
+        let frames = vec![
+            // TLS en un puerto no estándar, HTTP en claro y un segundo TLS en 443
+            tcp_frame(c, s, 41000, 8443, 1, TcpFlags::SYN, &[]),
+            tcp_frame(c, s, 41000, 8443, 2, TcpFlags::ACK, &a),
+            tcp_frame(c, s, 41001, 80, 1, TcpFlags::SYN, &[]),
+            tcp_frame(c, s, 41001, 80, 2, TcpFlags::ACK, b"GET / HTTP/1.1\r\n\r\n"),
+            tcp_frame(c, s, 41002, 443, 1, TcpFlags::SYN, &[]),
+            tcp_frame(c, s, 41002, 443, 2, TcpFlags::ACK, &b),
+        ];
+        let run = |filter: FlowFilter| -> Vec<u16> {
+            PcapImporter::with_filter(std::io::Cursor::new(pcap(&frames)), filter).unwrap()
+                .map(|r| r.unwrap().flow.unwrap().key.dst_port)
+                .collect()
+        };
+        assert_eq!(run(FlowFilter::default()), vec![8443, 443]);
+        assert_eq!(run(FlowFilter { ports: vec![443], ..Default::default() }), vec![443]);
+        assert_eq!(run(FlowFilter { sni_glob: Some("*.example.com".into()), ..Default::default() }), vec![8443]);
+        assert_eq!(run(FlowFilter { cidrs: vec!["172.16.0.0/12".parse().unwrap()], alpn: vec!["h2".into()], ..Default::default() }), vec![8443, 443]);
+        assert_eq!(run(FlowFilter { alpn: vec!["h3".into()], ..Default::default() }), Vec::<u16>::new());
+        // ventana temporal sobre el ClientHello: frame 1 → 1_000_000_001 us, frame 5 → 1_000_000_005 us
+        assert_eq!(run(FlowFilter { time_from_us: Some(1_000_000_004), ..Default::default() }), vec![443]);
+    }
+
+    #[test]
+    fn test_quic_initial_on_any_port() {
+        use hello_template::quic_initial::{encode_quic_initials, QuicInitialOptions};
+        let ch = hello_with_sni("quic.example.com");
+        // sin cabecera de record: en QUIC el ClientHello viaja suelto en frames CRYPTO
+        let hs = ch[5..].to_vec();
+        let mut opts = QuicInitialOptions::new(vec![0x5a; 8], vec![1, 2, 3, 4]);
+        opts.datagram_size = 80; // fuerza el reparto en varios paquetes
+        let dgs = encode_quic_initials(&hs, &opts).unwrap();
+        assert!(dgs.len() > 1);
+        let (c, s) = ([10, 9, 0, 1], [10, 9, 0, 2]);
+        let mut frames: Vec<Vec<u8>> = dgs.iter().rev().map(|dg| udp_frame(c, s, 50000, 4433, dg)).collect();
+        frames.push(udp_frame(c, s, 50001, 53, b"not quic at all"));
+        let snaps: Vec<TlsSnapshot> = PcapImporter::new(std::io::Cursor::new(pcap(&frames))).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(snaps.len(), 1);
+        assert_eq!(snaps[0].raw_client_hello, hs);
+        let flow = snaps[0].flow.as_ref().unwrap();
+        assert_eq!((flow.key.transport, flow.key.dst_port), (Transport::Udp, 4433));
+    }
+
+    // Initial protegido a mano: permite cifrar con claves de un DCID distinto del de la cabecera
+    fn initial_packet(keys_dcid: &[u8], dcid: &[u8], scid: &[u8], dir: Direction, pn: u8, frames: &[u8]) -> Vec<u8> {
+        use aes_gcm::aead::{Aead, KeyInit, Payload};
+        use aes_gcm::{Aes128Gcm, Nonce};
+        use crate::quic_decrypt::{header_protection_mask, initial_keys};
+        let keys = initial_keys(quic::QUIC_V1, keys_dcid, dir).unwrap();
+        let mut payload = frames.to_vec();
+        payload.resize(payload.len().max(32), 0);
+        let mut packet = vec![0xc0];
+        packet.extend_from_slice(&quic::QUIC_V1.to_be_bytes());
+        packet.push(dcid.len() as u8);
+        packet.extend_from_slice(dcid);
+        packet.push(scid.len() as u8);
+        packet.extend_from_slice(scid);
+        packet.push(0); // token
+        packet.extend_from_slice(&(0x4000 | (1 + payload.len() + 16) as u16).to_be_bytes());
+        let pn_offset = packet.len();
+        packet.push(pn);
+        let sealed = Aes128Gcm::new_from_slice(&keys.key).unwrap()
+            .encrypt(Nonce::from_slice(&keys.nonce(pn as u64)), Payload { msg: &payload, aad: &packet })
+            .unwrap();
+        packet.extend_from_slice(&sealed);
+        let mask = header_protection_mask(&keys.hp, &packet[pn_offset + 4..pn_offset + 20]).unwrap();
+        packet[0] ^= mask[0] & 0x0f;
+        packet[pn_offset] ^= mask[1];
+        packet
+    }
+
+    fn crypto_frame(offset: usize, data: &[u8]) -> Vec<u8> {
+        let mut f = vec![0x06, 0x40 | (offset >> 8) as u8, offset as u8, 0x40 | (data.len() >> 8) as u8, data.len() as u8];
+        f.extend_from_slice(data);
+        f
+    }
+
+    #[test]
+    fn test_quic_snapshot_is_emitted_before_end_of_capture() {
+        let (c, s) = ([10, 7, 0, 1], [10, 7, 0, 2]);
+        let tcp_ch = record(0x01, 120);
+        let ch = hello_with_sni("stream.example")[5..].to_vec();
+        let sh = vec![0x02, 0, 0, 4, 3, 3, 0, 0];
+        let (odcid, client_cid, server_cid) = ([0x11; 8], [0x22; 4], [0x33; 4]);
+        let frames = vec![
+            // TCP sin respuesta ni FIN: solo sale al final de la captura
+            tcp_frame(c, s, 40100, 443, 1, TcpFlags::SYN, &[]),
+            tcp_frame(c, s, 40100, 443, 2, TcpFlags::ACK, &tcp_ch),
+            udp_frame(c, s, 50100, 443, &initial_packet(&odcid, &odcid, &client_cid, Direction::Client, 0, &crypto_frame(0, &ch))),
+            udp_frame(s, c, 443, 50100, &initial_packet(&odcid, &client_cid, &server_cid, Direction::Server, 0, &crypto_frame(0, &sh))),
+        ];
+        let snaps: Vec<TlsSnapshot> = PcapImporter::new(std::io::Cursor::new(pcap(&frames))).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(snaps.len(), 2);
+        assert_eq!((&snaps[0].raw_client_hello, snaps[0].raw_server_hello.as_deref()), (&ch, Some(&sh[..])));
+        assert_eq!(snaps[0].flow.as_ref().unwrap().server_hello_us, Some(1_000_000_003));
+        assert_eq!(snaps[1].raw_client_hello, tcp_ch);
+    }
+
+    #[test]
+    fn test_quic_initial_keys_follow_original_dcid() {
+        let (c, s) = ([10, 6, 0, 1], [10, 6, 0, 2]);
+        let ch = hello_with_sni("dcid.example")[5..].to_vec();
+        let sh = vec![0x02, 0, 0, 4, 3, 3, 0, 0];
+        let (odcid, client_cid, server_cid) = ([0x44; 8], [0x55; 4], [0x66; 6]);
+        let mid = ch.len() / 2;
+        // tras el Initial del servidor el cliente usa su SCID como DCID, pero las claves no cambian
+        let frames = vec![
+            udp_frame(c, s, 50200, 443, &initial_packet(&odcid, &odcid, &client_cid, Direction::Client, 0, &crypto_frame(0, &ch[..mid]))),
+            udp_frame(s, c, 443, 50200, &initial_packet(&odcid, &client_cid, &server_cid, Direction::Server, 0, &[0x02, 0, 0, 0, 0])),
+            udp_frame(c, s, 50200, 443, &initial_packet(&odcid, &server_cid, &client_cid, Direction::Client, 1, &crypto_frame(mid, &ch[mid..]))),
+            udp_frame(s, c, 443, 50200, &initial_packet(&odcid, &client_cid, &server_cid, Direction::Server, 1, &crypto_frame(0, &sh))),
+        ];
+        let snaps: Vec<TlsSnapshot> = PcapImporter::new(std::io::Cursor::new(pcap(&frames))).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(snaps.len(), 1);
+        assert_eq!((&snaps[0].raw_client_hello, snaps[0].raw_server_hello.as_deref()), (&ch, Some(&sh[..])));
+
+        // un Retry sí cambia las claves: el cliente reenvía el ClientHello con el DCID que eligió el servidor
+        let retry_cid = [0x77; 8];
+        let mut retry = vec![0xf0];
+        retry.extend_from_slice(&quic::QUIC_V1.to_be_bytes());
+        retry.extend_from_slice(&[4, 0x55, 0x55, 0x55, 0x55, 8]);
+        retry.extend_from_slice(&retry_cid);
+        retry.extend_from_slice(&[0xaa; 8 + 16]); // token + integrity tag
+        let frames = vec![
+            udp_frame(c, s, 50201, 443, &initial_packet(&odcid, &odcid, &client_cid, Direction::Client, 0, &crypto_frame(0, &ch[..mid]))),
+            udp_frame(s, c, 443, 50201, &retry),
+            udp_frame(c, s, 50201, 443, &initial_packet(&retry_cid, &retry_cid, &client_cid, Direction::Client, 1, &crypto_frame(0, &ch))),
+            udp_frame(s, c, 443, 50201, &initial_packet(&retry_cid, &client_cid, &server_cid, Direction::Server, 0, &crypto_frame(0, &sh))),
+        ];
+        let snaps: Vec<TlsSnapshot> = PcapImporter::new(std::io::Cursor::new(pcap(&frames))).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(snaps.len(), 1);
+        assert_eq!((&snaps[0].raw_client_hello, snaps[0].raw_server_hello.as_deref()), (&ch, Some(&sh[..])));
+    }
+
+    #[test]
+    fn test_closed_tcp_flows_are_evicted() {
+        let (c, s) = ([10, 8, 0, 1], [10, 8, 0, 2]);
+        let mut table = FlowTable::default();
+        let feed = |table: &mut FlowTable, frames: Vec<Vec<u8>>| {
+            for f in frames { table.on_packet(0, Linktype::ETHERNET, &f, Vec::new()); }
+        };
+        feed(&mut table, vec![
+            tcp_frame(c, s, 40200, 443, 1, TcpFlags::SYN, &[]),
+            tcp_frame(c, s, 40200, 443, 2, TcpFlags::ACK, &record(0x01, 50)),
+            tcp_frame(s, c, 443, 40200, 9, TcpFlags::ACK, &record(0x02, 40)),
+        ]);
+        assert_eq!((table.ready.len(), table.tcp.len()), (1, 1));
+        feed(&mut table, vec![
+            tcp_frame(c, s, 40200, 443, 57, TcpFlags::FIN | TcpFlags::ACK, &[]),
+            tcp_frame(s, c, 443, 40200, 54, TcpFlags::ACK, &[]),
+            // HTTP en claro cerrado con RST: tampoco se queda en la tabla
+            tcp_frame(c, s, 40201, 80, 1, TcpFlags::SYN, &[]),
+            tcp_frame(c, s, 40201, 80, 2, TcpFlags::ACK, b"GET / HTTP/1.1\r\n\r\n"),
+            tcp_frame(s, c, 80, 40201, 1, TcpFlags::RST, &[]),
+        ]);
+        assert!(table.tcp.is_empty());
+        assert_eq!(table.ready.len(), 1);
+    }
+
+    fn udp_frame(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16, payload: &[u8]) -> Vec<u8> {
+        let mut f = vec![0u8; 12];
+        f.extend_from_slice(&[0x08, 0x00]);
+        let total = (20 + 8 + payload.len()) as u16;
+        f.extend_from_slice(&[0x45, 0, (total >> 8) as u8, total as u8, 0, 0, 0x40, 0, 64, 17, 0, 0]);
+        f.extend_from_slice(&src);
+        f.extend_from_slice(&dst);
+        f.extend_from_slice(&sport.to_be_bytes());
+        f.extend_from_slice(&dport.to_be_bytes());
+        f.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        f.extend_from_slice(&[0, 0]);
+        f.extend_from_slice(payload);
+        f
+    }
+
+    #[test]
+    fn test_link_types_and_ipv6_extension_headers() {
+        let l4 = [0x01, 0xbb, 0x9c, 0x40];
+        // IPv6 con cabecera hop-by-hop (8 bytes) antes de TCP
+        let mut v6 = vec![0x60, 0, 0, 0, 0, 12, 0, 64];
+        v6.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        v6.extend_from_slice(&[0; 12]);
+        v6.extend_from_slice(&[0; 15]);
+        v6.push(1);
+        v6.extend_from_slice(&[6, 0, 0, 0, 0, 0, 0, 0]);
+        v6.extend_from_slice(&l4);
+
+        // Ethernet + QinQ (0x88a8 + 0x8100)
+        let mut eth = vec![0u8; 12];
+        eth.extend_from_slice(&[0x88, 0xa8, 0, 10, 0x81, 0x00, 0, 20, 0x86, 0xdd]);
+        eth.extend_from_slice(&v6);
+        let (src, dst, proto, payload) = parse_ip(parse_link(Linktype::ETHERNET, &eth).unwrap()).unwrap();
+        assert_eq!(src, "2001:db8::".parse::<IpAddr>().unwrap());
+        assert_eq!(dst, "::1".parse::<IpAddr>().unwrap());
+        assert_eq!((proto, payload), (6, &l4[..]));
+
+        // Linux SLL2 (tcpdump -i any) + IPv4
+        let mut v4 = vec![0x45, 0, 0, 24, 0, 0, 0x40, 0, 64, 17, 0, 0, 127, 0, 0, 1, 127, 0, 0, 1];
+        v4.extend_from_slice(&l4);
+        let mut sll2 = vec![0x08, 0x00];
+        sll2.extend_from_slice(&[0; 18]);
+        sll2.extend_from_slice(&v4);
+        assert_eq!(parse_ip(parse_link(Linktype::LINUX_SLL2, &sll2).unwrap()).unwrap().2, 17);
+
+        // BSD loopback (AF_INET en orden del host) y RAW
+        let mut null = vec![2, 0, 0, 0];
+        null.extend_from_slice(&v4);
+        assert!(parse_ip(parse_link(Linktype::NULL, &null).unwrap()).is_some());
+        assert!(parse_ip(parse_link(Linktype::RAW, &v6).unwrap()).is_some());
+    }
 }
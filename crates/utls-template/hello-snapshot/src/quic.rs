@@ -1,4 +1,6 @@
-// Heuristic QUIC parser to extract CRYPTO frame payloads from Initial packets
+// QUIC packet/frame parsing (RFC 9000): coalesced packets, frames and CRYPTO stream reassembly
+
+use std::collections::BTreeMap;
 
 pub fn read_varint(buf: &[u8], pos: usize) -> Option<(u64, usize)> {
     if pos >= buf.len() { return None; }
@@ -17,96 +19,283 @@ fn is_long_header(first: u8) -> bool {
     (first & 0x80) != 0
 }
 
-// Try to extract CRYPTO frame payloads from a QUIC packet payload (after UDP/IP headers)
-pub fn extract_crypto_from_quic_packet(payload: &[u8]) -> Vec<u8> {
-    let mut out: Vec<u8> = Vec::new();
-    if payload.len() < 6 { return out; }
-    let first = payload[0];
-    if !is_long_header(first) { return out; }
-    // version at 1..5
-    if payload.len() < 6 { return out; }
-    let version = u32::from_be_bytes([payload[1], payload[2], payload[3], payload[4]]);
-    if version == 0 { return out; }
-    // parse DCID/SCID lengths at pos 5
+pub use hello_template::quic_initial::{QUIC_V1, QUIC_V2};
+
+/// True if the datagram starts with a long-header Initial packet of a known QUIC version
+/// (v1, v2 or an IETF draft), regardless of the UDP port it was sent to.
+pub fn is_client_initial(datagram: &[u8]) -> bool {
+    if datagram.len() < 7 { return false; }
+    let first = datagram[0];
+    // long header + fixed bit
+    if first & 0xc0 != 0xc0 { return false; }
+    let version = u32::from_be_bytes([datagram[1], datagram[2], datagram[3], datagram[4]]);
+    let initial_type = match version {
+        QUIC_V1 => 0x00,
+        QUIC_V2 => 0x01,
+        v if v >> 8 == 0x00ff_0000 => 0x00, // drafts
+        _ => return false,
+    };
+    // DCID de un Initial de cliente: al menos 8 bytes (RFC 9000 §7.2), máx. 20
+    let dcid_len = datagram[5] as usize;
+    (first >> 4) & 0x03 == initial_type && (8..=20).contains(&dcid_len)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketType { Initial, ZeroRtt, Handshake, Retry, VersionNegotiation, Short }
+
+/// Long-header packet type, taking the QUIC v2 codepoint permutation into account (RFC 9369 §3.2).
+fn long_packet_type(first: u8, version: u32) -> PacketType {
+    let bits = (first >> 4) & 0x03;
+    let bits = if version == QUIC_V2 { bits.wrapping_sub(1) & 0x03 } else { bits };
+    match bits {
+        0 => PacketType::Initial,
+        1 => PacketType::ZeroRtt,
+        2 => PacketType::Handshake,
+        _ => PacketType::Retry,
+    }
+}
+
+/// Splits a UDP datagram into its coalesced QUIC packets (RFC 9000 §12.2).
+///
+/// Long-header packets end at their Length field; Retry, Version Negotiation and short-header
+/// packets have no length and take the rest of the datagram. A malformed packet ends the split.
+pub fn split_coalesced(datagram: &[u8]) -> Vec<(PacketType, &[u8])> {
+    let mut out = Vec::new();
+    let mut pos = 0usize;
+    while pos < datagram.len() {
+        let buf = &datagram[pos..];
+        let first = buf[0];
+        if !is_long_header(first) {
+            out.push((PacketType::Short, buf));
+            break;
+        }
+        let Some(version) = buf.get(1..5).map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]])) else { break };
+        if version == 0 {
+            out.push((PacketType::VersionNegotiation, buf));
+            break;
+        }
+        let typ = long_packet_type(first, version);
+        if typ == PacketType::Retry {
+            out.push((typ, buf));
+            break;
+        }
+        let Some(len) = long_packet_len(buf, typ) else { break };
+        out.push((typ, &buf[..len]));
+        pos += len;
+    }
+    out
+}
+
+fn long_packet_len(buf: &[u8], typ: PacketType) -> Option<usize> {
     let mut idx = 5;
-    if idx >= payload.len() { return out; }
-    let dcid_len = payload[idx] as usize; idx += 1;
-    if idx + dcid_len > payload.len() { return out; }
-    idx += dcid_len;
-    if idx >= payload.len() { return out; }
-    let scid_len = payload[idx] as usize; idx += 1;
-    if idx + scid_len > payload.len() { return out; }
-    idx += scid_len;
-    // token length: varint
-    if let Some((toklen, toklen_bytes)) = read_varint(payload, idx) {
-        idx += toklen_bytes;
-        if idx + (toklen as usize) > payload.len() { return out; }
-        idx += toklen as usize;
-    } else {
-        return out;
+    idx += 1 + *buf.get(idx)? as usize;          // DCID
+    idx += 1 + *buf.get(idx)? as usize;          // SCID
+    if typ == PacketType::Initial {
+        let (tok_len, n) = read_varint(buf, idx)?;
+        idx += n + tok_len as usize;
+    }
+    let (length, n) = read_varint(buf, idx)?;
+    let end = idx + n + length as usize;
+    if end > buf.len() { return None; }
+    Some(end)
+}
+
+/// QUIC frame (RFC 9000 §19, plus DATAGRAM from RFC 9221).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Padding(usize), // número de bytes 0x00 consecutivos
+    Ping,
+    Ack { largest: u64, delay: u64, first_range: u64, ranges: Vec<(u64, u64)>, ecn: Option<[u64; 3]> },
+    ResetStream { stream_id: u64, error_code: u64, final_size: u64 },
+    StopSending { stream_id: u64, error_code: u64 },
+    Crypto { offset: u64, data: Vec<u8> },
+    NewToken { token: Vec<u8> },
+    Stream { stream_id: u64, offset: u64, fin: bool, data: Vec<u8> },
+    MaxData(u64),
+    MaxStreamData { stream_id: u64, max: u64 },
+    MaxStreams { bidi: bool, max: u64 },
+    DataBlocked(u64),
+    StreamDataBlocked { stream_id: u64, limit: u64 },
+    StreamsBlocked { bidi: bool, limit: u64 },
+    NewConnectionId { seq: u64, retire_prior_to: u64, cid: Vec<u8>, reset_token: [u8; 16] },
+    RetireConnectionId(u64),
+    PathChallenge([u8; 8]),
+    PathResponse([u8; 8]),
+    ConnectionClose { error_code: u64, frame_type: Option<u64>, reason: Vec<u8> }, // frame_type solo en 0x1c
+    HandshakeDone,
+    Datagram { data: Vec<u8> },
+}
+
+struct FrameReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> FrameReader<'a> {
+    fn varint(&mut self) -> Result<u64, String> {
+        let (v, n) = read_varint(self.buf, self.pos).ok_or("truncated varint in frame")?;
+        self.pos += n;
+        Ok(v)
     }
-    // length of packet (varint) - skip
-    if let Some((_plen, plen_bytes)) = read_varint(payload, idx) {
-        idx += plen_bytes;
-    } else {
-        return out;
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let b = self.buf.get(self.pos..self.pos + len).ok_or("truncated frame data")?;
+        self.pos += len;
+        Ok(b)
     }
-    if idx >= payload.len() { return out; }
-    // Now idx points to frames area. Iterate frames and collect CRYPTO frames (type 0x06)
-    while idx < payload.len() {
-        // read frame type varint
-        if let Some((ftype, ftype_len)) = read_varint(payload, idx) {
-            idx += ftype_len;
-            // CRYPTO frame has type 0x06
-            if ftype == 0x06 {
-                if let Some((flen, flen_len)) = read_varint(payload, idx) {
-                    idx += flen_len;
-                    let flen_usize = flen as usize;
-                    if idx + flen_usize <= payload.len() {
-                        out.extend_from_slice(&payload[idx..idx+flen_usize]);
-                        idx += flen_usize;
-                        continue;
-                    } else { break; }
-                } else { break; }
-            } else {
-                // skip known frame types minimally: for simplicity, try to skip a length varint if applicable
-                // many frames have varint length (e.g., STREAM). We'll attempt to skip if next bytes look like varint.
-                if ftype == 0x10 || ftype == 0x1d || ftype == 0x1e { // STREAM types (heuristic)
-                    // STREAM: stream id varint + offset varint + length varint + data
-                    if let Some((_sid, sid_len)) = read_varint(payload, idx) {
-                        idx += sid_len;
-                        if let Some((_off, off_len)) = read_varint(payload, idx) {
-                            idx += off_len;
-                            if let Some((dlen, dlen_len)) = read_varint(payload, idx) {
-                                idx += dlen_len + dlen as usize;
-                                continue;
-                            } else { break; }
-                        } else { break; }
-                    } else { break; }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut a = [0u8; N];
+        a.copy_from_slice(self.bytes(N)?);
+        Ok(a)
+    }
+}
+
+/// Decodes every frame of a decrypted packet payload. An unknown frame type is an error: its
+/// length cannot be known, so nothing after it can be trusted.
+pub fn parse_frames(payload: &[u8]) -> Result<Vec<Frame>, String> {
+    let mut r = FrameReader { buf: payload, pos: 0 };
+    let mut frames = Vec::new();
+    while r.pos < payload.len() {
+        let typ = r.varint()?;
+        let frame = match typ {
+            0x00 => {
+                let run = payload[r.pos..].iter().take_while(|b| **b == 0).count();
+                r.pos += run;
+                Frame::Padding(run + 1)
+            }
+            0x01 => Frame::Ping,
+            0x02 | 0x03 => {
+                let largest = r.varint()?;
+                let delay = r.varint()?;
+                let count = r.varint()?;
+                let first_range = r.varint()?;
+                let mut ranges = Vec::new();
+                for _ in 0..count {
+                    let gap = r.varint()?;
+                    ranges.push((gap, r.varint()?));
                 }
-                // fallback: break to avoid infinite loop
-                break;
+                let ecn = if typ == 0x03 { Some([r.varint()?, r.varint()?, r.varint()?]) } else { None };
+                Frame::Ack { largest, delay, first_range, ranges, ecn }
+            }
+            0x04 => Frame::ResetStream { stream_id: r.varint()?, error_code: r.varint()?, final_size: r.varint()? },
+            0x05 => Frame::StopSending { stream_id: r.varint()?, error_code: r.varint()? },
+            0x06 => {
+                let offset = r.varint()?;
+                let len = r.varint()? as usize;
+                Frame::Crypto { offset, data: r.bytes(len)?.to_vec() }
+            }
+            0x07 => {
+                let len = r.varint()? as usize;
+                Frame::NewToken { token: r.bytes(len)?.to_vec() }
             }
-        } else { break; }
+            0x08..=0x0f => {
+                // bits OFF (0x04), LEN (0x02), FIN (0x01)
+                let stream_id = r.varint()?;
+                let offset = if typ & 0x04 != 0 { r.varint()? } else { 0 };
+                let len = if typ & 0x02 != 0 { r.varint()? as usize } else { payload.len() - r.pos };
+                Frame::Stream { stream_id, offset, fin: typ & 0x01 != 0, data: r.bytes(len)?.to_vec() }
+            }
+            0x10 => Frame::MaxData(r.varint()?),
+            0x11 => Frame::MaxStreamData { stream_id: r.varint()?, max: r.varint()? },
+            0x12 | 0x13 => Frame::MaxStreams { bidi: typ == 0x12, max: r.varint()? },
+            0x14 => Frame::DataBlocked(r.varint()?),
+            0x15 => Frame::StreamDataBlocked { stream_id: r.varint()?, limit: r.varint()? },
+            0x16 | 0x17 => Frame::StreamsBlocked { bidi: typ == 0x16, limit: r.varint()? },
+            0x18 => {
+                let seq = r.varint()?;
+                let retire_prior_to = r.varint()?;
+                let len = r.bytes(1)?[0] as usize;
+                if !(1..=20).contains(&len) { return Err(format!("invalid connection id length {}", len)); }
+                let cid = r.bytes(len)?.to_vec();
+                Frame::NewConnectionId { seq, retire_prior_to, cid, reset_token: r.array()? }
+            }
+            0x19 => Frame::RetireConnectionId(r.varint()?),
+            0x1a => Frame::PathChallenge(r.array()?),
+            0x1b => Frame::PathResponse(r.array()?),
+            0x1c | 0x1d => {
+                let error_code = r.varint()?;
+                let frame_type = if typ == 0x1c { Some(r.varint()?) } else { None };
+                let len = r.varint()? as usize;
+                Frame::ConnectionClose { error_code, frame_type, reason: r.bytes(len)?.to_vec() }
+            }
+            0x1e => Frame::HandshakeDone,
+            0x30 | 0x31 => {
+                let len = if typ == 0x31 { r.varint()? as usize } else { payload.len() - r.pos };
+                Frame::Datagram { data: r.bytes(len)?.to_vec() }
+            }
+            t => return Err(format!("unknown QUIC frame type {:#x}", t)),
+        };
+        frames.push(frame);
     }
-    out
+    Ok(frames)
 }
 
-// Given a buffer that is concatenation of UDP payloads for a flow, collect CRYPTO bytes across packets
-pub fn extract_crypto_from_flow(buf: &[u8]) -> Vec<u8> {
-    let mut acc: Vec<u8> = Vec::new();
-    // try to scan for QUIC long headers and extract
-    let mut pos = 0usize;
-    while pos + 6 <= buf.len() {
-        if (buf[pos] & 0x80) != 0 && pos + 5 < buf.len() {
-            // try to extract from pos
-            let slice = &buf[pos..];
-            let extracted = extract_crypto_from_quic_packet(slice);
-            if !extracted.is_empty() {
-                acc.extend_from_slice(&extracted);
+/// CRYPTO stream of one packet-number space, reassembled by offset across packets.
+#[derive(Default)]
+pub struct CryptoStream {
+    data: Vec<u8>,                    // bytes contiguos desde el offset 0
+    pending: BTreeMap<u64, Vec<u8>>,  // fragmentos por delante del hueco
+}
+
+impl CryptoStream {
+    pub fn insert(&mut self, offset: u64, data: &[u8]) {
+        let end = offset + data.len() as u64;
+        if data.is_empty() || end <= self.data.len() as u64 { return; }
+        let keep = self.pending.get(&offset).map(|p| p.len()).unwrap_or(0);
+        if keep < data.len() {
+            self.pending.insert(offset, data.to_vec());
+        }
+        while let Some((&off, _)) = self.pending.iter().next() {
+            let have = self.data.len() as u64;
+            if off > have { break; }
+            let frag = self.pending.remove(&off).unwrap_or_default();
+            let skip = (have - off) as usize;
+            if skip < frag.len() {
+                self.data.extend_from_slice(&frag[skip..]);
             }
         }
-        pos += 1;
     }
-    acc
+
+    /// Contiguous bytes received so far, starting at offset 0.
+    pub fn contiguous(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_and_crypto_reassembly() {
+        // PADDING x3, PING, ACK con un rango extra, CRYPTO(off 5), STREAM(off, len, fin), CRYPTO(off 0)
+        let mut payload = vec![0, 0, 0, 0x01, 0x02, 0x0a, 0x00, 0x01, 0x02, 0x01, 0x03];
+        payload.extend_from_slice(&[0x06, 0x05, 0x03]);
+        payload.extend_from_slice(b"def");
+        payload.extend_from_slice(&[0x0f, 0x04, 0x40, 0x10, 0x02]);
+        payload.extend_from_slice(b"hi");
+        payload.extend_from_slice(&[0x06, 0x00, 0x05]);
+        payload.extend_from_slice(b"abcde");
+        let frames = parse_frames(&payload).unwrap();
+        assert_eq!(frames[0], Frame::Padding(3));
+        assert_eq!(frames[1], Frame::Ping);
+        assert_eq!(frames[2], Frame::Ack { largest: 10, delay: 0, first_range: 2, ranges: vec![(1, 3)], ecn: None });
+        assert_eq!(frames[4], Frame::Stream { stream_id: 4, offset: 16, fin: true, data: b"hi".to_vec() });
+        assert!(parse_frames(&[0x01, 0x21]).is_err());
+
+        let mut stream = CryptoStream::default();
+        for f in &frames {
+            if let Frame::Crypto { offset, data } = f { stream.insert(*offset, data); }
+        }
+        stream.insert(2, b"cdef"); // solape ya cubierto
+        assert_eq!(stream.contiguous(), b"abcdedef");
+
+        // Initial (token vacío, Length = 3) + Handshake coalescidos, seguidos de un paquete corto
+        let mut dg = vec![0xc0, 0, 0, 0, 1, 1, 0xaa, 0, 0, 3, 1, 2, 3];
+        dg.extend_from_slice(&[0xe0, 0, 0, 0, 1, 0, 0, 2, 9, 9]);
+        dg.extend_from_slice(&[0x40, 7, 7]);
+        let types: Vec<_> = split_coalesced(&dg).into_iter().map(|(t, p)| (t, p.len())).collect();
+        assert_eq!(types, vec![(PacketType::Initial, 13), (PacketType::Handshake, 10), (PacketType::Short, 3)]);
+    }
 }
@@ -1,78 +1,180 @@
-use hkdf::Hkdf;
-use sha2::Sha256;
-use aes_gcm::aead::{Aead, KeyInit, OsRng};
-use aes_gcm::{Aes128Gcm, Aes256Gcm, Nonce};
-use sha2::Digest;
-
-// Simplified QUIC initial decryption flow (IETF QUIC v1)
-// - Derive initial secrets from DCID and version using Hkdf with salt (per QUIC v1)
-// - Use derived key/iv to decrypt AEAD (CRYPTO) payload
-
-// These constants/salts are as per drafts (example for v1):
-const INITIAL_SALT_V1: &[u8] = &[
-    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3,
-    0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
-    0xcc, 0xbb, 0x7f, 0x0a,
-];
-
-fn hkdf_extract_expand(salt: &[u8], ikm: &[u8], info: &[u8], out_len: usize) -> Result<Vec<u8>, String> {
-    let hk = Hkdf::<Sha256>::new(Some(salt), ikm);
-    let mut okm = vec![0u8; out_len];
-    hk.expand(info, &mut okm).map_err(|_| "hkdf expand error".to_string())?;
-    Ok(okm)
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+
+use crate::quic::read_varint;
+
+// Descifrado de paquetes Initial de QUIC (RFC 9001 §5, RFC 9369 §3.3). La derivación de claves y
+// la máscara de cabecera se comparten con el encoder de hello_template::quic_initial.
+
+pub use hello_template::quic_initial::{initial_keys, header_protection_mask, Direction, InitialKeys};
+use hello_template::quic_initial::initial_type_bits;
+
+/// An Initial packet with header protection removed and its payload decrypted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InitialPacket {
+    pub version: u32,
+    pub dcid: Vec<u8>,
+    pub scid: Vec<u8>,
+    pub token: Vec<u8>,
+    pub packet_number: u64,
+    pub payload: Vec<u8>, // frames en claro
 }
 
-// Derive initial secret using salt and DCID
-fn initial_secret(dcid: &[u8]) -> Result<Vec<u8>, String> {
-    // per spec: initial_secret = HKDF-Extract(salt, client_dst_connection_id)
-    let hk = Hkdf::<Sha256>::new(Some(INITIAL_SALT_V1), dcid);
-    let mut prk = [0u8; 32];
-    // expand with empty info to get 32 bytes
-    hk.expand(&[], &mut prk).map_err(|_| "hkdf extract expand failed".to_string())?;
-    Ok(prk.to_vec())
+/// Reconstructs a full packet number from its truncated encoding (RFC 9000 Appendix A.3).
+pub fn decode_packet_number(largest_pn: Option<u64>, truncated: u64, pn_nbits: u32) -> u64 {
+    let expected = largest_pn.map(|l| l + 1).unwrap_or(0);
+    let win = 1u64 << pn_nbits;
+    let hwin = win / 2;
+    let mask = win - 1;
+    let candidate = (expected & !mask) | truncated;
+    if candidate + hwin <= expected && candidate < (1u64 << 62) - win {
+        candidate + win
+    } else if candidate > expected + hwin && candidate >= win {
+        candidate - win
+    } else {
+        candidate
+    }
 }
 
-// derive key/iv using HKDF-Expand-Label syntax
-fn hkdf_expand_label(secret: &[u8], label: &str, out_len: usize) -> Result<Vec<u8>, String> {
-    // info = length || "tls13 " + label || 0
-    let mut info: Vec<u8> = Vec::new();
-    info.push(((out_len >> 8) & 0xff) as u8);
-    info.push((out_len & 0xff) as u8);
-    let full_label = format!("tls13 {}", label);
-    info.push(full_label.len() as u8);
-    info.extend_from_slice(full_label.as_bytes());
-    info.push(0u8);
-    hkdf_extract_expand(&[], secret, &info, out_len)
+/// Long-header fields read before header protection is removed.
+struct LongHeader {
+    version: u32,
+    dcid: Vec<u8>,
+    scid: Vec<u8>,
+    token: Vec<u8>,
+    pn_offset: usize,
+    end: usize, // fin del paquete dentro del datagrama (campo Length)
 }
 
-// High-level function: given DCID and CRYPTO ciphertext+aad+nonce info, try to decrypt using derived key
-// For simplification, we assume AEAD AES-128-GCM.
-
-pub fn decrypt_initial_crypto(dcid: &[u8], aead_key_len: usize, key_info: &[u8], ciphertext: &[u8], nonce: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
-    // Derive initial secret (PRK)
-    let prk = initial_secret(dcid)?;
-    // Derive key using hkdf_expand_label
-    let key = hkdf_expand_label(&prk, "quic key", aead_key_len)?;
-    let iv = hkdf_expand_label(&prk, "quic iv", 12)?; // 96-bit nonce
-
-    // XOR nonce with iv to produce actual AEAD nonce -> here we receive nonce from packet header and XOR
-    if nonce.len() != iv.len() { return Err("nonce/iv len mismatch".into()); }
-    let mut real_nonce = vec![0u8; iv.len()];
-    for i in 0..iv.len() { real_nonce[i] = iv[i] ^ nonce[i]; }
-
-    match aead_key_len {
-        16 => {
-            let cipher = Aes128Gcm::new_from_slice(&key).map_err(|e| format!("cipher init: {:?}", e))?;
-            let nonce = Nonce::from_slice(&real_nonce);
-            let pt = cipher.decrypt(nonce, aes_gcm::aead::Payload { msg: ciphertext, aad }).map_err(|_| "decrypt failed".to_string())?;
-            Ok(pt)
-        }
-        32 => {
-            let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| format!("cipher init: {:?}", e))?;
-            let nonce = Nonce::from_slice(&real_nonce);
-            let pt = cipher.decrypt(nonce, aes_gcm::aead::Payload { msg: ciphertext, aad }).map_err(|_| "decrypt failed".to_string())?;
-            Ok(pt)
+fn parse_long_header(buf: &[u8]) -> Result<LongHeader, String> {
+    let first = *buf.first().ok_or("empty packet")?;
+    if first & 0x80 == 0 { return Err("not a long header packet".into()); }
+    let version = u32::from_be_bytes(buf.get(1..5).ok_or("truncated version")?.try_into().unwrap());
+    if (first >> 4) & 0x03 != initial_type_bits(version) { return Err("not an Initial packet".into()); }
+    let mut idx = 5;
+    let dcid_len = *buf.get(idx).ok_or("truncated dcid")? as usize; idx += 1;
+    let dcid = buf.get(idx..idx + dcid_len).ok_or("truncated dcid")?.to_vec(); idx += dcid_len;
+    let scid_len = *buf.get(idx).ok_or("truncated scid")? as usize; idx += 1;
+    let scid = buf.get(idx..idx + scid_len).ok_or("truncated scid")?.to_vec(); idx += scid_len;
+    let (tok_len, n) = read_varint(buf, idx).ok_or("truncated token length")?; idx += n;
+    let token = buf.get(idx..idx + tok_len as usize).ok_or("truncated token")?.to_vec(); idx += tok_len as usize;
+    let (length, n) = read_varint(buf, idx).ok_or("truncated length")?; idx += n;
+    let end = idx + length as usize;
+    if end > buf.len() { return Err("packet length exceeds datagram".into()); }
+    Ok(LongHeader { version, dcid, scid, token, pn_offset: idx, end })
+}
+
+/// Removes header protection from and decrypts the Initial packet at the start of `datagram`.
+///
+/// Both directions are keyed by the DCID of the client's first Initial (`client_dcid`), or of its
+/// first Initial after a Retry; the client keeps those keys after switching to the server's
+/// connection ID (RFC 9001 §5.2). Without `client_dcid`, a client packet is keyed by its own
+/// DCID. `largest_pn` is the largest packet number already seen in that direction.
+/// Returns the packet and the number of bytes it occupies, so coalesced packets can follow.
+pub fn decrypt_initial_packet(datagram: &[u8], direction: Direction, client_dcid: Option<&[u8]>, largest_pn: Option<u64>) -> Result<(InitialPacket, usize), String> {
+    let hdr = parse_long_header(datagram)?;
+    let dcid_for_keys = match direction {
+        Direction::Client => client_dcid.unwrap_or(&hdr.dcid),
+        Direction::Server => client_dcid.ok_or("server Initial needs the client's DCID")?,
+    };
+    let keys = initial_keys(hdr.version, dcid_for_keys, direction)?;
+
+    // muestra de 16 bytes que empieza 4 bytes después del inicio del packet number
+    let sample = datagram.get(hdr.pn_offset + 4..hdr.pn_offset + 20).ok_or("packet too short for hp sample")?;
+    let mask = header_protection_mask(&keys.hp, sample)?;
+    let mut header = datagram[..hdr.pn_offset].to_vec();
+    header[0] ^= mask[0] & 0x0f;
+    let pn_len = (header[0] & 0x03) as usize + 1;
+    if hdr.pn_offset + pn_len > hdr.end { return Err("packet number truncated".into()); }
+    let mut truncated = 0u64;
+    for i in 0..pn_len {
+        let b = datagram[hdr.pn_offset + i] ^ mask[1 + i];
+        header.push(b);
+        truncated = (truncated << 8) | b as u64;
+    }
+    let packet_number = decode_packet_number(largest_pn, truncated, (pn_len * 8) as u32);
+
+    let cipher = Aes128Gcm::new_from_slice(&keys.key).map_err(|e| format!("cipher init: {:?}", e))?;
+    let nonce = keys.nonce(packet_number);
+    let payload = cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &datagram[hdr.pn_offset + pn_len..hdr.end], aad: &header })
+        .map_err(|_| "Initial packet decrypt failed".to_string())?;
+
+    let pkt = InitialPacket { version: hdr.version, dcid: hdr.dcid, scid: hdr.scid, token: hdr.token, packet_number, payload };
+    Ok((pkt, hdr.end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic::{QUIC_V1, QUIC_V2};
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_rfc9001_initial_keys_and_server_packet() {
+        // RFC 9001 Appendix A.1
+        let dcid = hex("8394c8f03e515708");
+        let client = initial_keys(QUIC_V1, &dcid, Direction::Client).unwrap();
+        assert_eq!(client.key.to_vec(), hex("1f369613dd76d5467730efcbe3b1a22d"));
+        assert_eq!(client.iv.to_vec(), hex("fa044b2f42a3fd3b46fb255c"));
+        assert_eq!(client.hp.to_vec(), hex("9f50449e04a0e810283a1e9933adedd2"));
+        let server = initial_keys(QUIC_V1, &dcid, Direction::Server).unwrap();
+        assert_eq!(server.key.to_vec(), hex("cf3a5331653c364c88f0f379b6067e37"));
+        assert_eq!(server.hp.to_vec(), hex("c206b8d9b9f0f37644430b490eeaa314"));
+
+        // RFC 9369 Appendix A.1 (QUIC v2)
+        let v2 = initial_keys(QUIC_V2, &dcid, Direction::Client).unwrap();
+        assert_eq!(v2.key.to_vec(), hex("8b1a0bc121284290a29e0971b5cd045d"));
+
+        // RFC 9001 Appendix A.3: Initial del servidor
+        let packet = hex("cf000000010008f067a5502a4262b5004075c0d95a482cd0991cd25b0aac406a
+            5816b6394100f37a1c69797554780bb38cc5a99f5ede4cf73c3ec2493a1839b3
+            dbcba3f6ea46c5b7684df3548e7ddeb9c3bf9c73cc3f3bded74b562bfb19fb84
+            022f8ef4cdd93795d77d06edbb7aaf2f58891850abbdca3d20398c276456cbc4
+            2158407dd074ee");
+        let (pkt, used) = decrypt_initial_packet(&packet, Direction::Server, Some(&dcid), None).unwrap();
+        assert_eq!(used, packet.len());
+        assert_eq!(pkt.packet_number, 1);
+        assert_eq!(pkt.scid, hex("f067a5502a4262b5"));
+        assert_eq!(pkt.payload, hex("02000000000600405a020000560303eefce7f7b37ba1d1632e96677825ddf739
+            88cfc79825df566dc5430b9a045a1200130100002e00330024001d00209d3c94
+            0d89690b84d08a60993c144eca684d1081287c834d5311bcf32bb9da1a002b00
+            020304"));
+        assert!(decrypt_initial_packet(&packet, Direction::Server, Some(&dcid[..4]), None).is_err());
+        let frames = crate::quic::parse_frames(&pkt.payload).unwrap();
+        assert!(matches!(&frames[1], crate::quic::Frame::Crypto { offset: 0, data } if data.len() == 90 && data[0] == 0x02));
+
+        assert_eq!(decode_packet_number(Some(0xa82f30ea), 0x9b32, 16), 0xa82f9b32);
+    }
+
+    #[test]
+    fn test_encoder_round_trip_split_reordered() {
+        use hello_template::quic_initial::{encode_quic_initials, QuicFrameSpec, QuicInitialOptions};
+        use crate::quic::{parse_frames, CryptoStream, Frame};
+        // ClientHello de 1800 bytes (tamaño típico con ML-KEM) partido y desordenado en 2 paquetes
+        let mut ch = vec![0x01, 0x00, 0x07, 0x04];
+        ch.extend((0..1796u32).map(|i| (i * 7) as u8));
+        let mut opts = QuicInitialOptions::new(hex("0001020304050607"), hex("c0ffee"));
+        opts.token = vec![9; 5];
+        opts.pn_len = 2;
+        opts.packets = vec![
+            vec![QuicFrameSpec::Crypto { offset: 900, len: 900 }, QuicFrameSpec::Ping, QuicFrameSpec::Padding { len: 7 }],
+            vec![QuicFrameSpec::Crypto { offset: 0, len: 400 }, QuicFrameSpec::Crypto { offset: 400, len: 500 }],
+        ];
+        let dgs = encode_quic_initials(&ch, &opts).unwrap();
+        let mut stream = CryptoStream::default();
+        for (i, dg) in dgs.iter().enumerate() {
+            assert_eq!(dg.len(), 1200);
+            let (pkt, used) = decrypt_initial_packet(dg, Direction::Client, None, i.checked_sub(1).map(|p| p as u64)).unwrap();
+            assert_eq!((used, pkt.packet_number, &pkt.token[..]), (1200, i as u64, &[9u8; 5][..]));
+            for f in parse_frames(&pkt.payload).unwrap() {
+                if let Frame::Crypto { offset, data } = f { stream.insert(offset, &data); }
+            }
         }
-        _ => Err("unsupported key length".into())
+        assert_eq!(stream.contiguous(), &ch[..]);
     }
 }
@@ -0,0 +1,160 @@
+// ServerHello parser + huellas de servidor JA3S / JA4S.
+//
+// JA3S = md5("SSLVersion,Cipher,Extensions") en decimal (https://github.com/salesforce/ja3).
+// JA4S = "t130200_1301_<sha256(extensiones en orden)>" (https://github.com/FoxIO-LLC/ja4).
+
+use md5::{Md5, Digest};
+use crate::ja4::{ja4_alpn, ja4_version, sha256_12, Ja4Transport};
+
+pub struct ParsedServerHello {
+    pub version: u16,                 // legacy_version
+    pub random: [u8; 32],
+    pub session_id: Vec<u8>,
+    pub cipher_suite: u16,
+    pub compression_method: u8,
+    pub extensions: Vec<(u16, Vec<u8>)>, // orden visto
+}
+
+/// SHA-256("HelloRetryRequest"): valor fijo del random en un HRR (RFC 8446 §4.1.3).
+pub const HRR_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+impl ParsedServerHello {
+    /// Versión negociada: supported_versions (0x002b) si existe, si no legacy_version.
+    pub fn negotiated_version(&self) -> u16 {
+        self.extensions.iter()
+            .find(|(t, d)| *t == 0x002b && d.len() == 2)
+            .map(|(_, d)| u16::from_be_bytes([d[0], d[1]]))
+            .unwrap_or(self.version)
+    }
+
+    /// Protocolo ALPN elegido por el servidor, si lo hay.
+    pub fn alpn(&self) -> Option<Vec<u8>> {
+        let (_, d) = self.extensions.iter().find(|(t, _)| *t == 0x0010)?;
+        // u16 list len + u8 name len + name
+        let n = *d.get(2)? as usize;
+        d.get(3..3 + n).map(|p| p.to_vec())
+    }
+
+    pub fn is_hello_retry_request(&self) -> bool {
+        self.random == HRR_RANDOM
+    }
+}
+
+fn be16(b: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*b.get(i)?, *b.get(i + 1)?]))
+}
+
+/// Parses a ServerHello given as a TLS record or as a bare handshake message (QUIC CRYPTO).
+pub fn parse_server_hello(raw: &[u8]) -> Result<ParsedServerHello, String> {
+    let hs = match raw.first() {
+        Some(0x16) => raw.get(5..).ok_or("too short for record")?,
+        Some(0x02) => raw,
+        _ => return Err("not a ServerHello record or message".into()),
+    };
+    if hs.len() < 4 { return Err("handshake truncated".into()); }
+    if hs[0] != 0x02 { return Err("not a ServerHello handshake".into()); }
+    let body_len = ((hs[1] as usize) << 16) | ((hs[2] as usize) << 8) | hs[3] as usize;
+    let body = hs.get(4..4 + body_len).ok_or("ServerHello body truncated")?;
+
+    let version = be16(body, 0).ok_or("no legacy_version")?;
+    let mut random = [0u8; 32];
+    random.copy_from_slice(body.get(2..34).ok_or("random truncated")?);
+    let mut idx = 34;
+    let sid_len = *body.get(idx).ok_or("no session id")? as usize; idx += 1;
+    let session_id = body.get(idx..idx + sid_len).ok_or("session id truncated")?.to_vec(); idx += sid_len;
+    let cipher_suite = be16(body, idx).ok_or("no cipher suite")?; idx += 2;
+    let compression_method = *body.get(idx).ok_or("no compression method")?; idx += 1;
+
+    let mut extensions = Vec::new();
+    if let Some(exts_len) = be16(body, idx) {
+        idx += 2;
+        let end = idx + exts_len as usize;
+        if end > body.len() { return Err("extensions truncated".into()); }
+        while idx + 4 <= end {
+            let typ = be16(body, idx).ok_or("extension truncated")?;
+            let len = be16(body, idx + 2).ok_or("extension truncated")? as usize;
+            let data = body.get(idx + 4..idx + 4 + len).filter(|_| idx + 4 + len <= end).ok_or("extension data truncated")?;
+            extensions.push((typ, data.to_vec()));
+            idx += 4 + len;
+        }
+    }
+    Ok(ParsedServerHello { version, random, session_id, cipher_suite, compression_method, extensions })
+}
+
+/// JA3S string sin hashear: "SSLVersion,Cipher,SSLExtension".
+pub fn ja3s_string(sh: &ParsedServerHello) -> String {
+    let exts = sh.extensions.iter().map(|(t, _)| t.to_string()).collect::<Vec<_>>().join("-");
+    format!("{},{},{}", sh.version, sh.cipher_suite, exts)
+}
+
+pub fn ja3s_from_raw(raw: &[u8]) -> Result<String, String> {
+    let sh = parse_server_hello(raw)?;
+    let mut hasher = Md5::new();
+    hasher.input(ja3s_string(&sh).as_bytes());
+    Ok(format!("{:x}", hasher.result()))
+}
+
+fn ja4s_parts(sh: &ParsedServerHello, transport: Ja4Transport) -> (String, String, String) {
+    let a = format!(
+        "{}{}{:02}{}",
+        transport.prefix(),
+        ja4_version(sh.negotiated_version()),
+        sh.extensions.len().min(99),
+        ja4_alpn(sh.alpn().as_deref()),
+    );
+    let b = format!("{:04x}", sh.cipher_suite);
+    let c = sh.extensions.iter().map(|(t, _)| format!("{:04x}", t)).collect::<Vec<_>>().join(",");
+    (a, b, c)
+}
+
+/// JA4S: `t130200_1301_<sha256(extensiones en orden original)[..12]>`.
+pub fn ja4s(raw: &[u8], transport: Ja4Transport) -> Result<String, String> {
+    let sh = parse_server_hello(raw)?;
+    let (a, b, c) = ja4s_parts(&sh, transport);
+    Ok(format!("{}_{}_{}", a, b, sha256_12(&c)))
+}
+
+/// JA4S_r: lista de extensiones sin hashear.
+pub fn ja4s_r(raw: &[u8], transport: Ja4Transport) -> Result<String, String> {
+    let sh = parse_server_hello(raw)?;
+    let (a, b, c) = ja4s_parts(&sh, transport);
+    Ok(format!("{}_{}_{}", a, b, c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ServerHello TLS 1.3: TLS_AES_128_GCM_SHA256, key_share x25519, supported_versions 1.3
+    fn server_hello() -> Vec<u8> {
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x42; 32]);
+        body.push(0);
+        body.extend_from_slice(&[0x13, 0x01, 0x00]);
+        let mut exts = vec![0x00, 0x33, 0x00, 0x24, 0x00, 0x1d, 0x00, 0x20];
+        exts.extend_from_slice(&[0x55; 32]);
+        exts.extend_from_slice(&[0x00, 0x2b, 0x00, 0x02, 0x03, 0x04]);
+        body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        body.extend_from_slice(&exts);
+        let mut rec = vec![0x16, 0x03, 0x03];
+        rec.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+        rec.push(0x02);
+        rec.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        rec.extend_from_slice(&body);
+        rec
+    }
+
+    #[test]
+    fn test_ja3s_ja4s() {
+        let raw = server_hello();
+        let sh = parse_server_hello(&raw).unwrap();
+        assert_eq!(sh.negotiated_version(), 0x0304);
+        assert_eq!(ja3s_string(&sh), "771,4865,51-43");
+        assert_eq!(ja4s_r(&raw, Ja4Transport::Tcp).unwrap(), "t130200_1301_0033,002b");
+        assert!(ja4s(&raw, Ja4Transport::Tcp).unwrap().starts_with("t130200_1301_"));
+        assert_eq!(ja3s_from_raw(&raw).unwrap().len(), 32);
+    }
+}
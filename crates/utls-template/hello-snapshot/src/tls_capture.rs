@@ -1,11 +1,38 @@
-use std::net::TcpStream;
-use std::time::SystemTime;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, SystemTime};
 
+use serde::{Serialize, Deserialize};
+use hello_template::decode::decode_client_hello;
+use hello_template::pcap_writer::{tcp_pcap, Direction, PcapOptions};
+use hello_template::{is_grease, Encoder, Extension, HelloTemplate};
+
+use crate::ja4::{self, Ja4Transport};
+use crate::hrr::{build_hello_retry_request, diff_client_hellos, ClientHelloDiff, HrrOptions};
+
+// Captura en vivo del primer vuelo TLS: un listener local al que se apunta el navegador, que lee
+// el ClientHello (fragmentado o no en varios records), opcionalmente responde con un
+// HelloRetryRequest (crate::hrr) para obtener el segundo ClientHello y cierra con un alert.
+
+const RECORD_CHANGE_CIPHER_SPEC: u8 = 0x14;
+const RECORD_ALERT: u8 = 0x15;
+const RECORD_HANDSHAKE: u8 = 0x16;
+const MAX_FLIGHT: usize = 64 * 1024; // un ClientHello real no pasa de unos pocos KB
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+// alert fatal handshake_failure: el navegador muestra un error en lugar de quedarse colgado
+const HANDSHAKE_FAILURE_ALERT: [u8; 7] = [RECORD_ALERT, 0x03, 0x03, 0x00, 0x02, 0x02, 0x28];
+const CHANGE_CIPHER_SPEC: [u8; 6] = [RECORD_CHANGE_CIPHER_SPEC, 0x03, 0x03, 0x00, 0x01, 0x01];
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct CaptureOptions {
     pub allow_hrr: bool, // Si es true, captura HRR + segundo CH
     pub emit_pcap: bool, // Si es true, guarda en formato PCAP
+    #[serde(default)]
+    pub hrr: HrrOptions, // grupo / cookie que pide el HRR
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TlsSnapshot {
     pub cipher_suites: Vec<u16>,
     pub extensions: Vec<ExtView>,    // orden vista
@@ -13,91 +40,282 @@ pub struct TlsSnapshot {
     pub sig_algs: Vec<u16>,
     pub keyshare_groups: Vec<u16>,
     pub alpn: Vec<String>,
+    pub sni: Option<String>,
     pub has_grease: bool,
-    pub raw_bytes: Vec<u8>, // Añadir raw_bytes para facilitar la escritura en pcap
-    // ...
+    pub ja3: Option<String>,
+    pub ja4: Option<String>,
+    pub template: HelloTemplate,  // ClientHello decodificado, reutilizable como plantilla
+    pub raw_bytes: Vec<u8>, // records tal y como llegaron (para escribir el pcap)
 }
 
-pub struct ExtView { /* ... */ } // Placeholder for ExtView
+/// One extension as seen on the wire.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExtView {
+    pub typ: u16,
+    pub name: String,
+    pub data: Vec<u8>, // cuerpo de la extensión, sin tipo ni longitud
+    pub grease: bool,
+}
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CaptureResult {
     pub initial_client_hello: TlsSnapshot,
     pub hrr_response: Option<Vec<u8>>, // Bytes del HRR si se recibió
     pub second_client_hello: Option<TlsSnapshot>, // Segundo CH si se recibió HRR
+    pub hrr_diff: Option<ClientHelloDiff>, // cambios del segundo CH respecto al primero
     pub pcap_bytes: Option<Vec<u8>>, // Bytes del PCAP si emit_pcap es true
 }
 
-fn write_u32_le(buf: &mut Vec<u8>, v: u32) {
-    buf.extend_from_slice(&v.to_le_bytes());
+/// IANA name of a TLS extension type, `unknown` for unassigned codepoints.
+pub fn extension_name(typ: u16) -> &'static str {
+    match typ {
+        0x0000 => "server_name",
+        0x0005 => "status_request",
+        0x000a => "supported_groups",
+        0x000b => "ec_point_formats",
+        0x000d => "signature_algorithms",
+        0x0010 => "application_layer_protocol_negotiation",
+        0x0012 => "signed_certificate_timestamp",
+        0x0015 => "padding",
+        0x0016 => "encrypt_then_mac",
+        0x0017 => "extended_master_secret",
+        0x001b => "compress_certificate",
+        0x001c => "record_size_limit",
+        0x0022 => "delegated_credentials",
+        0x0023 => "session_ticket",
+        0x0029 => "pre_shared_key",
+        0x002a => "early_data",
+        0x002b => "supported_versions",
+        0x002c => "cookie",
+        0x002d => "psk_key_exchange_modes",
+        0x0031 => "post_handshake_auth",
+        0x0033 => "key_share",
+        0x0039 => "quic_transport_parameters",
+        0x4469 | 0x44cd => "application_settings",
+        0xfe0d => "encrypted_client_hello",
+        0xff01 => "renegotiation_info",
+        t if is_grease(t) => "GREASE",
+        _ => "unknown",
+    }
 }
-fn write_u16_le(buf: &mut Vec<u8>, v: u16) {
-    buf.extend_from_slice(&v.to_le_bytes());
+
+impl TlsSnapshot {
+    /// Builds a snapshot from the ClientHello records (or bare handshake message) in `raw`.
+    pub fn from_raw(raw: &[u8]) -> Result<Self, String> {
+        let template = decode_client_hello(raw)?;
+        let mut snap = TlsSnapshot {
+            cipher_suites: template.cipher_suites.clone(),
+            extensions: Vec::with_capacity(template.extensions.len()),
+            supported_groups: Vec::new(),
+            sig_algs: Vec::new(),
+            keyshare_groups: Vec::new(),
+            alpn: Vec::new(),
+            sni: None,
+            has_grease: template.cipher_suites.iter().any(|c| is_grease(*c)),
+            ja3: crate::ja3::ja3_from_raw(raw).ok(),
+            ja4: None,
+            template: template.clone(),
+            raw_bytes: raw.to_vec(),
+        };
+        let transport = if raw.first() == Some(&RECORD_HANDSHAKE) { Ja4Transport::Tcp } else { Ja4Transport::Quic };
+        snap.ja4 = ja4::ja4(raw, transport).ok();
+
+        for ext in &template.extensions {
+            // el decoder garantiza que volver a codificar reproduce los bytes originales
+            let mut wire = Vec::new();
+            Encoder::encode_extension(ext, &mut wire);
+            let typ = ext.ext_type();
+            snap.has_grease |= is_grease(typ);
+            snap.extensions.push(ExtView { typ, name: extension_name(typ).to_string(), data: wire[4..].to_vec(), grease: is_grease(typ) });
+            match ext {
+                Extension::ServerName { host } => snap.sni = Some(host.clone()),
+                Extension::SupportedGroups { groups, .. } => snap.supported_groups = groups.clone(),
+                Extension::SignatureAlgorithms { algs, .. } => snap.sig_algs = algs.clone(),
+                Extension::KeyShare { shares, .. } => snap.keyshare_groups = shares.iter().map(|(g, _)| *g).collect(),
+                Extension::Alpn { protocols } => snap.alpn = protocols.clone(),
+                _ => {}
+            }
+        }
+        snap.has_grease |= snap.supported_groups.iter().chain(&snap.keyshare_groups).any(|g| is_grease(*g));
+        Ok(snap)
+    }
 }
 
-pub fn capture_client_hello(_stream: &mut TcpStream, opt: &CaptureOptions) -> Result<CaptureResult, String> {
-    // Placeholder para la lógica de captura real
-    // En un caso real, aquí se leerían los bytes del stream TCP.
-    let dummy_initial_ch_bytes = vec![0x16, 0x03, 0x01, 0x00, 0x01]; // Ejemplo muy simplificado
-    let initial_client_hello = TlsSnapshot {
-        cipher_suites: vec![], extensions: vec![], supported_groups: vec![],
-        sig_algs: vec![], keyshare_groups: vec![], alpn: vec![],
-        has_grease: false, raw_bytes: dummy_initial_ch_bytes.clone(),
-    };
+// Un record completo (cabecera incluida).
+fn read_record(stream: &mut impl Read) -> Result<Vec<u8>, String> {
+    let mut record = vec![0u8; 5];
+    stream.read_exact(&mut record).map_err(|e| format!("reading record header: {}", e))?;
+    let len = u16::from_be_bytes([record[3], record[4]]) as usize;
+    if len > 16384 + 2048 { return Err(format!("record too large ({} bytes)", len)); }
+    record.resize(5 + len, 0);
+    stream.read_exact(&mut record[5..]).map_err(|e| format!("reading record body: {}", e))?;
+    Ok(record)
+}
+
+/// Reads records until a complete ClientHello has arrived and returns the handshake records as
+/// they came off the wire. ChangeCipherSpec records (middlebox compatibility, RFC 8446 §D.4)
+/// are skipped.
+pub fn read_client_hello_flight(stream: &mut impl Read) -> Result<Vec<u8>, String> {
+    let mut flight = Vec::new();
+    let mut handshake = Vec::new(); // mensaje de handshake reensamblado, para saber cuándo está completo
+    loop {
+        let record = read_record(stream)?;
+        match record[0] {
+            RECORD_CHANGE_CIPHER_SPEC => continue,
+            RECORD_ALERT => return Err(format!("client sent alert {:?}", &record[5..])),
+            RECORD_HANDSHAKE => {}
+            t => return Err(format!("not a TLS handshake (record type {:#04x})", t)),
+        }
+        handshake.extend_from_slice(&record[5..]);
+        flight.extend_from_slice(&record);
+        if handshake.first().is_some_and(|t| *t != 0x01) { return Err("first handshake message is not a ClientHello".into()); }
+        if flight.len() > MAX_FLIGHT { return Err("ClientHello flight too large".into()); }
+        if handshake.len() >= 4 {
+            let need = 4 + (((handshake[1] as usize) << 16) | ((handshake[2] as usize) << 8) | handshake[3] as usize);
+            if handshake.len() >= need { return Ok(flight); }
+        }
+    }
+}
+
+/// Captures the first flight of a TLS client connected on `stream` and, with `allow_hrr`, the
+/// second ClientHello sent after a HelloRetryRequest built from `opt.hrr`, plus the diff between
+/// both. The connection is closed with an alert.
+pub fn capture_client_hello(stream: &mut TcpStream, opt: &CaptureOptions) -> Result<CaptureResult, String> {
+    stream.set_read_timeout(Some(READ_TIMEOUT)).map_err(|e| format!("set timeout: {}", e))?;
+    // antes de leer: el cliente puede cerrar en cuanto recibe el alert
+    let peer = stream.peer_addr().map_err(|e| format!("peer addr: {}", e))?;
+    let local = stream.local_addr().map_err(|e| format!("local addr: {}", e))?;
+    let initial_client_hello = TlsSnapshot::from_raw(&read_client_hello_flight(stream)?)?;
 
     let mut hrr_response: Option<Vec<u8>> = None;
     let mut second_client_hello: Option<TlsSnapshot> = None;
+    let mut hrr_diff: Option<ClientHelloDiff> = None;
 
-    // Simulación de HRR si allow_hrr es true
     if opt.allow_hrr {
-        hrr_response = Some(vec![0x16, 0x03, 0x04, 0x00, 0x02]); // Dummy HRR
-        second_client_hello = Some(TlsSnapshot {
-            cipher_suites: vec![], extensions: vec![], supported_groups: vec![],
-            sig_algs: vec![], keyshare_groups: vec![], alpn: vec![],
-            has_grease: false, raw_bytes: vec![0x16, 0x03, 0x01, 0x00, 0x03],
-        }); // Dummy second CH
+        let mut hrr = build_hello_retry_request(&initial_client_hello.template, &opt.hrr)?;
+        // modo de compatibilidad: con session_id no vacío el servidor manda CCS tras el HRR
+        if initial_client_hello.template.session_id.is_some() { hrr.extend_from_slice(&CHANGE_CIPHER_SPEC); }
+        stream.write_all(&hrr).map_err(|e| format!("sending HRR: {}", e))?;
+        let second = TlsSnapshot::from_raw(&read_client_hello_flight(stream)?)?;
+        hrr_diff = Some(diff_client_hellos(&initial_client_hello.template, &second.template, opt.hrr.cookie.as_deref()));
+        second_client_hello = Some(second);
+        hrr_response = Some(hrr);
     }
+    let _ = stream.write_all(&HANDSHAKE_FAILURE_ALERT);
 
-    let mut pcap_bytes: Option<Vec<u8>> = None;
-
-    if opt.emit_pcap {
-        let mut pcap: Vec<u8> = Vec::new();
-        // pcap global header (little-endian: 0xd4c3b2a1)
-        write_u32_le(&mut pcap, 0xd4c3b2a1);
-        write_u16_le(&mut pcap, 2); // version major
-        write_u16_le(&mut pcap, 4); // version minor
-        write_u32_le(&mut pcap, 0); // thiszone
-        write_u32_le(&mut pcap, 0); // sigfigs
-        write_u32_le(&mut pcap, 65535); // snaplen
-        write_u32_le(&mut pcap, 1); // network (LINKTYPE_ETHERNET)
-
+    let pcap_bytes = if opt.emit_pcap {
+        // la conversación tal cual, sobre TCP con las direcciones reales y la hora de la captura
         let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_err(|e| format!("ts failed: {}", e))?;
-        let sec = now.as_secs() as u32;
-        let usec = now.subsec_micros() as u32;
-
-        // helper to append a packet record
-        let mut append_packet = |pkt: &Vec<u8>| {
-            write_u32_le(&mut pcap, sec);
-            write_u32_le(&mut pcap, usec);
-            write_u32_le(&mut pcap, pkt.len() as u32);
-            write_u32_le(&mut pcap, pkt.len() as u32);
-            pcap.extend_from_slice(pkt);
+        let pcap_opts = PcapOptions {
+            client: peer,
+            server: local,
+            start_us: now.as_micros() as u64,
+            ..Default::default()
         };
-
-        append_packet(&initial_client_hello.raw_bytes);
-        if let Some(hrr) = &hrr_response {
-            append_packet(hrr);
-        }
-        if let Some(second) = &second_client_hello {
-            append_packet(&second.raw_bytes);
+        let mut flights: Vec<(Direction, &[u8])> = vec![(Direction::Client, &initial_client_hello.raw_bytes)];
+        if let (Some(hrr), Some(second)) = (&hrr_response, &second_client_hello) {
+            flights.push((Direction::Server, hrr));
+            flights.push((Direction::Client, &second.raw_bytes));
         }
-
-        pcap_bytes = Some(pcap);
-    }
+        flights.push((Direction::Server, &HANDSHAKE_FAILURE_ALERT));
+        Some(tcp_pcap(&pcap_opts, &flights)?)
+    } else {
+        None
+    };
 
     Ok(CaptureResult {
         initial_client_hello,
         hrr_response,
         second_client_hello,
+        hrr_diff,
         pcap_bytes,
     })
 }
+
+/// Accepts connections on `listener` (point a browser at it) and captures each one in turn.
+/// Stops after `max_connections` when given; per-connection errors go to `on_capture` too.
+pub fn serve(listener: &TcpListener, opt: &CaptureOptions, max_connections: Option<usize>, mut on_capture: impl FnMut(SocketAddr, Result<CaptureResult, String>)) -> Result<(), String> {
+    let mut handled = 0;
+    while max_connections.is_none_or(|m| handled < m) {
+        let (mut stream, peer) = listener.accept().map_err(|e| format!("accept failed: {}", e))?;
+        on_capture(peer, capture_client_hello(&mut stream, opt));
+        handled += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chrome_like() -> HelloTemplate {
+        HelloTemplate {
+            record_version: Some(0x0301),
+            client_random: Some([7; 32]),
+            session_id: Some(vec![0x42; 32]),
+            cipher_suites: vec![0x2a2a, 0x1301, 0x1302, 0xc02b],
+            cipher_grease_slots: vec![0],
+            extensions: vec![
+                Extension::Grease { typ: 0x2a2a, bytes: vec![] },
+                Extension::ServerName { host: "capture.test".into() },
+                Extension::SupportedGroups { groups: vec![0x2a2a, 0x001d, 0x0017], grease_slots: vec![0] },
+                Extension::KeyShare { shares: vec![(0x001d, vec![1; 32])], grease_slots: vec![] },
+                Extension::Alpn { protocols: vec!["h2".into(), "http/1.1".into()] },
+                Extension::SupportedVersions { versions: vec![0x0304, 0x0303], grease_slots: vec![] },
+                Extension::ExtendedMasterSecret,
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_live_capture_with_hrr() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut s = TcpStream::connect(addr).unwrap();
+            // primer ClientHello partido en dos records
+            let hs = Encoder::encode_handshake(&chrome_like());
+            for part in [&hs[..100], &hs[100..]] {
+                s.write_all(&[RECORD_HANDSHAKE, 0x03, 0x01]).unwrap();
+                s.write_all(&(part.len() as u16).to_be_bytes()).unwrap();
+                s.write_all(part).unwrap();
+            }
+            let hrr = read_record(&mut s).unwrap();
+            assert_eq!(read_record(&mut s).unwrap(), CHANGE_CIPHER_SPEC);
+            let mut second = chrome_like();
+            second.extensions[3] = Extension::KeyShare { shares: vec![(0x0017, vec![4; 65])], grease_slots: vec![] };
+            s.write_all(&CHANGE_CIPHER_SPEC).unwrap();
+            s.write_all(&Encoder::encode_client_hello(&second, false).unwrap().raw_bytes).unwrap();
+            hrr
+        });
+
+        let mut results = Vec::new();
+        serve(&listener, &CaptureOptions { allow_hrr: true, emit_pcap: true, hrr: HrrOptions::default() }, Some(1), |_, r| results.push(r)).unwrap();
+        let hrr = client.join().unwrap();
+        let res = results.pop().unwrap().unwrap();
+
+        let first = &res.initial_client_hello;
+        assert_eq!(first.sni.as_deref(), Some("capture.test"));
+        assert_eq!(first.keyshare_groups, vec![0x001d]);
+        assert!(first.has_grease);
+        assert_eq!(first.extensions[6].name, "extended_master_secret");
+        assert_eq!((first.template.record_version, &first.template.extensions), (Some(0x0301), &chrome_like().extensions));
+        assert!(first.ja4.as_deref().unwrap().starts_with("t13d"));
+
+        let parsed = crate::server_hello::parse_server_hello(&hrr).unwrap();
+        assert!(parsed.is_hello_retry_request());
+        assert_eq!(parsed.extensions[1], (0x0033, vec![0x00, 0x17]));
+        assert_eq!(res.second_client_hello.unwrap().keyshare_groups, vec![0x0017]);
+        let diff = res.hrr_diff.unwrap();
+        assert_eq!(diff.key_share.unwrap().after, vec![0x0017]);
+        assert!(diff.grease_preserved && !diff.cookie_added);
+
+        // el pcap es una conversación TCP válida que el importer vuelve a leer
+        let pcap = res.pcap_bytes.unwrap();
+        let imported: Vec<_> = crate::pcap_importer::PcapImporter::new(std::io::Cursor::new(pcap)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].raw_client_hello, first.raw_bytes);
+        assert_eq!(imported[0].flow.as_ref().unwrap().key.dst_port, addr.port());
+    }
+}
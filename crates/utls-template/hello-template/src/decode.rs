@@ -0,0 +1,493 @@
+// Decoder inverso del Encoder: bytes de un ClientHello (record TLS o handshake suelto) → HelloTemplate.
+//
+// Cada extensión se decodifica a su variante tipada y se vuelve a codificar; si el resultado no
+// coincide byte a byte con el original se conserva como `Unknown`, de modo que
+// `Encoder::encode_client_hello(&decode_client_hello(x)?, false)?.raw_bytes == x` para cualquier
+// record con un único ClientHello.
+
+use crate::ech::ECH_VERSION;
+use crate::grease::{GreaseMode, GreaseValues};
+use crate::{boring_padding_len, is_grease, AlpsCodepoint, Encoder, Extension, HelloTemplate, PskIdentity};
+use crate::transport_params::decode_transport_params;
+
+/// Cursor mínimo con comprobación de límites sobre un slice.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub(crate) fn bytes(&mut self, n: usize, what: &str) -> Result<&'a [u8], String> {
+        if self.remaining() < n {
+            return Err(format!("{} truncated", what));
+        }
+        let out = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    pub(crate) fn u8(&mut self, what: &str) -> Result<u8, String> {
+        Ok(self.bytes(1, what)?[0])
+    }
+
+    pub(crate) fn u16(&mut self, what: &str) -> Result<u16, String> {
+        let b = self.bytes(2, what)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u24(&mut self, what: &str) -> Result<usize, String> {
+        let b = self.bytes(3, what)?;
+        Ok(((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+
+    /// Reads a vector prefixed by a 1-byte length.
+    pub(crate) fn vec8(&mut self, what: &str) -> Result<&'a [u8], String> {
+        let n = self.u8(what)? as usize;
+        self.bytes(n, what)
+    }
+
+    /// Reads a vector prefixed by a 2-byte length.
+    pub(crate) fn vec16(&mut self, what: &str) -> Result<&'a [u8], String> {
+        let n = self.u16(what)? as usize;
+        self.bytes(n, what)
+    }
+}
+
+fn u16_list(buf: &[u8], what: &str) -> Result<Vec<u16>, String> {
+    if !buf.len().is_multiple_of(2) {
+        return Err(format!("{} has odd length", what));
+    }
+    Ok(buf.chunks(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect())
+}
+
+// GREASE observado por campo. Los campos sin GREASE toman el primer valor visto (no hay slot
+// donde escribirlos). Solo se devuelve PerField si aplicarlo deja los valores del cable tal
+// cual; si no (key_share distinto de supported_groups, tres extensiones GREASE, dos valores
+// distintos en una lista) queda GreaseMode::None y el hello se codifica literal.
+fn observed_grease(t: &HelloTemplate) -> GreaseMode {
+    let first = |list: &[u16], slots: &[u16]| slots.first().and_then(|i| list.get(*i as usize)).copied();
+    let (mut group, mut version, mut sig_alg) = (None, None, None);
+    let mut ext_types = Vec::new();
+    for e in &t.extensions {
+        match e {
+            Extension::SupportedGroups { groups, grease_slots } => group = group.or(first(groups, grease_slots)),
+            Extension::KeyShare { shares, grease_slots } => group = group.or(grease_slots.first().and_then(|i| shares.get(*i as usize)).map(|s| s.0)),
+            Extension::SupportedVersions { versions, grease_slots } => version = first(versions, grease_slots),
+            Extension::SignatureAlgorithms { algs, grease_slots } => sig_alg = first(algs, grease_slots),
+            Extension::Grease { typ, .. } => ext_types.push(*typ),
+            _ => {}
+        }
+    }
+    let cipher = first(&t.cipher_suites, &t.cipher_grease_slots);
+    let (extension1, extension2) = (ext_types.first().copied(), ext_types.get(1).copied());
+    let Some(any) = [cipher, group, extension1, extension2, version, sig_alg].into_iter().flatten().next() else {
+        return GreaseMode::None;
+    };
+    let values = GreaseValues {
+        cipher: cipher.unwrap_or(any),
+        group: group.unwrap_or(any),
+        extension1: extension1.unwrap_or(any),
+        extension2: extension2.unwrap_or(any),
+        version: version.unwrap_or(any),
+        sig_alg: sig_alg.unwrap_or(any),
+    };
+    let mut applied = t.clone();
+    crate::apply_grease(&mut applied, &values);
+    if applied == *t { GreaseMode::PerField(values) } else { GreaseMode::None }
+}
+
+fn grease_slots(list: &[u16]) -> Vec<u16> {
+    list.iter().enumerate().filter(|(_, v)| is_grease(**v)).map(|(i, _)| i as u16).collect()
+}
+
+fn string_list(buf: &[u8], what: &str) -> Result<Vec<String>, String> {
+    let mut r = Reader::new(buf);
+    let mut out = Vec::new();
+    while !r.is_empty() {
+        let p = r.vec8(what)?;
+        out.push(String::from_utf8(p.to_vec()).map_err(|_| format!("{} not utf-8", what))?);
+    }
+    Ok(out)
+}
+
+/// Extracts the handshake message from a TLS record stream (possibly fragmented across several
+/// records) or returns the input as-is when it already is a bare handshake message.
+/// Returns `(record_version, handshake_bytes)`.
+pub fn split_record(raw: &[u8]) -> Result<(Option<u16>, Vec<u8>), String> {
+    match raw.first() {
+        Some(0x16) => {
+            let mut r = Reader::new(raw);
+            let mut handshake: Vec<u8> = Vec::new();
+            let mut version = None;
+            while !r.is_empty() {
+                if r.u8("record type")? != 0x16 { return Err("not a handshake record".into()); }
+                let v = r.u16("record version")?;
+                version.get_or_insert(v);
+                handshake.extend_from_slice(r.vec16("record")?);
+                // stop once the first handshake message is complete
+                if handshake.len() >= 4 {
+                    let need = 4 + (((handshake[1] as usize) << 16) | ((handshake[2] as usize) << 8) | handshake[3] as usize);
+                    if handshake.len() >= need { break; }
+                }
+            }
+            Ok((version, handshake))
+        }
+        Some(0x01) => Ok((None, raw.to_vec())),
+        Some(_) => Err("neither a handshake record nor a ClientHello message".into()),
+        None => Err("empty input".into()),
+    }
+}
+
+/// Decodes a single extension body into its typed variant, falling back to `Unknown`
+/// (or `Grease`) when the body has no typed representation that re-encodes identically.
+pub fn decode_extension(typ: u16, data: &[u8]) -> Extension {
+    let typed = if is_grease(typ) {
+        Ok(Extension::Grease { typ, bytes: data.to_vec() })
+    } else {
+        decode_typed_extension(typ, data)
+    };
+    match typed {
+        Ok(ext) => {
+            let mut check = Vec::with_capacity(data.len() + 4);
+            Encoder::encode_extension(&ext, &mut check);
+            if check.len() == data.len() + 4 && check[4..] == *data && ext.ext_type() == typ {
+                ext
+            } else {
+                Extension::Unknown { typ, bytes: data.to_vec() }
+            }
+        }
+        Err(_) => Extension::Unknown { typ, bytes: data.to_vec() },
+    }
+}
+
+fn decode_typed_extension(typ: u16, data: &[u8]) -> Result<Extension, String> {
+    let mut r = Reader::new(data);
+    let ext = match typ {
+        0x0000 => {
+            let mut list = Reader::new(r.vec16("server_name list")?);
+            if list.u8("name type")? != 0 { return Err("unsupported name type".into()); }
+            let host = list.vec16("host_name")?;
+            if !list.is_empty() { return Err("more than one server name".into()); }
+            let host = String::from_utf8(host.to_vec()).map_err(|_| "host_name not utf-8".to_string())?;
+            Extension::ServerName { host }
+        }
+        0x002b => {
+            let versions = u16_list(r.vec8("supported_versions")?, "supported_versions")?;
+            Extension::SupportedVersions { grease_slots: grease_slots(&versions), versions }
+        }
+        0x000a => {
+            let groups = u16_list(r.vec16("supported_groups")?, "supported_groups")?;
+            Extension::SupportedGroups { grease_slots: grease_slots(&groups), groups }
+        }
+        0x000d => {
+            let algs = u16_list(r.vec16("signature_algorithms")?, "signature_algorithms")?;
+            Extension::SignatureAlgorithms { grease_slots: grease_slots(&algs), algs }
+        }
+        0x0033 => {
+            let mut list = Reader::new(r.vec16("key_share list")?);
+            let mut shares = Vec::new();
+            while !list.is_empty() {
+                let group = list.u16("key_share group")?;
+                let key = list.vec16("key_exchange")?;
+                shares.push((group, key.to_vec()));
+            }
+            let groups: Vec<u16> = shares.iter().map(|(g, _)| *g).collect();
+            Extension::KeyShare { grease_slots: grease_slots(&groups), shares }
+        }
+        0x0010 => {
+            let protocols = string_list(r.vec16("alpn list")?, "alpn protocol")?;
+            Extension::Alpn { protocols }
+        }
+        0x0015 => {
+            if data.iter().any(|b| *b != 0) { return Err("non-zero padding".into()); }
+            return Ok(Extension::Padding { len: data.len() });
+        }
+        AlpsCodepoint::OLD | AlpsCodepoint::NEW => {
+            let protocols = string_list(r.vec16("alps list")?, "alps protocol")?;
+            let codepoint = if typ == AlpsCodepoint::NEW { AlpsCodepoint::New } else { AlpsCodepoint::Old };
+            Extension::ApplicationSettings { protocols, codepoint }
+        }
+        ECH_VERSION => match r.u8("ech type")? {
+            0 => Extension::Ech {
+                kdf_id: r.u16("ech kdf_id")?,
+                aead_id: r.u16("ech aead_id")?,
+                config_id: r.u8("ech config_id")?,
+                enc: r.vec16("ech enc")?.to_vec(),
+                payload: r.vec16("ech payload")?.to_vec(),
+                sealed: false,
+            },
+            1 => Extension::EchInner,
+            t => return Err(format!("unknown ECH type {}", t)),
+        },
+        0x0039 => return Ok(Extension::QuicTransportParameters { params: decode_transport_params(data)? }),
+        0x002d => {
+            let modes = r.vec8("psk_key_exchange_modes")?.to_vec();
+            Extension::PskKeyExchangeModes { modes }
+        }
+        0x0017 | 0x0012 | 0x002a | 0x0031 | 0x0016 => {
+            if !data.is_empty() { return Err("extension body should be empty".into()); }
+            match typ {
+                0x0017 => Extension::ExtendedMasterSecret,
+                0x0012 => Extension::SignedCertificateTimestamp,
+                0x002a => Extension::EarlyData,
+                0x0031 => Extension::PostHandshakeAuth,
+                _ => Extension::EncryptThenMac,
+            }
+        }
+        0xff01 => Extension::RenegotiationInfo { renegotiated_connection: r.vec8("renegotiated_connection")?.to_vec() },
+        0x0005 => Extension::StatusRequest {
+            status_type: r.u8("status_type")?,
+            responder_id_list: r.vec16("responder_id_list")?.to_vec(),
+            request_extensions: r.vec16("request_extensions")?.to_vec(),
+        },
+        0x0023 => return Ok(Extension::SessionTicket { ticket: data.to_vec() }),
+        0x001b => Extension::CompressCertificate { algorithms: u16_list(r.vec8("compress_certificate")?, "compress_certificate")? },
+        0x001c => Extension::RecordSizeLimit { limit: r.u16("record_size_limit")? },
+        0x0022 => Extension::DelegatedCredentials { algs: u16_list(r.vec16("delegated_credentials")?, "delegated_credentials")? },
+        0x0029 => {
+            let mut ids = Reader::new(r.vec16("psk identities")?);
+            let mut identities = Vec::new();
+            while !ids.is_empty() {
+                let identity = ids.vec16("psk identity")?.to_vec();
+                let age = ids.bytes(4, "obfuscated_ticket_age")?;
+                identities.push(PskIdentity { identity, obfuscated_ticket_age: u32::from_be_bytes([age[0], age[1], age[2], age[3]]) });
+            }
+            let mut list = Reader::new(r.vec16("psk binders")?);
+            let mut binders = Vec::new();
+            while !list.is_empty() {
+                binders.push(list.vec8("psk binder")?.to_vec());
+            }
+            Extension::PreSharedKey { identities, binders }
+        }
+        0x002c => Extension::Cookie { cookie: r.vec16("cookie")?.to_vec() },
+        _ => return Ok(Extension::Unknown { typ, bytes: data.to_vec() }),
+    };
+    if !r.is_empty() {
+        return Err("trailing bytes in extension".into());
+    }
+    Ok(ext)
+}
+
+/// Decodes a ClientHello (TLS record or bare handshake message) into a `HelloTemplate`,
+/// preserving the order of cipher suites and extensions and the position of GREASE values.
+pub fn decode_client_hello(raw: &[u8]) -> Result<HelloTemplate, String> {
+    let (record_version, handshake) = split_record(raw)?;
+    let mut hs = Reader::new(&handshake);
+    if hs.u8("handshake type")? != 0x01 { return Err("not a ClientHello handshake".into()); }
+    let body_len = hs.u24("handshake length")?;
+    let mut r = Reader::new(hs.bytes(body_len, "ClientHello body")?);
+
+    let legacy_version = r.u16("legacy_version")?;
+    let mut random = [0u8; 32];
+    random.copy_from_slice(r.bytes(32, "random")?);
+    let sid = r.vec8("session_id")?;
+    let cipher_suites = u16_list(r.vec16("cipher_suites")?, "cipher_suites")?;
+    let compression_methods = r.vec8("compression_methods")?.to_vec();
+
+    let mut extensions = Vec::new();
+    let no_extensions_block = r.is_empty();
+    if !no_extensions_block {
+        let mut exts = Reader::new(r.vec16("extensions")?);
+        while !exts.is_empty() {
+            let typ = exts.u16("extension type")?;
+            let data = exts.vec16("extension data")?;
+            extensions.push(decode_extension(typ, data));
+        }
+    }
+    if !r.is_empty() {
+        return Err("trailing bytes after extensions".into());
+    }
+    // padding que cumple la regla de BoringSSL: se guarda como regla, así sigue siendo correcta
+    // si cambia el tamaño del hello (otro SNI, otra session ID)
+    let paddings: Vec<usize> = extensions.iter().filter_map(|e| match e {
+        Extension::Padding { len } => Some(*len),
+        _ => None,
+    }).collect();
+    if let [len] = paddings[..] {
+        if boring_padding_len(body_len - len) == Some(len) {
+            for e in &mut extensions {
+                if matches!(e, Extension::Padding { .. }) { *e = Extension::BoringPadding; }
+            }
+        }
+    }
+
+    let tls_version = extensions.iter().find_map(|e| match e {
+        Extension::SupportedVersions { versions, .. } => versions.iter().copied().filter(|v| !is_grease(*v)).max(),
+        _ => None,
+    }).unwrap_or(legacy_version);
+    let psk_key_exchange_modes = extensions.iter().find_map(|e| match e {
+        Extension::PskKeyExchangeModes { modes } => Some(modes.clone()),
+        _ => None,
+    });
+
+    let cipher_grease_slots = grease_slots(&cipher_suites);
+    let mut t = HelloTemplate {
+        tls_version,
+        record_version,
+        legacy_version: Some(legacy_version),
+        client_random: Some(random),
+        session_id: if sid.is_empty() { None } else { Some(sid.to_vec()) },
+        cipher_suites,
+        cipher_grease_slots,
+        compression_methods,
+        extensions,
+        no_extensions_block,
+        grease_mode: GreaseMode::None,
+        psk_key_exchange_modes,
+    };
+    t.grease_mode = observed_grease(&t);
+    Ok(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chrome_like() -> HelloTemplate {
+        HelloTemplate {
+            tls_version: 0x0304,
+            record_version: Some(0x0301),
+            legacy_version: Some(0x0303),
+            client_random: Some([7u8; 32]),
+            session_id: Some(vec![0x11; 32]),
+            cipher_suites: vec![0x6a6a, 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f],
+            cipher_grease_slots: vec![0],
+            extensions: vec![
+                Extension::Grease { typ: 0x2a2a, bytes: vec![] },
+                Extension::ServerName { host: "example.com".into() },
+                Extension::ExtendedMasterSecret,
+                Extension::RenegotiationInfo { renegotiated_connection: vec![] },
+                Extension::SupportedGroups { groups: vec![0x8a8a, 0x001d, 0x0017, 0x0018], grease_slots: vec![0] },
+                Extension::Unknown { typ: 0x000b, bytes: vec![1, 0] },
+                Extension::Alpn { protocols: vec!["h2".into(), "http/1.1".into()] },
+                Extension::SignatureAlgorithms { algs: vec![0x0403, 0x0804, 0x0401], grease_slots: vec![] },
+                Extension::KeyShare { shares: vec![(0x8a8a, vec![0]), (0x001d, vec![9; 32])], grease_slots: vec![0] },
+                Extension::PskKeyExchangeModes { modes: vec![1] },
+                Extension::SupportedVersions { versions: vec![0xbaba, 0x0304, 0x0303], grease_slots: vec![0] },
+                Extension::Grease { typ: 0xdada, bytes: vec![0] },
+            ],
+            grease_mode: GreaseMode::PerField(GreaseValues {
+                cipher: 0x6a6a,
+                group: 0x8a8a,
+                extension1: 0x2a2a,
+                extension2: 0xdada,
+                version: 0xbaba,
+                sig_alg: 0x6a6a,
+            }),
+            psk_key_exchange_modes: Some(vec![1]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let tpl = chrome_like();
+        let raw = Encoder::encode_client_hello(&tpl, false).unwrap().raw_bytes;
+        let decoded = decode_client_hello(&raw).unwrap();
+        assert_eq!(decoded, tpl);
+        assert_eq!(Encoder::encode_client_hello(&decoded, false).unwrap().raw_bytes, raw);
+
+        // bare handshake input decodes to the same template (minus the record version)
+        let bare = decode_client_hello(&raw[5..]).unwrap();
+        assert_eq!(bare.record_version, None);
+        assert_eq!(bare.extensions, tpl.extensions);
+    }
+
+    #[test]
+    fn test_decode_hello_without_extensions_block() {
+        let tpl = HelloTemplate {
+            tls_version: 0x0303,
+            record_version: Some(0x0301),
+            legacy_version: Some(0x0303),
+            client_random: Some([5; 32]),
+            cipher_suites: vec![0xc02f, 0x002f],
+            no_extensions_block: true,
+            ..Default::default()
+        };
+        let raw = Encoder::encode_client_hello(&tpl, false).unwrap().raw_bytes;
+        assert_eq!(raw.len(), 5 + 4 + 2 + 32 + 1 + 2 + 4 + 2);
+        let decoded = decode_client_hello(&raw).unwrap();
+        assert_eq!(decoded, tpl);
+        assert_eq!(Encoder::encode_client_hello(&decoded, false).unwrap().raw_bytes, raw);
+
+        // un bloque vacío se conserva como tal
+        let empty = HelloTemplate { no_extensions_block: false, ..tpl };
+        let raw = Encoder::encode_client_hello(&empty, false).unwrap().raw_bytes;
+        assert_eq!(&raw[raw.len() - 2..], &[0, 0]);
+        assert!(!decode_client_hello(&raw).unwrap().no_extensions_block);
+    }
+
+    // hellos cuyo GREASE no cabe en PerField: el decoder los deja literales
+    fn assert_grease_kept_literal(edit: fn(&mut Vec<Extension>)) {
+        let mut tpl = HelloTemplate { grease_mode: GreaseMode::None, ..chrome_like() };
+        edit(&mut tpl.extensions);
+        let raw = Encoder::encode_client_hello(&tpl, false).unwrap().raw_bytes;
+        let decoded = decode_client_hello(&raw).unwrap();
+        assert_eq!(decoded.grease_mode, GreaseMode::None);
+        assert_eq!(Encoder::encode_client_hello(&decoded, false).unwrap().raw_bytes, raw);
+    }
+
+    #[test]
+    fn test_decode_round_trip_key_share_grease_differs_from_groups() {
+        assert_grease_kept_literal(|exts| exts[8] = Extension::KeyShare { shares: vec![(0xfafa, vec![0]), (0x001d, vec![9; 32])], grease_slots: vec![0] });
+    }
+
+    #[test]
+    fn test_decode_round_trip_third_grease_extension() {
+        assert_grease_kept_literal(|exts| exts.insert(5, Extension::Grease { typ: 0x3a3a, bytes: vec![] }));
+    }
+
+    #[test]
+    fn test_decode_round_trip_two_grease_values_in_one_list() {
+        assert_grease_kept_literal(|exts| exts[4] = Extension::SupportedGroups { groups: vec![0x8a8a, 0x001d, 0x9a9a], grease_slots: vec![0, 2] });
+    }
+
+    #[test]
+    fn test_decode_typed_extensions() {
+        let cases: Vec<(u16, &[u8], Extension)> = vec![
+            (0x0005, &[1, 0, 0, 0, 0], Extension::StatusRequest { status_type: 1, responder_id_list: vec![], request_extensions: vec![] }),
+            (0x0012, &[], Extension::SignedCertificateTimestamp),
+            (0x0023, &[0xaa, 0xbb], Extension::SessionTicket { ticket: vec![0xaa, 0xbb] }),
+            (0x001b, &[2, 0, 2], Extension::CompressCertificate { algorithms: vec![2] }),
+            (0x001c, &[0x40, 0x01], Extension::RecordSizeLimit { limit: 0x4001 }),
+            (0x0022, &[0, 4, 4, 3, 5, 3], Extension::DelegatedCredentials { algs: vec![0x0403, 0x0503] }),
+            (0x002a, &[], Extension::EarlyData),
+            (0x002c, &[0, 2, 7, 7], Extension::Cookie { cookie: vec![7, 7] }),
+            (0x0031, &[], Extension::PostHandshakeAuth),
+            (0xfe0d, &[0, 0, 1, 0, 1, 9, 0, 1, 0xee, 0, 2, 5, 6], Extension::Ech { kdf_id: 1, aead_id: 1, config_id: 9, enc: vec![0xee], payload: vec![5, 6], sealed: false }),
+            (0xfe0d, &[1], Extension::EchInner),
+            (0x4469, &[0, 3, 2, b'h', b'2'], Extension::ApplicationSettings { protocols: vec!["h2".into()], codepoint: AlpsCodepoint::Old }),
+            (0x44cd, &[0, 3, 2, b'h', b'2'], Extension::ApplicationSettings { protocols: vec!["h2".into()], codepoint: AlpsCodepoint::New }),
+            (0x0016, &[], Extension::EncryptThenMac),
+            (
+                0x0029,
+                &[0, 7, 0, 1, 0xee, 0, 0, 0, 9, 0, 3, 2, 1, 2],
+                Extension::PreSharedKey { identities: vec![PskIdentity { identity: vec![0xee], obfuscated_ticket_age: 9 }], binders: vec![vec![1, 2]] },
+            ),
+        ];
+        for (typ, body, ext) in cases {
+            assert_eq!(decode_extension(typ, body), ext);
+        }
+        // cuerpo no vacío en una extensión vacía: opaco
+        assert_eq!(decode_extension(0x0017, &[0]), Extension::Unknown { typ: 0x0017, bytes: vec![0] });
+    }
+
+    #[test]
+    fn test_decode_keeps_non_canonical_bodies_opaque() {
+        // SNI list with two entries has no typed representation
+        let body = [0x00, 0x09, 0x00, 0x00, 0x01, b'a', 0x00, 0x00, 0x02, b'b', b'c'];
+        assert!(matches!(decode_extension(0x0000, &body), Extension::Unknown { typ: 0x0000, .. }));
+    }
+}
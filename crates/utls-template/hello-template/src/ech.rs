@@ -0,0 +1,303 @@
+// Encrypted Client Hello (draft-ietf-tls-esni, versión 0xfe0d): ECHConfigList, HPKE en modo base
+// (RFC 9180, DHKEM(X25519, HKDF-SHA256)) y construcción de los hellos outer/inner. También el
+// GREASE ECH de Chrome, que en el cable no se distingue de uno real.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::decode::Reader;
+use crate::key_share::{generate_key_share, x25519_public, GROUP_X25519};
+use crate::rng::HelloRng;
+use crate::{Encoder, Extension, HelloTemplate};
+
+pub const ECH_VERSION: u16 = 0xfe0d;
+pub const KEM_X25519_HKDF_SHA256: u16 = 0x0020;
+pub const KDF_HKDF_SHA256: u16 = 0x0001;
+pub const AEAD_AES_128_GCM: u16 = 0x0001;
+pub const AEAD_CHACHA20_POLY1305: u16 = 0x0003;
+
+const AEAD_TAG_LEN: usize = 16;
+/// Plaintext lengths BoringSSL picks from for a GREASE ECH payload (the AEAD tag comes on top).
+pub const GREASE_PAYLOAD_LENS: [usize; 4] = [128, 160, 192, 224];
+
+/// One ECHConfig of an ECHConfigList.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EchConfig {
+    pub config_id: u8,
+    pub kem_id: u16,
+    pub public_key: Vec<u8>,
+    pub cipher_suites: Vec<(u16, u16)>, // (kdf_id, aead_id)
+    pub maximum_name_length: u8,
+    pub public_name: String,
+    pub extensions: Vec<u8>,
+    /// The whole serialized ECHConfig (version, length, contents), which goes into the HPKE info.
+    pub raw: Vec<u8>,
+}
+
+fn supported_suite(kdf_id: u16, aead_id: u16) -> bool {
+    kdf_id == KDF_HKDF_SHA256 && matches!(aead_id, AEAD_AES_128_GCM | AEAD_CHACHA20_POLY1305)
+}
+
+impl EchConfig {
+    /// First usable HPKE suite, preferring `aead_id` when the config offers it.
+    pub fn select_suite(&self, aead_id: u16) -> Option<(u16, u16)> {
+        let usable = || self.cipher_suites.iter().copied().filter(|(k, a)| supported_suite(*k, *a));
+        usable().find(|(_, a)| *a == aead_id).or_else(|| usable().next())
+    }
+}
+
+/// Parses an ECHConfigList (as published in the HTTPS DNS record). Configs of other versions
+/// are skipped; only DHKEM(X25519, HKDF-SHA256) configs with a supported suite are returned.
+pub fn parse_ech_config_list(data: &[u8]) -> Result<Vec<EchConfig>, String> {
+    let mut outer = Reader::new(data);
+    let mut list = Reader::new(outer.vec16("ECHConfigList")?);
+    if !outer.is_empty() { return Err("trailing bytes after ECHConfigList".into()); }
+    let mut configs = Vec::new();
+    while !list.is_empty() {
+        let version = list.u16("ECHConfig version")?;
+        let contents = list.vec16("ECHConfig contents")?;
+        if version != ECH_VERSION { continue; }
+        let mut raw = Vec::with_capacity(4 + contents.len());
+        raw.extend_from_slice(&version.to_be_bytes());
+        raw.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+        raw.extend_from_slice(contents);
+
+        let mut r = Reader::new(contents);
+        let config_id = r.u8("config_id")?;
+        let kem_id = r.u16("kem_id")?;
+        let public_key = r.vec16("public_key")?.to_vec();
+        let mut suites = Reader::new(r.vec16("cipher_suites")?);
+        let mut cipher_suites = Vec::new();
+        while !suites.is_empty() {
+            cipher_suites.push((suites.u16("kdf_id")?, suites.u16("aead_id")?));
+        }
+        let maximum_name_length = r.u8("maximum_name_length")?;
+        let public_name = String::from_utf8(r.vec8("public_name")?.to_vec()).map_err(|_| "public_name not utf-8")?;
+        let extensions = r.vec16("ECHConfig extensions")?.to_vec();
+        if !r.is_empty() { return Err("trailing bytes in ECHConfig".into()); }
+
+        let config = EchConfig { config_id, kem_id, public_key, cipher_suites, maximum_name_length, public_name, extensions, raw };
+        if config.kem_id == KEM_X25519_HKDF_SHA256 && config.public_key.len() == 32 && config.select_suite(AEAD_AES_128_GCM).is_some() {
+            configs.push(config);
+        }
+    }
+    if configs.is_empty() { return Err("no supported ECHConfig in list".into()); }
+    Ok(configs)
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &str, ikm: &[u8]) -> Vec<u8> {
+    let mut labeled = b"HPKE-v1".to_vec();
+    labeled.extend_from_slice(suite_id);
+    labeled.extend_from_slice(label.as_bytes());
+    labeled.extend_from_slice(ikm);
+    Hkdf::<Sha256>::extract(Some(salt), &labeled).0.to_vec()
+}
+
+fn labeled_expand(suite_id: &[u8], prk: &[u8], label: &str, info: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let mut labeled = (len as u16).to_be_bytes().to_vec();
+    labeled.extend_from_slice(b"HPKE-v1");
+    labeled.extend_from_slice(suite_id);
+    labeled.extend_from_slice(label.as_bytes());
+    labeled.extend_from_slice(info);
+    let mut out = vec![0u8; len];
+    Hkdf::<Sha256>::from_prk(prk).map_err(|_| "hkdf prk too short")?.expand(&labeled, &mut out).map_err(|_| "hkdf expand error")?;
+    Ok(out)
+}
+
+/// HPKE base-mode sender context (RFC 9180 §5.1), good for the single message ECH needs.
+struct HpkeSealer {
+    aead_id: u16,
+    key: Vec<u8>,
+    base_nonce: Vec<u8>,
+}
+
+impl HpkeSealer {
+    // `dh` es el secreto X25519 entre la clave efímera `enc` y la del servidor `pk_r`
+    fn key_schedule(dh: &[u8], enc: &[u8], pk_r: &[u8], info: &[u8], kdf_id: u16, aead_id: u16) -> Result<Self, String> {
+        let kem_suite = [b"KEM".as_slice(), &KEM_X25519_HKDF_SHA256.to_be_bytes()].concat();
+        let eae_prk = labeled_extract(&kem_suite, b"", "eae_prk", dh);
+        let shared_secret = labeled_expand(&kem_suite, &eae_prk, "shared_secret", &[enc, pk_r].concat(), 32)?;
+
+        let suite = [b"HPKE".as_slice(), &KEM_X25519_HKDF_SHA256.to_be_bytes(), &kdf_id.to_be_bytes(), &aead_id.to_be_bytes()].concat();
+        let mut context = vec![0u8]; // mode_base
+        context.extend_from_slice(&labeled_extract(&suite, b"", "psk_id_hash", b""));
+        context.extend_from_slice(&labeled_extract(&suite, b"", "info_hash", info));
+        let secret = labeled_extract(&suite, &shared_secret, "secret", b"");
+        let key_len = if aead_id == AEAD_AES_128_GCM { 16 } else { 32 };
+        Ok(HpkeSealer {
+            aead_id,
+            key: labeled_expand(&suite, &secret, "key", &context, key_len)?,
+            base_nonce: labeled_expand(&suite, &secret, "base_nonce", &context, 12)?,
+        })
+    }
+
+    /// Encap against `pk_r` with a fresh ephemeral key; returns `enc` and the context.
+    fn setup(pk_r: &[u8], info: &[u8], kdf_id: u16, aead_id: u16, rng: &mut HelloRng) -> Result<(Vec<u8>, Self), String> {
+        if !supported_suite(kdf_id, aead_id) {
+            return Err(format!("unsupported HPKE suite kdf {:#06x} aead {:#06x}", kdf_id, aead_id));
+        }
+        let (enc, ephemeral) = generate_key_share(GROUP_X25519, rng)?;
+        let dh = ephemeral.shared_secret(pk_r)?;
+        Ok((enc.clone(), Self::key_schedule(&dh, &enc, pk_r, info, kdf_id, aead_id)?))
+    }
+
+    // primer (y único) mensaje: nonce = base_nonce
+    fn seal(&self, aad: &[u8], msg: &[u8]) -> Result<Vec<u8>, String> {
+        let payload = Payload { msg, aad };
+        let nonce = Nonce::from_slice(&self.base_nonce);
+        match self.aead_id {
+            AEAD_AES_128_GCM => Aes128Gcm::new_from_slice(&self.key).map_err(|e| format!("cipher init: {:?}", e))?.encrypt(nonce, payload),
+            _ => ChaCha20Poly1305::new_from_slice(&self.key).map_err(|e| format!("cipher init: {:?}", e))?.encrypt(nonce, payload),
+        }
+        .map_err(|_| "HPKE seal failed".into())
+    }
+}
+
+/// Chrome's GREASE ECH: random config ID, a real X25519 `enc` and a random payload of 128 to
+/// 224 bytes (a multiple of 32) plus the AEAD tag.
+pub fn grease_ech(kdf_id: u16, aead_id: u16, rng: &mut HelloRng) -> Extension {
+    let config_id = rng.below(256) as u8;
+    let mut secret = [0u8; 32];
+    rng.fill(&mut secret);
+    let enc = x25519_public(&secret);
+    let len = GREASE_PAYLOAD_LENS[rng.below(GREASE_PAYLOAD_LENS.len() as u32) as usize] + AEAD_TAG_LEN;
+    Extension::Ech { kdf_id, aead_id, config_id, enc, payload: rng.bytes(len), sealed: false }
+}
+
+/// Outer and inner ClientHello of one ECH connection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EchHellos {
+    /// What goes on the wire: `public_name` as SNI, the inner hello sealed in the ECH extension.
+    pub outer: HelloTemplate,
+    /// What the server recovers (it takes the session ID from the outer hello).
+    pub inner: HelloTemplate,
+}
+
+// Padding del EncodedClientHelloInner (draft-ietf-tls-esni §6.1.3): el SNI hasta
+// maximum_name_length y el total a un múltiplo de 32
+fn inner_padding(inner: &HelloTemplate, config: &EchConfig, encoded_len: usize) -> usize {
+    let host_len = inner.extensions.iter().find_map(|e| match e {
+        Extension::ServerName { host } => Some(host.len()),
+        _ => None,
+    });
+    let max_name = config.maximum_name_length as usize;
+    let pad = match host_len {
+        Some(n) => max_name.saturating_sub(n),
+        None => max_name + 9,
+    };
+    pad + 31 - ((encoded_len + pad - 1) % 32)
+}
+
+/// Encrypts an instantiated `hello` to `config`. The ECH extension of `hello` (`Ech` or
+/// `EchGrease`) marks where the real one goes and which AEAD to prefer. The inner hello is
+/// `hello` with a fresh random and the inner ECH marker. The outer hello is `hello` with
+/// `public_name` as SNI. Inner extensions are sent in full (no ech_outer_extensions).
+pub fn encrypt_client_hello(hello: &HelloTemplate, config: &EchConfig, rng: &mut HelloRng) -> Result<EchHellos, String> {
+    let (slot, preferred) = hello
+        .extensions
+        .iter()
+        .enumerate()
+        .find_map(|(i, e)| match e {
+            Extension::Ech { aead_id, .. } | Extension::EchGrease { aead_id, .. } => Some((i, *aead_id)),
+            _ => None,
+        })
+        .ok_or("template has no ECH extension")?;
+    let (kdf_id, aead_id) = config.select_suite(preferred).ok_or("no supported HPKE suite in ECHConfig")?;
+
+    let mut inner = hello.clone();
+    let mut random = [0u8; 32];
+    rng.fill(&mut random);
+    inner.client_random = Some(random);
+    inner.extensions[slot] = Extension::EchInner;
+    // EncodedClientHelloInner: el cuerpo del ClientHello sin session ID, más ceros
+    let mut encoded = Encoder::encode_handshake(&HelloTemplate { session_id: None, ..inner.clone() })[4..].to_vec();
+    encoded.resize(encoded.len() + inner_padding(&inner, config, encoded.len()), 0);
+
+    let info = [b"tls ech\0".as_slice(), &config.raw].concat();
+    let (enc, sealer) = HpkeSealer::setup(&config.public_key, &info, kdf_id, aead_id, rng)?;
+    let mut outer = hello.clone();
+    for e in &mut outer.extensions {
+        if let Extension::ServerName { host } = e { *host = config.public_name.clone(); }
+    }
+    // AAD: el ClientHello outer con el payload a ceros (misma longitud)
+    let payload = vec![0u8; encoded.len() + AEAD_TAG_LEN];
+    outer.extensions[slot] = Extension::Ech { kdf_id, aead_id, config_id: config.config_id, enc, payload, sealed: true };
+    let aad = Encoder::encode_handshake(&outer)[4..].to_vec();
+    let sealed = sealer.seal(&aad, &encoded)?;
+    if let Extension::Ech { payload, .. } = &mut outer.extensions[slot] { *payload = sealed; }
+    Ok(EchHellos { outer, inner })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hpke::{aead::AesGcm128, kdf::HkdfSha256, kem::X25519HkdfSha256, Deserializable, Kem, OpModeR};
+
+    fn config_list(public_key: &[u8], suites: &[(u16, u16)]) -> Vec<u8> {
+        let mut c = vec![7u8];
+        c.extend_from_slice(&KEM_X25519_HKDF_SHA256.to_be_bytes());
+        c.extend_from_slice(&(public_key.len() as u16).to_be_bytes());
+        c.extend_from_slice(public_key);
+        c.extend_from_slice(&((suites.len() * 4) as u16).to_be_bytes());
+        for (k, a) in suites { c.extend_from_slice(&k.to_be_bytes()); c.extend_from_slice(&a.to_be_bytes()); }
+        c.push(64);
+        c.push(14);
+        c.extend_from_slice(b"public.example");
+        c.extend_from_slice(&[0, 0]);
+        // una versión desconocida delante, que se ignora
+        let mut list = vec![0xfe, 0x0c, 0, 1, 0xaa];
+        list.extend_from_slice(&ECH_VERSION.to_be_bytes());
+        list.extend_from_slice(&(c.len() as u16).to_be_bytes());
+        list.extend_from_slice(&c);
+        let mut out = (list.len() as u16).to_be_bytes().to_vec();
+        out.extend_from_slice(&list);
+        out
+    }
+
+    #[test]
+    fn test_inner_hello_opens_with_hpke() {
+        let mut rng = HelloRng::seeded(24);
+        let (server_pub, server) = generate_key_share(GROUP_X25519, &mut rng).unwrap();
+        let configs = parse_ech_config_list(&config_list(&server_pub, &[(KDF_HKDF_SHA256, 0x0002), (KDF_HKDF_SHA256, AEAD_AES_128_GCM)])).unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].public_name, "public.example");
+        assert_eq!(configs[0].select_suite(AEAD_CHACHA20_POLY1305), Some((KDF_HKDF_SHA256, AEAD_AES_128_GCM)));
+
+        let hello = HelloTemplate {
+            record_version: Some(0x0301),
+            client_random: Some([1; 32]),
+            session_id: Some(vec![2; 32]),
+            cipher_suites: vec![0x1301],
+            extensions: vec![
+                Extension::ServerName { host: "secret.example.com".into() },
+                Extension::EchGrease { kdf_id: KDF_HKDF_SHA256, aead_id: AEAD_AES_128_GCM },
+            ],
+            ..Default::default()
+        };
+        let hellos = encrypt_client_hello(&hello, &configs[0], &mut rng).unwrap();
+        assert_eq!(hellos.outer.extensions[0], Extension::ServerName { host: "public.example".into() });
+        assert_eq!(hellos.inner.extensions[1], Extension::EchInner);
+        let Extension::Ech { config_id: 7, enc, payload, .. } = &hellos.outer.extensions[1] else { panic!("no outer ECH") };
+
+        // el servidor abre el payload con una implementación HPKE independiente
+        let sk = <X25519HkdfSha256 as Kem>::PrivateKey::from_bytes(&server.private).unwrap();
+        let encapped = <X25519HkdfSha256 as Kem>::EncappedKey::from_bytes(enc).unwrap();
+        let info = [b"tls ech\0".as_slice(), &configs[0].raw].concat();
+        let mut ctx = hpke::setup_receiver::<AesGcm128, HkdfSha256, X25519HkdfSha256>(&OpModeR::Base, &sk, &encapped, &info).unwrap();
+        let mut outer_aad = hellos.outer.clone();
+        if let Extension::Ech { payload, .. } = &mut outer_aad.extensions[1] { payload.fill(0); }
+        let plain = ctx.open(payload, &Encoder::encode_handshake(&outer_aad)[4..]).unwrap();
+        assert_eq!(plain.len() % 32, 0);
+
+        // sin el padding y con la session ID del outer es exactamente el hello inner
+        let unpadded = Encoder::encode_handshake(&HelloTemplate { session_id: None, ..hellos.inner.clone() })[4..].to_vec();
+        assert_eq!(&plain[..unpadded.len()], &unpadded[..]);
+        assert!(plain[unpadded.len()..].iter().all(|b| *b == 0));
+
+        let Extension::Ech { payload, .. } = grease_ech(KDF_HKDF_SHA256, AEAD_AES_128_GCM, &mut rng) else { unreachable!() };
+        assert!([144, 176, 208, 240].contains(&payload.len()));
+    }
+}
@@ -1,8 +1,11 @@
 use serde::{Serialize};
-use crate::{HelloTemplate, Extension as InternalExtension}; // Alias for clarity
+use crate::{is_grease, AlpsCodepoint, Encoder, HelloTemplate, Extension as InternalExtension}; // Alias for clarity
+use crate::ech::{AEAD_AES_128_GCM, GREASE_PAYLOAD_LENS, KDF_HKDF_SHA256};
+use crate::transport_params::encode_transport_params;
+use crate::http_template::{H2PriorityTemplate, H2Template};
 
 #[derive(Serialize)]
-struct UtlsJson {
+pub struct UtlsJson {
     tls_vers_min: u16,
     tls_vers_max: u16,
     cipher_suites: Vec<u16>,
@@ -10,9 +13,10 @@ struct UtlsJson {
     // flags auxiliares (grease_fixed, alpn, etc.)
 }
 
-// Representación de las extensiones de uTLS en Go
+// Representación de las extensiones de uTLS en Go. Cada objeto lleva el nombre del tipo de uTLS
+// en "name": sin él, las extensiones sin campos (EMS, status_request, SCT) serían todas `{}`
 #[derive(Serialize)]
-#[serde(untagged)] // Para serializar el enum directamente sin un tag de variante
+#[serde(tag = "name")]
 pub enum UtlsExt {
     SNIExtension { server_name: String },
     SupportedCurvesExtension { curves: Vec<u16> },
@@ -22,10 +26,34 @@ pub enum UtlsExt {
     KeyShareExtension { key_shares: Vec<UtlsKeyShare> },
     PSKKeyExchangeModesExtension { modes: Vec<u8> },
     SupportedVersionsExtension { versions: Vec<u16> },
-    ApplicationSettingsExtension { supported_protocols: Vec<String>, data: Vec<u8> },
-    GenericExtension { id: u16, data: Vec<u8> }, // Para ECH outer/grease y otras extensiones desconocidas
+    ApplicationSettingsExtension { supported_protocols: Vec<String> },    // 0x4469
+    ApplicationSettingsExtensionNew { supported_protocols: Vec<String> }, // 0x44cd
+    GenericExtension { id: u16, data: Vec<u8> }, // Para extensiones desconocidas
+    GREASEEncryptedClientHelloExtension { candidate_cipher_suites: Vec<UtlsHpkeSuite>, candidate_payload_lens: Vec<u16> },
     GREASEExtension,
     UtlsPaddingExtension { get_padding_len: String }, // Representado como string para la función Go
+    ExtendedMasterSecretExtension {},
+    RenegotiationInfoExtension { renegotiated_connection: Vec<u8> },
+    StatusRequestExtension {},
+    SCTExtension {},
+    SessionTicketExtension { ticket: Vec<u8> },
+    UtlsCompressCertExtension { algorithms: Vec<u16> },
+    FakeRecordSizeLimitExtension { limit: u16 },
+    FakeDelegatedCredentialsExtension { supported_signature_algorithms: Vec<u16> },
+    FakePreSharedKeyExtension { identities: Vec<UtlsPskIdentity>, binders: Vec<Vec<u8>> },
+    CookieExtension { cookie: Vec<u8> },
+}
+
+#[derive(Serialize)]
+pub struct UtlsPskIdentity {
+    label: Vec<u8>,
+    obfuscated_ticket_age: u32,
+}
+
+#[derive(Serialize)]
+pub struct UtlsHpkeSuite {
+    kdf_id: u16,
+    aead_id: u16,
 }
 
 #[derive(Serialize)]
@@ -34,37 +62,109 @@ pub struct UtlsKeyShare {
     data: Vec<u8>,
 }
 
-pub fn export_utls_json(t: &HelloTemplate) -> UtlsJson {
+// uTLS solo sabe parecer ECH (GREASE) o cifrar con su propia ECHConfigList: un ECH ya sellado
+// no tiene equivalente en un ClientHelloSpec
+const SEALED_ECH_ERROR: &str = "sealed ECH cannot be exported to uTLS; export the hello before encrypt_client_hello and set Config.EncryptedClientHelloConfigList";
+
+/// JSON mirror of `t` as uTLS extensions. Fails on a sealed `Ech` (see `export_utls_go`).
+pub fn export_utls_json(t: &HelloTemplate) -> Result<UtlsJson, String> {
     // mapear InternalExtension::{...} → UtlsExt::{...}
     // preservar orden exacto
     // Convertir a la estructura JSON compatible con uTLS
     let extensions = t.extensions.iter().map(|ext| {
-        match ext {
+        Ok(match ext {
             InternalExtension::ServerName { host } => UtlsExt::SNIExtension { server_name: host.clone() },
-            InternalExtension::SupportedVersions { versions } => UtlsExt::SupportedVersionsExtension { versions: versions.clone() },
+            InternalExtension::SupportedVersions { versions, .. } => UtlsExt::SupportedVersionsExtension { versions: versions.clone() },
             InternalExtension::SupportedGroups { groups, grease_slots: _ } => UtlsExt::SupportedCurvesExtension { curves: groups.clone() }, // uTLS usa SupportedCurvesExtension para grupos
-            InternalExtension::SignatureAlgorithms { algs } => UtlsExt::SignatureAlgorithmsExtension { algs: algs.clone() },
-            InternalExtension::KeyShare { shares } => UtlsExt::KeyShareExtension {
+            InternalExtension::SignatureAlgorithms { algs, .. } => UtlsExt::SignatureAlgorithmsExtension { algs: algs.clone() },
+            InternalExtension::KeyShare { shares, .. } => UtlsExt::KeyShareExtension {
                 key_shares: shares.iter().map(|(group, key)| UtlsKeyShare { group: *group, data: key.clone() }).collect()
             },
             InternalExtension::Alpn { protocols } => UtlsExt::ALPNExtension { alpn_protocols: protocols.clone() },
             InternalExtension::Padding { len } => UtlsExt::UtlsPaddingExtension { get_padding_len: format!("func(clientHelloLen int) int {{ return {} }}", len) },
-            InternalExtension::EchOuterStub { config_id } => UtlsExt::GenericExtension { id: 0xfe0d, data: config_id.clone() },
-            InternalExtension::ApplicationSettings { protocols, data } => UtlsExt::ApplicationSettingsExtension { supported_protocols: protocols.clone(), data: data.clone() },
+            InternalExtension::BoringPadding => UtlsExt::UtlsPaddingExtension { get_padding_len: "utls.BoringPaddingStyle".into() },
+            InternalExtension::EchGrease { kdf_id, aead_id } => UtlsExt::GREASEEncryptedClientHelloExtension {
+                candidate_cipher_suites: vec![UtlsHpkeSuite { kdf_id: *kdf_id, aead_id: *aead_id }],
+                candidate_payload_lens: GREASE_PAYLOAD_LENS.iter().map(|l| *l as u16).collect(),
+            },
+            InternalExtension::Ech { sealed: true, .. } => return Err(SEALED_ECH_ERROR.into()),
+            InternalExtension::Ech { kdf_id, aead_id, payload, .. } => UtlsExt::GREASEEncryptedClientHelloExtension {
+                candidate_cipher_suites: vec![UtlsHpkeSuite { kdf_id: *kdf_id, aead_id: *aead_id }],
+                candidate_payload_lens: vec![payload.len().saturating_sub(16) as u16],
+            },
+            InternalExtension::EchInner => UtlsExt::GenericExtension { id: 0xfe0d, data: vec![1] },
+            InternalExtension::ApplicationSettings { protocols, codepoint: AlpsCodepoint::Old } => UtlsExt::ApplicationSettingsExtension { supported_protocols: protocols.clone() },
+            InternalExtension::ApplicationSettings { protocols, codepoint: AlpsCodepoint::New } => UtlsExt::ApplicationSettingsExtensionNew { supported_protocols: protocols.clone() },
+            InternalExtension::Grease { .. } => UtlsExt::GREASEExtension,
             InternalExtension::Unknown { typ, bytes } => UtlsExt::GenericExtension { id: *typ, data: bytes.clone() },
             InternalExtension::PskKeyExchangeModes { modes } => UtlsExt::PSKKeyExchangeModesExtension { modes: modes.clone() },
-        }
-    }).collect();
+            InternalExtension::QuicTransportParameters { params } => UtlsExt::GenericExtension { id: 0x0039, data: encode_transport_params(params) },
+            InternalExtension::ExtendedMasterSecret => UtlsExt::ExtendedMasterSecretExtension {},
+            InternalExtension::RenegotiationInfo { renegotiated_connection } => UtlsExt::RenegotiationInfoExtension { renegotiated_connection: renegotiated_connection.clone() },
+            // StatusRequestExtension de uTLS solo escribe OCSP sin responder IDs ni extensiones
+            InternalExtension::StatusRequest { status_type: 1, responder_id_list, request_extensions } if responder_id_list.is_empty() && request_extensions.is_empty() => UtlsExt::StatusRequestExtension {},
+            InternalExtension::StatusRequest { .. } => UtlsExt::GenericExtension { id: 0x0005, data: extension_body(ext) },
+            InternalExtension::SignedCertificateTimestamp => UtlsExt::SCTExtension {},
+            InternalExtension::SessionTicket { ticket } => UtlsExt::SessionTicketExtension { ticket: ticket.clone() },
+            InternalExtension::CompressCertificate { algorithms } => UtlsExt::UtlsCompressCertExtension { algorithms: algorithms.clone() },
+            InternalExtension::RecordSizeLimit { limit } => UtlsExt::FakeRecordSizeLimitExtension { limit: *limit },
+            InternalExtension::DelegatedCredentials { algs } => UtlsExt::FakeDelegatedCredentialsExtension { supported_signature_algorithms: algs.clone() },
+            InternalExtension::PreSharedKey { identities, binders } => UtlsExt::FakePreSharedKeyExtension {
+                identities: identities.iter().map(|i| UtlsPskIdentity { label: i.identity.clone(), obfuscated_ticket_age: i.obfuscated_ticket_age }).collect(),
+                binders: binders.clone(),
+            },
+            InternalExtension::Cookie { cookie } => UtlsExt::CookieExtension { cookie: cookie.clone() },
+            // sin tipo propio en uTLS: cuerpo tal cual
+            InternalExtension::EarlyData | InternalExtension::PostHandshakeAuth | InternalExtension::EncryptThenMac => UtlsExt::GenericExtension { id: ext.ext_type(), data: extension_body(ext) },
+        })
+    }).collect::<Result<_, String>>()?;
 
-    UtlsJson {
+    Ok(UtlsJson {
         tls_vers_min: 0x0303, // TLS 1.2
         tls_vers_max: 0x0304, // TLS 1.3
         cipher_suites: t.cipher_suites.clone(),
         extensions,
-    }
+    })
+}
+
+// Cuerpo codificado de una extensión (sin tipo ni longitud)
+fn extension_body(ext: &InternalExtension) -> Vec<u8> {
+    let mut out = Vec::new();
+    Encoder::encode_extension(ext, &mut out);
+    out.split_off(4)
+}
+
+fn go_bytes(bytes: &[u8]) -> String {
+    let items: Vec<String> = bytes.iter().map(|b| format!("0x{:02x}", b)).collect();
+    format!("[]byte{{{}}}", items.join(", "))
 }
 
-pub fn export_utls_go(t: &HelloTemplate) -> String {
+// Lista Go con GREASE como utls.GREASE_PLACEHOLDER (uTLS elige el valor por conexión)
+fn go_grease_list(ty: &str, items: &[u16]) -> String {
+    let items: Vec<String> = items
+        .iter()
+        .map(|v| if is_grease(*v) { format!("{}(utls.GREASE_PLACEHOLDER)", ty) } else { format!("0x{:04x}", v) })
+        .collect();
+    items.join(", ")
+}
+
+fn go_grease_ech(kdf_id: u16, aead_id: u16, payload_lens: &[usize]) -> String {
+    let lens: Vec<String> = payload_lens.iter().map(|l| l.to_string()).collect();
+    format!(
+        "    &utls.GREASEEncryptedClientHelloExtension{{CandidateCipherSuites: []utls.HPKESymmetricCipherSuite{{{{KdfId: 0x{:04x}, AeadId: 0x{:04x}}}}}, CandidatePayloadLens: []uint16{{{}}}}},\n",
+        kdf_id, aead_id, lens.join(", ")
+    )
+}
+
+fn go_u16_list(ty: &str, items: &[u16]) -> String {
+    let items: Vec<String> = items.iter().map(|v| format!("0x{:04x}", v)).collect();
+    format!("[]{}{{{}}}", ty, items.join(", "))
+}
+
+/// Go snippet declaring `Spec`, a utls.ClientHelloSpec for `t`. GREASE ECH (`EchGrease` or an
+/// unsealed `Ech`) maps to uTLS's GREASE ECH; a sealed `Ech` is an error, since its payload only
+/// makes sense with the ECHConfigList it was sealed to, which uTLS takes from its Config.
+pub fn export_utls_go(t: &HelloTemplate) -> Result<String, String> {
     // Genera un snippet Go sencillo que declara una variable `Spec` tipo utls.ClientHelloSpec
     // Conserva orden de cipher suites y añade SNI/ALPN si están presentes.
     let mut go = String::new();
@@ -73,10 +173,10 @@ pub fn export_utls_go(t: &HelloTemplate) -> String {
     go.push_str("var Spec = &utls.ClientHelloSpec{\n");
     go.push_str(&format!("  TLSVersMin: 0x{:04x}, TLSVersMax: 0x{:04x},\n", 0x0303u16, 0x0304u16));
 
-    // Cipher suites
+    // Cipher suites (GREASE por conexión, como en el resto de listas)
     go.push_str("  CipherSuites: []uint16{\n");
     for cs in &t.cipher_suites {
-        go.push_str(&format!("    0x{:04x},\n", cs));
+        go.push_str(&format!("    {},\n", go_grease_list("uint16", &[*cs])));
     }
     go.push_str("  },\n");
 
@@ -92,22 +192,221 @@ pub fn export_utls_go(t: &HelloTemplate) -> String {
                 for p in protocols { go.push_str(&format!("      \"{}\",\n", p)); }
                 go.push_str("    }},\n");
             }
-            InternalExtension::Unknown { typ, bytes } => {
-                go.push_str(&format!("    &utls.GenericExtension{{Id: 0x{:04x}, Data: []byte{{", *typ));
-                for b in bytes { go.push_str(&format!("0x{:02x}, ", b)); }
-                go.push_str("0x00}},\n");
+            InternalExtension::ApplicationSettings { protocols, codepoint } => {
+                let ty = match codepoint {
+                    AlpsCodepoint::Old => "ApplicationSettingsExtension",
+                    AlpsCodepoint::New => "ApplicationSettingsExtensionNew",
+                };
+                go.push_str(&format!("    &utls.{}{{SupportedProtocols: []string{{{}}}}},\n", ty, go_string_list(protocols)));
+            }
+            InternalExtension::SupportedGroups { groups, .. } => {
+                go.push_str(&format!("    &utls.SupportedCurvesExtension{{Curves: []utls.CurveID{{{}}}}},\n", go_grease_list("utls.CurveID", groups)));
+            }
+            InternalExtension::SignatureAlgorithms { algs, .. } => {
+                go.push_str(&format!("    &utls.SignatureAlgorithmsExtension{{SupportedSignatureAlgorithms: []utls.SignatureScheme{{{}}}}},\n", go_grease_list("utls.SignatureScheme", algs)));
+            }
+            InternalExtension::SupportedVersions { versions, .. } => {
+                go.push_str(&format!("    &utls.SupportedVersionsExtension{{Versions: []uint16{{{}}}}},\n", go_grease_list("uint16", versions)));
+            }
+            InternalExtension::KeyShare { shares, .. } => {
+                // uTLS genera las claves: solo grupos (la entrada GREASE lleva un byte, como Chrome)
+                let items: Vec<String> = shares
+                    .iter()
+                    .map(|(g, _)| match is_grease(*g) {
+                        true => "{Group: utls.CurveID(utls.GREASE_PLACEHOLDER), Data: []byte{0}}".to_string(),
+                        false => format!("{{Group: utls.CurveID(0x{:04x})}}", g),
+                    })
+                    .collect();
+                go.push_str(&format!("    &utls.KeyShareExtension{{KeyShares: []utls.KeyShare{{{}}}}},\n", items.join(", ")));
+            }
+            InternalExtension::PskKeyExchangeModes { modes } => {
+                go.push_str(&format!("    &utls.PSKKeyExchangeModesExtension{{Modes: []uint8{{{}}}}},\n", modes.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ")));
+            }
+            InternalExtension::ExtendedMasterSecret => go.push_str("    &utls.ExtendedMasterSecretExtension{},\n"),
+            InternalExtension::RenegotiationInfo { .. } => go.push_str("    &utls.RenegotiationInfoExtension{Renegotiation: utls.RenegotiateOnceAsClient},\n"),
+            InternalExtension::StatusRequest { status_type: 1, responder_id_list, request_extensions } if responder_id_list.is_empty() && request_extensions.is_empty() => {
+                go.push_str("    &utls.StatusRequestExtension{},\n");
+            }
+            InternalExtension::SignedCertificateTimestamp => go.push_str("    &utls.SCTExtension{},\n"),
+            InternalExtension::SessionTicket { ticket } => {
+                go.push_str(&format!("    &utls.SessionTicketExtension{{Session: nil, Ticket: {}}},\n", go_bytes(ticket)));
+            }
+            InternalExtension::CompressCertificate { algorithms } => {
+                let algos: Vec<String> = algorithms.iter().map(|a| format!("utls.CertCompressionAlgo(0x{:04x})", a)).collect();
+                go.push_str(&format!("    &utls.UtlsCompressCertExtension{{Algorithms: []utls.CertCompressionAlgo{{{}}}}},\n", algos.join(", ")));
+            }
+            InternalExtension::RecordSizeLimit { limit } => {
+                go.push_str(&format!("    &utls.FakeRecordSizeLimitExtension{{Limit: 0x{:04x}}},\n", limit));
+            }
+            InternalExtension::DelegatedCredentials { algs } => {
+                go.push_str(&format!("    &utls.FakeDelegatedCredentialsExtension{{SupportedSignatureAlgorithms: {}}},\n", go_u16_list("utls.SignatureScheme", algs)));
             }
+            InternalExtension::PreSharedKey { identities, binders } => {
+                go.push_str("    &utls.FakePreSharedKeyExtension{\n      Identities: []utls.PskIdentity{\n");
+                for id in identities {
+                    go.push_str(&format!("        {{Label: {}, ObfuscatedTicketAge: {}}},\n", go_bytes(&id.identity), id.obfuscated_ticket_age));
+                }
+                go.push_str("      },\n      Binders: [][]byte{\n");
+                for b in binders { go.push_str(&format!("        {},\n", go_bytes(b))); }
+                go.push_str("      },\n    },\n");
+            }
+            InternalExtension::Cookie { cookie } => {
+                go.push_str(&format!("    &utls.CookieExtension{{Cookie: {}}},\n", go_bytes(cookie)));
+            }
+            // uTLS envía ECH real si el Config lleva EncryptedClientHelloConfigList y GREASE si no
+            InternalExtension::EchGrease { kdf_id: KDF_HKDF_SHA256, aead_id: AEAD_AES_128_GCM } => go.push_str("    utls.BoringGREASEECH(),\n"),
+            InternalExtension::EchGrease { kdf_id, aead_id } => go.push_str(&go_grease_ech(*kdf_id, *aead_id, &GREASE_PAYLOAD_LENS)),
+            InternalExtension::Ech { sealed: true, .. } => return Err(SEALED_ECH_ERROR.into()),
+            InternalExtension::Ech { kdf_id, aead_id, payload, .. } => go.push_str(&go_grease_ech(*kdf_id, *aead_id, &[payload.len().saturating_sub(16)])),
+            InternalExtension::BoringPadding => go.push_str("    &utls.UtlsPaddingExtension{GetPaddingLen: utls.BoringPaddingStyle},\n"),
+            InternalExtension::Grease { .. } => go.push_str("    &utls.UtlsGREASEExtension{},\n"),
             _ => {
-                // For other extensions, we can just add them as GenericExtension
-                // This is a simplification; a more accurate mapping would be needed
-                // For now, we'll just add them as GenericExtension with a placeholder ID
-                // or a more specific mapping if available.
-                // For now, we'll just add them as GenericExtension with a placeholder ID
-                // or a more specific mapping if available.
+                // resto: extensión genérica con el cuerpo exacto que produce el encoder
+                go.push_str(&format!("    &utls.GenericExtension{{Id: 0x{:04x}, Data: {}}},\n", ext.ext_type(), go_bytes(&extension_body(ext))));
             }
         }
     }
     go.push_str("  },\n");
     go.push_str("}\n");
+    Ok(go)
+}
+
+fn go_h2_setting(id: u16) -> String {
+    match id {
+        0x1 => "http2.SettingHeaderTableSize".into(),
+        0x2 => "http2.SettingEnablePush".into(),
+        0x3 => "http2.SettingMaxConcurrentStreams".into(),
+        0x4 => "http2.SettingInitialWindowSize".into(),
+        0x5 => "http2.SettingMaxFrameSize".into(),
+        0x6 => "http2.SettingMaxHeaderListSize".into(),
+        other => format!("http2.SettingID(0x{:x})", other),
+    }
+}
+
+// PriorityParam.Weight en Go es el valor del wire (weight - 1)
+fn go_h2_priority_param(p: &H2PriorityTemplate) -> String {
+    format!("http2.PriorityParam{{StreamDep: {}, Exclusive: {}, Weight: {}}}", p.depends_on, p.exclusive, p.weight.saturating_sub(1))
+}
+
+fn go_string_list(items: &[String]) -> String {
+    items.iter().map(|s| format!("{:?}", s)).collect::<Vec<_>>().join(", ")
+}
+
+/// Go snippet with the HTTP/2 transport settings of `t` for clients built on
+/// `github.com/bogdanfinn/fhttp/http2` (settings order, connection flow, priorities, header order).
+pub fn export_http2_go(t: &H2Template) -> String {
+    let mut go = String::new();
+    go.push_str("package templates\n\n");
+    go.push_str("import \"github.com/bogdanfinn/fhttp/http2\"\n\n");
+
+    go.push_str("var H2Settings = map[http2.SettingID]uint32{\n");
+    for (id, v) in &t.settings { go.push_str(&format!("  {}: {},\n", go_h2_setting(*id), v)); }
+    go.push_str("}\n\n");
+    go.push_str("var H2SettingsOrder = []http2.SettingID{\n");
+    for (id, _) in &t.settings { go.push_str(&format!("  {},\n", go_h2_setting(*id))); }
+    go.push_str("}\n\n");
+
+    go.push_str(&format!("var ConnectionFlow uint32 = {}\n\n", t.window_update.unwrap_or(0)));
+    match &t.headers_priority {
+        Some(p) => go.push_str(&format!("var HeaderPriority = &{}\n\n", go_h2_priority_param(p))),
+        None => go.push_str("var HeaderPriority *http2.PriorityParam = nil\n\n"),
+    }
+    go.push_str("var Priorities = []http2.Priority{\n");
+    for p in &t.priorities {
+        go.push_str(&format!("  {{StreamID: {}, PriorityParam: {}}},\n", p.stream_id, go_h2_priority_param(p)));
+    }
+    go.push_str("}\n\n");
+
+    go.push_str(&format!("var PseudoHeaderOrder = []string{{{}}}\n\n", go_string_list(&t.pseudo_header_order)));
+    let names: Vec<String> = t.headers.iter().map(|(n, _)| n.clone()).collect();
+    go.push_str(&format!("var HeaderOrder = []string{{{}}}\n", go_string_list(&names)));
     go
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ech::{encrypt_client_hello, grease_ech, EchConfig};
+    use crate::key_share::generate_key_share;
+    use crate::rng::HelloRng;
+
+    fn hello_with(ech: InternalExtension) -> HelloTemplate {
+        HelloTemplate {
+            cipher_suites: vec![0x1301],
+            extensions: vec![InternalExtension::ServerName { host: "secret.example".into() }, ech],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ech_export() {
+        let mut rng = HelloRng::seeded(5);
+        let grease = hello_with(grease_ech(KDF_HKDF_SHA256, AEAD_AES_128_GCM, &mut rng));
+        let InternalExtension::Ech { payload, .. } = &grease.extensions[1] else { unreachable!() };
+        let lens = vec![(payload.len() - 16) as u16];
+        let json = export_utls_json(&grease).unwrap();
+        assert!(matches!(&json.extensions[1], UtlsExt::GREASEEncryptedClientHelloExtension { candidate_cipher_suites, candidate_payload_lens }
+            if candidate_cipher_suites.len() == 1 && candidate_cipher_suites[0].aead_id == AEAD_AES_128_GCM && *candidate_payload_lens == lens));
+        let go = export_utls_go(&grease).unwrap();
+        assert!(go.contains(&format!("CandidatePayloadLens: []uint16{{{}}}", lens[0])));
+
+        // un ECH sellado de verdad no se hace pasar por GREASE
+        let (server_pub, _) = generate_key_share(0x001d, &mut rng).unwrap();
+        let config = EchConfig {
+            config_id: 3,
+            kem_id: 0x0020,
+            public_key: server_pub,
+            cipher_suites: vec![(KDF_HKDF_SHA256, AEAD_AES_128_GCM)],
+            maximum_name_length: 0,
+            public_name: "public.example".into(),
+            extensions: vec![],
+            raw: vec![],
+        };
+        let sealed = encrypt_client_hello(&grease, &config, &mut rng).unwrap().outer;
+        assert!(matches!(export_utls_json(&sealed), Err(e) if e.contains("EncryptedClientHelloConfigList")));
+        assert!(export_utls_go(&sealed).unwrap_err().contains("EncryptedClientHelloConfigList"));
+    }
+
+    #[test]
+    fn test_go_cipher_suites_use_grease_placeholder() {
+        let t = HelloTemplate { cipher_suites: vec![0xeaea, 0x1301], cipher_grease_slots: vec![0], ..Default::default() };
+        let go = export_utls_go(&t).unwrap();
+        assert!(go.contains("  CipherSuites: []uint16{\n    uint16(utls.GREASE_PLACEHOLDER),\n    0x1301,\n  },"));
+        assert!(!go.contains("0xeaea"));
+    }
+
+    #[test]
+    fn test_json_extensions_are_self_identifying() {
+        let t = HelloTemplate {
+            extensions: vec![
+                InternalExtension::ExtendedMasterSecret,
+                InternalExtension::StatusRequest { status_type: 1, responder_id_list: vec![], request_extensions: vec![] },
+                InternalExtension::SignedCertificateTimestamp,
+                InternalExtension::Grease { typ: 0x0a0a, bytes: vec![] },
+            ],
+            ..Default::default()
+        };
+        let exts: Vec<String> = export_utls_json(&t).unwrap().extensions.iter().map(|e| serde_json::to_string(e).unwrap()).collect();
+        assert_eq!(exts, vec![
+            r#"{"name":"ExtendedMasterSecretExtension"}"#,
+            r#"{"name":"StatusRequestExtension"}"#,
+            r#"{"name":"SCTExtension"}"#,
+            r#"{"name":"GREASEExtension"}"#,
+        ]);
+        let sni = serde_json::to_string(&UtlsExt::SNIExtension { server_name: "a.test".into() }).unwrap();
+        assert_eq!(sni, r#"{"name":"SNIExtension","server_name":"a.test"}"#);
+    }
+
+    #[test]
+    fn test_status_request_with_fields_is_generic() {
+        let plain = InternalExtension::StatusRequest { status_type: 1, responder_id_list: vec![], request_extensions: vec![] };
+        let json = export_utls_json(&hello_with(plain)).unwrap();
+        assert!(matches!(json.extensions[1], UtlsExt::StatusRequestExtension {}));
+
+        let with_ids = hello_with(InternalExtension::StatusRequest { status_type: 1, responder_id_list: vec![0, 2, 0xab, 0xcd], request_extensions: vec![] });
+        let body = extension_body(&with_ids.extensions[1]);
+        let json = export_utls_json(&with_ids).unwrap();
+        assert!(matches!(&json.extensions[1], UtlsExt::GenericExtension { id: 0x0005, data } if *data == body));
+        assert!(export_utls_go(&with_ids).unwrap().contains(&format!("&utls.GenericExtension{{Id: 0x0005, Data: {}}}", go_bytes(&body))));
+    }
+}
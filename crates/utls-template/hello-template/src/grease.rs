@@ -1,18 +1,110 @@
 use serde::{Serialize, Deserialize};
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
-pub enum GreaseMode { Fixed(u16, u16), Random, None }
+use crate::rng::HelloRng;
 
+/// How the encoder fills the template's GREASE slots.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum GreaseMode {
+    /// Keep the values written in the template (as captured).
+    None,
+    /// One value everywhere except the second GREASE extension (legacy pair form).
+    Fixed(u16, u16),
+    /// Explicit value per field.
+    PerField(GreaseValues),
+    /// Fresh values per connection, following Chrome's rules.
+    Random,
+}
+
+/// GREASE value of each field of a ClientHello (RFC 8701), as BoringSSL assigns them:
+/// one independent value per field, the key_share entry reuses the group value and the
+/// two GREASE extensions never share a codepoint.
+///
+/// There is no ALPS field: BoringSSL draws no GREASE for application_settings, whose body
+/// only repeats ALPN protocol names, so Chrome never sends a GREASE value there.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GreaseValues {
+    pub cipher: u16,
+    pub group: u16, // también la entrada GREASE de key_share
+    pub extension1: u16,
+    pub extension2: u16,
+    pub version: u16,
+    pub sig_alg: u16,
+}
+
+/// GREASE value `0x?a?a` number `i` (0..16) of RFC 8701.
+pub fn grease_value(i: u32) -> u16 {
+    let b = ((i & 0x0f) << 4 | 0x0a) as u16;
+    (b << 8) | b
+}
+
+impl GreaseValues {
+    pub fn random(rng: &mut HelloRng) -> Self {
+        let mut draw = || grease_value(rng.below(16));
+        let (cipher, group, extension1, mut extension2, version, sig_alg) = (draw(), draw(), draw(), draw(), draw(), draw());
+        // BoringSSL: si coinciden, la segunda extensión se desplaza (0x?a?a ^ 0x1010)
+        if extension2 == extension1 { extension2 ^= 0x1010; }
+        GreaseValues { cipher, group, extension1, extension2, version, sig_alg }
+    }
+
+    pub fn from_pair(a: u16, b: u16) -> Self {
+        GreaseValues { cipher: a, group: a, extension1: a, extension2: b, version: a, sig_alg: a }
+    }
+}
+
+impl GreaseMode {
+    /// Values of the deterministic modes (`Fixed`, `PerField`).
+    pub fn fixed_values(&self) -> Option<GreaseValues> {
+        match *self {
+            GreaseMode::Fixed(a, b) => Some(GreaseValues::from_pair(a, b)),
+            GreaseMode::PerField(v) => Some(v),
+            GreaseMode::None | GreaseMode::Random => None,
+        }
+    }
+}
+
+/// Legacy pair form `(value, second extension value)`; `(0, 0)` for `None`.
+#[deprecated(note = "use grease_values, which keeps one value per field")]
 pub fn get_grease_pairs(mode: GreaseMode) -> (u16, u16) {
+    match grease_values(mode, &mut HelloRng::from_entropy()) {
+        Some(v) => (v.cipher, v.extension2),
+        None => (0, 0),
+    }
+}
+
+/// Values for one connection; `None` when the template's own values must be kept.
+pub fn grease_values(mode: GreaseMode, rng: &mut HelloRng) -> Option<GreaseValues> {
     match mode {
-        GreaseMode::Fixed(x, y) => (x, y), // e.g., 0x0a0a, 0x1a1a en tests
-        GreaseMode::Random => rand_grease_pair(), // Función para generar pares aleatorios
-        GreaseMode::None => (0,0), // No GREASE
+        GreaseMode::Random => Some(GreaseValues::random(rng)),
+        _ => mode.fixed_values(),
     }
 }
 
-// TODO: Implementar rand_grease_pair criptográficamente segura
-fn rand_grease_pair() -> (u16, u16) {
-    // Implementación placeholder
-    (0x0a0a, 0x1a1a) // Devolver valores fijos por ahora
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::is_grease;
+
+    #[test]
+    fn test_random_values_follow_rules() {
+        let mut rng = HelloRng::seeded(3);
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..500 {
+            let v = GreaseValues::random(&mut rng);
+            assert!([v.cipher, v.group, v.extension1, v.extension2, v.version, v.sig_alg].iter().all(|x| is_grease(*x)));
+            assert_ne!(v.extension1, v.extension2);
+            seen.insert(v.cipher);
+        }
+        assert_eq!(seen.len(), 16);
+        assert_eq!(grease_values(GreaseMode::None, &mut rng), None);
+        assert_eq!(grease_values(GreaseMode::Fixed(0x0a0a, 0x1a1a), &mut rng).unwrap().extension2, 0x1a1a);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_legacy_grease_pairs() {
+        assert_eq!(get_grease_pairs(GreaseMode::Fixed(0x0a0a, 0x1a1a)), (0x0a0a, 0x1a1a));
+        assert_eq!(get_grease_pairs(GreaseMode::None), (0, 0));
+        let (a, b) = get_grease_pairs(GreaseMode::Random);
+        assert!(is_grease(a) && is_grease(b));
+    }
 }
@@ -0,0 +1,378 @@
+// Plantillas de la capa HTTP (HTTP/2 y HTTP/3) que acompañan a un HelloTemplate: orden y valores
+// de SETTINGS, WINDOW_UPDATE, frames PRIORITY y orden de headers del primer request. Los encoders
+// emiten el preface/streams del cliente tal y como salen al cable tras el handshake.
+
+use serde::{Serialize, Deserialize};
+
+use crate::transport_params::write_varint;
+
+pub const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Pseudo-header values of the first request.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub scheme: String,
+    pub authority: String,
+    pub path: String,
+}
+
+impl HttpRequest {
+    /// `GET https://<authority>/`
+    pub fn get(authority: &str) -> Self {
+        HttpRequest { method: "GET".into(), scheme: "https".into(), authority: authority.into(), path: "/".into() }
+    }
+
+    fn pseudo_value(&self, name: &str) -> Result<&str, String> {
+        match name {
+            ":method" => Ok(&self.method),
+            ":scheme" => Ok(&self.scheme),
+            ":authority" => Ok(&self.authority),
+            ":path" => Ok(&self.path),
+            other => Err(format!("unsupported pseudo-header {}", other)),
+        }
+    }
+
+    // pseudo-headers en el orden de la plantilla seguidos de los headers normales
+    fn header_list(&self, pseudo_order: &[String], headers: &[(String, String)]) -> Result<Vec<(String, String)>, String> {
+        let mut out = Vec::with_capacity(pseudo_order.len() + headers.len());
+        for name in pseudo_order {
+            out.push((name.clone(), self.pseudo_value(name)?.to_string()));
+        }
+        out.extend(headers.iter().cloned());
+        Ok(out)
+    }
+}
+
+/// Stream dependency as sent in PRIORITY frames or in the HEADERS priority block.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct H2PriorityTemplate {
+    pub stream_id: u32,
+    pub exclusive: bool,
+    pub depends_on: u32,
+    pub weight: u16, // 1..=256 (el wire lleva weight - 1)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct H2Template {
+    pub settings: Vec<(u16, u32)>,                          // orden exacto
+    pub window_update: Option<u32>,                         // incremento de la ventana de conexión
+    #[serde(default)]
+    pub priorities: Vec<H2PriorityTemplate>,                // frames PRIORITY antes del request
+    #[serde(default)]
+    pub headers_priority: Option<H2PriorityTemplate>,       // stream_id se ignora: siempre 1
+    pub pseudo_header_order: Vec<String>,                   // e.g. [":method", ":authority", ":scheme", ":path"]
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,                     // headers normales, orden exacto
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct H3Template {
+    pub settings: Vec<(u64, u64)>,                          // orden exacto, GREASE incluido
+    #[serde(default)]
+    pub control_frames: Vec<(u64, Vec<u8>)>,                // frames extra tras SETTINGS (GREASE)
+    pub uni_streams: Vec<u64>,                              // tipos de stream unidireccional, en orden
+    pub pseudo_header_order: Vec<String>,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+}
+
+/// HTTP layer bundle stored next to a HelloTemplate.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HttpTemplate {
+    pub request: HttpRequest,
+    #[serde(default)]
+    pub h2: Option<H2Template>,
+    #[serde(default)]
+    pub h3: Option<H3Template>,
+}
+
+// Tamaño máximo de frame que el servidor acepta antes de anunciar el suyo (RFC 9113 §4.2).
+const H2_DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+
+fn h2_frame(out: &mut Vec<u8>, typ: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Result<(), String> {
+    if payload.len() >= 1 << 24 { return Err("HTTP/2 frame payload too large".into()); }
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+    out.push(typ);
+    out.push(flags);
+    out.extend_from_slice(&stream_id.to_be_bytes());
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+fn h2_priority_block(p: &H2PriorityTemplate) -> Result<[u8; 5], String> {
+    if !(1..=256).contains(&p.weight) { return Err(format!("HTTP/2 weight {} out of range 1..=256", p.weight)); }
+    let dep = (p.depends_on & 0x7fff_ffff) | if p.exclusive { 0x8000_0000 } else { 0 };
+    let d = dep.to_be_bytes();
+    Ok([d[0], d[1], d[2], d[3], (p.weight - 1) as u8])
+}
+
+/// Client bytes of an HTTP/2 connection: preface, SETTINGS, WINDOW_UPDATE, PRIORITY frames and the
+/// first request HEADERS (stream 1, END_STREAM) encoded with HPACK, continued in CONTINUATION
+/// frames when the block exceeds the default 16 KiB frame size.
+pub fn encode_h2_start(t: &H2Template, req: &HttpRequest) -> Result<Vec<u8>, String> {
+    let mut out = H2_PREFACE.to_vec();
+    let settings: Vec<u8> = t.settings.iter().flat_map(|(id, v)| id.to_be_bytes().into_iter().chain(v.to_be_bytes())).collect();
+    h2_frame(&mut out, 0x4, 0, 0, &settings)?;
+    if let Some(inc) = t.window_update {
+        h2_frame(&mut out, 0x8, 0, 0, &(inc & 0x7fff_ffff).to_be_bytes())?;
+    }
+    for p in &t.priorities {
+        h2_frame(&mut out, 0x2, 0, p.stream_id, &h2_priority_block(p)?)?;
+    }
+
+    let headers = req.header_list(&t.pseudo_header_order, &t.headers)?;
+    let block = hpack::Encoder::new().encode(headers.iter().map(|(n, v)| (n.as_bytes(), v.as_bytes())));
+    let mut payload = Vec::with_capacity(block.len() + 5);
+    let mut flags = 0x1; // END_STREAM
+    if let Some(p) = &t.headers_priority {
+        payload.extend_from_slice(&h2_priority_block(p)?);
+        flags |= 0x20;
+    }
+    payload.extend_from_slice(&block);
+    // bloque mayor que SETTINGS_MAX_FRAME_SIZE inicial: HEADERS + CONTINUATION, END_HEADERS solo en el último
+    let chunks: Vec<&[u8]> = payload.chunks(H2_DEFAULT_MAX_FRAME_SIZE).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let last = if i + 1 == chunks.len() { 0x4 } else { 0 };
+        if i == 0 {
+            h2_frame(&mut out, 0x1, flags | last, 1, chunk)?;
+        } else {
+            h2_frame(&mut out, 0x9, last, 1, chunk)?;
+        }
+    }
+    Ok(out)
+}
+
+fn h3_frame(out: &mut Vec<u8>, typ: u64, payload: &[u8]) {
+    write_varint(out, typ);
+    write_varint(out, payload.len() as u64);
+    out.extend_from_slice(payload);
+}
+
+// Entero con prefijo de N bits (RFC 7541 §5.1); `flags` son los bits por encima del prefijo.
+fn write_prefixed_int(out: &mut Vec<u8>, flags: u8, bits: u8, v: u64) {
+    let max = (1u64 << bits) - 1;
+    if v < max {
+        out.push(flags | v as u8);
+        return;
+    }
+    out.push(flags | max as u8);
+    let mut rest = v - max;
+    while rest >= 0x80 {
+        out.push((rest as u8 & 0x7f) | 0x80);
+        rest >>= 7;
+    }
+    out.push(rest as u8);
+}
+
+// Sección de campos QPACK solo con tabla estática (Required Insert Count 0, sin Huffman).
+fn qpack_field_section(headers: &[(String, String)]) -> Vec<u8> {
+    let mut out = vec![0x00, 0x00];
+    for (name, value) in headers {
+        let exact = QPACK_STATIC_TABLE.iter().position(|(n, v)| n == name && v == value);
+        let by_name = QPACK_STATIC_TABLE.iter().position(|(n, _)| n == name);
+        match (exact, by_name) {
+            (Some(idx), _) => write_prefixed_int(&mut out, 0xc0, 6, idx as u64),
+            (None, Some(idx)) => {
+                write_prefixed_int(&mut out, 0x50, 4, idx as u64);
+                write_prefixed_int(&mut out, 0x00, 7, value.len() as u64);
+                out.extend_from_slice(value.as_bytes());
+            }
+            (None, None) => {
+                write_prefixed_int(&mut out, 0x20, 3, name.len() as u64);
+                out.extend_from_slice(name.as_bytes());
+                write_prefixed_int(&mut out, 0x00, 7, value.len() as u64);
+                out.extend_from_slice(value.as_bytes());
+            }
+        }
+    }
+    out
+}
+
+/// Client streams of an HTTP/3 connection as (stream id, data): the unidirectional streams in
+/// template order (ids 2, 6, 10, ...) followed by the first request on stream 0.
+pub fn encode_h3_streams(t: &H3Template, req: &HttpRequest) -> Result<Vec<(u64, Vec<u8>)>, String> {
+    let mut streams = Vec::with_capacity(t.uni_streams.len() + 1);
+    for (i, typ) in t.uni_streams.iter().enumerate() {
+        let mut data = Vec::new();
+        write_varint(&mut data, *typ);
+        if *typ == 0x00 {
+            let mut settings = Vec::new();
+            for (id, v) in &t.settings {
+                write_varint(&mut settings, *id);
+                write_varint(&mut settings, *v);
+            }
+            h3_frame(&mut data, 0x04, &settings);
+            for (typ, payload) in &t.control_frames { h3_frame(&mut data, *typ, payload); }
+        }
+        streams.push((2 + 4 * i as u64, data));
+    }
+    if !t.uni_streams.contains(&0x00) { return Err("H3 template has no control stream".into()); }
+
+    let headers = req.header_list(&t.pseudo_header_order, &t.headers)?;
+    let mut request = Vec::new();
+    h3_frame(&mut request, 0x01, &qpack_field_section(&headers));
+    streams.push((0, request));
+    Ok(streams)
+}
+
+// RFC 9204 Appendix A
+pub const QPACK_STATIC_TABLE: [(&str, &str); 99] = [
+    (":authority", ""),
+    (":path", "/"),
+    ("age", "0"),
+    ("content-disposition", ""),
+    ("content-length", "0"),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("referer", ""),
+    ("set-cookie", ""),
+    (":method", "CONNECT"),
+    (":method", "DELETE"),
+    (":method", "GET"),
+    (":method", "HEAD"),
+    (":method", "OPTIONS"),
+    (":method", "POST"),
+    (":method", "PUT"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "103"),
+    (":status", "200"),
+    (":status", "304"),
+    (":status", "404"),
+    (":status", "503"),
+    ("accept", "*/*"),
+    ("accept", "application/dns-message"),
+    ("accept-encoding", "gzip, deflate, br"),
+    ("accept-ranges", "bytes"),
+    ("access-control-allow-headers", "cache-control"),
+    ("access-control-allow-headers", "content-type"),
+    ("access-control-allow-origin", "*"),
+    ("cache-control", "max-age=0"),
+    ("cache-control", "max-age=2592000"),
+    ("cache-control", "max-age=604800"),
+    ("cache-control", "no-cache"),
+    ("cache-control", "no-store"),
+    ("cache-control", "public, max-age=31536000"),
+    ("content-encoding", "br"),
+    ("content-encoding", "gzip"),
+    ("content-type", "application/dns-message"),
+    ("content-type", "application/javascript"),
+    ("content-type", "application/json"),
+    ("content-type", "application/x-www-form-urlencoded"),
+    ("content-type", "image/gif"),
+    ("content-type", "image/jpeg"),
+    ("content-type", "image/png"),
+    ("content-type", "text/css"),
+    ("content-type", "text/html; charset=utf-8"),
+    ("content-type", "text/plain"),
+    ("content-type", "text/plain;charset=utf-8"),
+    ("range", "bytes=0-"),
+    ("strict-transport-security", "max-age=31536000"),
+    ("strict-transport-security", "max-age=31536000; includesubdomains"),
+    ("strict-transport-security", "max-age=31536000; includesubdomains; preload"),
+    ("vary", "accept-encoding"),
+    ("vary", "origin"),
+    ("x-content-type-options", "nosniff"),
+    ("x-xss-protection", "1; mode=block"),
+    (":status", "100"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "302"),
+    (":status", "400"),
+    (":status", "403"),
+    (":status", "421"),
+    (":status", "425"),
+    (":status", "500"),
+    ("accept-language", ""),
+    ("access-control-allow-credentials", "FALSE"),
+    ("access-control-allow-credentials", "TRUE"),
+    ("access-control-allow-headers", "*"),
+    ("access-control-allow-methods", "get"),
+    ("access-control-allow-methods", "get, post, options"),
+    ("access-control-allow-methods", "options"),
+    ("access-control-expose-headers", "content-length"),
+    ("access-control-request-headers", "content-type"),
+    ("access-control-request-method", "get"),
+    ("access-control-request-method", "post"),
+    ("alt-svc", "clear"),
+    ("authorization", ""),
+    ("content-security-policy", "script-src 'none'; object-src 'none'; base-uri 'none'"),
+    ("early-data", "1"),
+    ("expect-ct", ""),
+    ("forwarded", ""),
+    ("if-range", ""),
+    ("origin", ""),
+    ("purpose", "prefetch"),
+    ("server", ""),
+    ("timing-allow-origin", "*"),
+    ("upgrade-insecure-requests", "1"),
+    ("user-agent", ""),
+    ("x-forwarded-for", ""),
+    ("x-frame-options", "deny"),
+    ("x-frame-options", "sameorigin"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_h2_start_layout() {
+        let t = H2Template {
+            settings: vec![(1, 65536), (2, 0), (4, 6_291_456), (6, 262_144)],
+            window_update: Some(15_663_105),
+            priorities: vec![],
+            headers_priority: Some(H2PriorityTemplate { stream_id: 1, exclusive: true, depends_on: 0, weight: 256 }),
+            pseudo_header_order: vec![":method".into(), ":authority".into(), ":scheme".into(), ":path".into()],
+            headers: vec![("user-agent".into(), "x".into())],
+        };
+        let raw = encode_h2_start(&t, &HttpRequest::get("example.com")).unwrap();
+        let settings_at = H2_PREFACE.len();
+        assert_eq!(&raw[settings_at..settings_at + 5], &[0, 0, 24, 0x04, 0]);
+        let window_at = settings_at + 9 + 24;
+        assert_eq!(&raw[window_at..window_at + 13], &[0, 0, 4, 0x08, 0, 0, 0, 0, 0, 0x00, 0xef, 0x00, 0x01]);
+        let headers_at = window_at + 13;
+        assert_eq!(&raw[headers_at + 3..headers_at + 14], &[0x01, 0x25, 0, 0, 0, 1, 0x80, 0, 0, 0, 0xff]);
+        assert_eq!(raw[headers_at + 14], 0x82); // :method GET indexado en la tabla estática HPACK
+
+        let mut bad = t.clone();
+        bad.pseudo_header_order.push(":protocol".into());
+        assert!(encode_h2_start(&bad, &HttpRequest::get("example.com")).is_err());
+    }
+
+    #[test]
+    fn test_encode_h2_start_splits_large_header_block() {
+        let t = H2Template {
+            settings: vec![],
+            window_update: None,
+            priorities: vec![],
+            headers_priority: None,
+            pseudo_header_order: vec![":method".into(), ":authority".into(), ":scheme".into(), ":path".into()],
+            headers: vec![("cookie".into(), "a".repeat(40_000))],
+        };
+        let raw = encode_h2_start(&t, &HttpRequest::get("example.com")).unwrap();
+        let mut pos = H2_PREFACE.len() + 9; // SETTINGS vacío
+        let (mut frames, mut block) = (Vec::new(), Vec::new());
+        while pos < raw.len() {
+            let len = u32::from_be_bytes([0, raw[pos], raw[pos + 1], raw[pos + 2]]) as usize;
+            assert!(len <= H2_DEFAULT_MAX_FRAME_SIZE);
+            assert_eq!(&raw[pos + 5..pos + 9], &1u32.to_be_bytes());
+            frames.push((raw[pos + 3], raw[pos + 4]));
+            block.extend_from_slice(&raw[pos + 9..pos + 9 + len]);
+            pos += 9 + len;
+        }
+        assert!(frames.len() >= 2);
+        assert_eq!(frames[0], (0x1, 0x1));
+        assert!(frames[1..frames.len() - 1].iter().all(|f| *f == (0x9, 0)));
+        assert_eq!(frames[frames.len() - 1], (0x9, 0x4));
+        let headers = hpack::Decoder::new().decode(&block).unwrap();
+        assert_eq!(headers.last().unwrap().1.len(), 40_000);
+    }
+}
@@ -0,0 +1,147 @@
+// Generación de key shares reales para el ClientHello. La parte privada se devuelve para que
+// quien use el hello pueda terminar el handshake (shared_secret con la key_share del servidor).
+
+use ml_kem::kem::{Decapsulate, DecapsulationKey};
+use ml_kem::{EncodedSizeUser, KemCore, MlKem768, MlKem768Params};
+use serde::{Serialize, Deserialize};
+
+use crate::rng::HelloRng;
+
+pub const GROUP_SECP256R1: u16 = 0x0017;
+pub const GROUP_SECP384R1: u16 = 0x0018;
+pub const GROUP_X25519: u16 = 0x001d;
+pub const GROUP_X25519_MLKEM768: u16 = 0x11ec;
+
+const X25519_LEN: usize = 32;
+const MLKEM768_CIPHERTEXT_LEN: usize = 1088;
+
+/// Whether `generate_key_share` can produce keys for `group`.
+pub fn is_supported_group(group: u16) -> bool {
+    matches!(group, GROUP_SECP256R1 | GROUP_SECP384R1 | GROUP_X25519 | GROUP_X25519_MLKEM768)
+}
+
+/// Private half of a generated key share.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct KeySharePrivate {
+    pub group: u16,
+    /// X25519 / ECDH scalar; for X25519MLKEM768 the encoded ML-KEM decapsulation key
+    /// followed by the X25519 scalar.
+    pub private: Vec<u8>,
+}
+
+impl std::fmt::Debug for KeySharePrivate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "KeySharePrivate {{ group: {:#06x}, .. }}", self.group)
+    }
+}
+
+pub(crate) fn x25519_public(secret: &[u8; 32]) -> Vec<u8> {
+    x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(*secret)).as_bytes().to_vec()
+}
+
+fn x25519_shared(secret: &[u8], peer: &[u8]) -> Result<Vec<u8>, String> {
+    let secret: [u8; 32] = secret.try_into().map_err(|_| "bad X25519 scalar")?;
+    let peer: [u8; 32] = peer.try_into().map_err(|_| "X25519 share must be 32 bytes")?;
+    let shared = x25519_dalek::StaticSecret::from(secret).diffie_hellman(&x25519_dalek::PublicKey::from(peer));
+    if !shared.was_contributory() { return Err("X25519 shared secret is all zeros".into()); }
+    Ok(shared.as_bytes().to_vec())
+}
+
+/// Fresh key pair for `group`: (key_exchange bytes for the ClientHello, private half).
+/// Public encodings follow RFC 8446 (uncompressed SEC1 points for the NIST curves) and
+/// draft-ietf-tls-ecdhe-mlkem (ML-KEM-768 encapsulation key, then the X25519 share).
+pub fn generate_key_share(group: u16, rng: &mut HelloRng) -> Result<(Vec<u8>, KeySharePrivate), String> {
+    let (public, private) = match group {
+        GROUP_X25519 => {
+            let mut secret = [0u8; X25519_LEN];
+            rng.fill(&mut secret);
+            (x25519_public(&secret), secret.to_vec())
+        }
+        GROUP_SECP256R1 => {
+            let secret = p256::SecretKey::random(rng);
+            (p256::EncodedPoint::from(secret.public_key()).as_bytes().to_vec(), secret.to_bytes().to_vec())
+        }
+        GROUP_SECP384R1 => {
+            let secret = p384::SecretKey::random(rng);
+            (p384::EncodedPoint::from(secret.public_key()).as_bytes().to_vec(), secret.to_bytes().to_vec())
+        }
+        GROUP_X25519_MLKEM768 => {
+            let (dk, ek) = MlKem768::generate(rng);
+            let mut secret = [0u8; X25519_LEN];
+            rng.fill(&mut secret);
+            let mut public = ek.as_bytes().to_vec();
+            public.extend_from_slice(&x25519_public(&secret));
+            let mut private = dk.as_bytes().to_vec();
+            private.extend_from_slice(&secret);
+            (public, private)
+        }
+        _ => return Err(format!("key share generation not supported for group {:#06x}", group)),
+    };
+    Ok((public, KeySharePrivate { group, private }))
+}
+
+impl KeySharePrivate {
+    /// Shared secret with the server's key_share for the same group (the TLS 1.3 (EC)DHE input;
+    /// for X25519MLKEM768 the ML-KEM secret followed by the X25519 one).
+    pub fn shared_secret(&self, server_share: &[u8]) -> Result<Vec<u8>, String> {
+        match self.group {
+            GROUP_X25519 => x25519_shared(&self.private, server_share),
+            GROUP_SECP256R1 => {
+                let secret = p256::SecretKey::from_slice(&self.private).map_err(|e| e.to_string())?;
+                let peer = p256::PublicKey::from_sec1_bytes(server_share).map_err(|e| format!("bad P-256 share: {}", e))?;
+                Ok(p256::ecdh::diffie_hellman(secret.to_nonzero_scalar(), peer.as_affine()).raw_secret_bytes().to_vec())
+            }
+            GROUP_SECP384R1 => {
+                let secret = p384::SecretKey::from_slice(&self.private).map_err(|e| e.to_string())?;
+                let peer = p384::PublicKey::from_sec1_bytes(server_share).map_err(|e| format!("bad P-384 share: {}", e))?;
+                Ok(p384::ecdh::diffie_hellman(secret.to_nonzero_scalar(), peer.as_affine()).raw_secret_bytes().to_vec())
+            }
+            GROUP_X25519_MLKEM768 => {
+                if server_share.len() != MLKEM768_CIPHERTEXT_LEN + X25519_LEN {
+                    return Err("X25519MLKEM768 server share must be 1120 bytes".into());
+                }
+                let (ct, x_peer) = server_share.split_at(MLKEM768_CIPHERTEXT_LEN);
+                let (dk_bytes, x_secret) = self.private.split_at(self.private.len() - X25519_LEN);
+                let dk_bytes = dk_bytes.try_into().map_err(|_| "bad ML-KEM decapsulation key")?;
+                let dk = DecapsulationKey::<MlKem768Params>::from_bytes(dk_bytes);
+                let ct = ct.try_into().map_err(|_| "bad ML-KEM ciphertext")?;
+                let mut shared = dk.decapsulate(ct).map_err(|_| "ML-KEM decapsulation failed")?.to_vec();
+                shared.extend_from_slice(&x25519_shared(x_secret, x_peer)?);
+                Ok(shared)
+            }
+            g => Err(format!("unsupported group {:#06x}", g)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ml_kem::kem::{Encapsulate, EncapsulationKey};
+
+    #[test]
+    fn test_key_exchange_with_server() {
+        let mut rng = HelloRng::seeded(11);
+        for group in [GROUP_X25519, GROUP_SECP256R1, GROUP_SECP384R1] {
+            let (client_pub, client) = generate_key_share(group, &mut rng).unwrap();
+            let (server_pub, server) = generate_key_share(group, &mut rng).unwrap();
+            assert_eq!(client_pub.len(), [32, 65, 97][[GROUP_X25519, GROUP_SECP256R1, GROUP_SECP384R1].iter().position(|g| *g == group).unwrap()]);
+            assert_eq!(client.shared_secret(&server_pub).unwrap(), server.shared_secret(&client_pub).unwrap());
+        }
+
+        // servidor híbrido: encapsula contra la clave ML-KEM y manda su share X25519 detrás
+        let (client_pub, client) = generate_key_share(GROUP_X25519_MLKEM768, &mut rng).unwrap();
+        assert_eq!(client_pub.len(), 1184 + 32);
+        let ek = EncapsulationKey::<MlKem768Params>::from_bytes(client_pub[..1184].try_into().unwrap());
+        let (ct, ss) = ek.encapsulate(&mut rng).unwrap();
+        let (x_pub, x_server) = generate_key_share(GROUP_X25519, &mut rng).unwrap();
+        let mut server_share = ct.to_vec();
+        server_share.extend_from_slice(&x_pub);
+        let mut expected = ss.to_vec();
+        expected.extend_from_slice(&x_server.shared_secret(&client_pub[1184..]).unwrap());
+        assert_eq!(client.shared_secret(&server_share).unwrap(), expected);
+
+        assert!(generate_key_share(0x0100, &mut rng).is_err());
+        assert_eq!(generate_key_share(GROUP_X25519, &mut HelloRng::seeded(1)).unwrap().0, generate_key_share(GROUP_X25519, &mut HelloRng::seeded(1)).unwrap().0);
+    }
+}
@@ -1,42 +1,168 @@
 pub mod export_utls;
 pub mod tls_record;
+pub mod decode;
+pub mod transport_params;
+pub mod quic_initial;
+pub mod http_template;
+pub mod key_share;
+pub mod pcap_writer;
+pub mod rng;
+pub mod ech;
+pub mod psk;
 
 use serde::{Serialize, Deserialize};
-use std::time::SystemTime;
 
 pub mod grease;
 use grease::GreaseMode;
+use rng::HelloRng;
+use transport_params::TransportParam;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct HelloTemplate {
     pub tls_version: u16,                // e.g., TLS1_3 (0x0304)
+    #[serde(default)]
+    pub record_version: Option<u16>,     // versión de la capa record (None → 0x0303; Chrome usa 0x0301)
+    #[serde(default)]
+    pub legacy_version: Option<u16>,     // legacy_version del ClientHello (None → 0x0303)
     pub client_random: Option<[u8; 32]>,
     pub session_id: Option<Vec<u8>>,
     pub cipher_suites: Vec<u16>,                // orden exacto
+    #[serde(default)]
+    pub cipher_grease_slots: Vec<u16>,          // índices GREASE dentro de cipher_suites
     pub compression_methods: Vec<u8>,
     pub extensions: Vec<Extension>,             // orden exacto
+    #[serde(default)]
+    pub no_extensions_block: bool,              // hello sin bloque de extensiones (solo si `extensions` está vacía)
     pub grease_mode: GreaseMode,
     pub psk_key_exchange_modes: Option<Vec<u8>>, // Para 0-RTT/PSK modes
 }
 
-#[derive(Serialize, Deserialize)]
+/// Empty TLS 1.3 hello: null compression and nothing else. Builders and tests set the fields
+/// they care about with `..Default::default()`.
+impl Default for HelloTemplate {
+    fn default() -> Self {
+        HelloTemplate {
+            tls_version: 0x0304,
+            record_version: None,
+            legacy_version: None,
+            client_random: None,
+            session_id: None,
+            cipher_suites: Vec::new(),
+            cipher_grease_slots: Vec::new(),
+            compression_methods: vec![0],
+            extensions: Vec::new(),
+            no_extensions_block: false,
+            grease_mode: GreaseMode::None,
+            psk_key_exchange_modes: None,
+        }
+    }
+}
+
+// `grease_slots` are indices into the sibling list whose entry is a GREASE value.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum Extension {
     ServerName { host: String },
-    SupportedVersions { versions: Vec<u16> },
+    SupportedVersions { versions: Vec<u16>, #[serde(default)] grease_slots: Vec<u16> },
     SupportedGroups { groups: Vec<u16>, grease_slots: Vec<u16> },
-    SignatureAlgorithms { algs: Vec<u16> },
-    KeyShare { shares: Vec<(u16 /*group*/, Vec<u8> /*key*/)> },
+    SignatureAlgorithms { algs: Vec<u16>, #[serde(default)] grease_slots: Vec<u16> },
+    KeyShare { shares: Vec<(u16 /*group*/, Vec<u8> /*key*/)>, #[serde(default)] grease_slots: Vec<u16> },
     Alpn { protocols: Vec<String> },
     Padding { len: usize }, // Ahora determinista
-    EchOuterStub { config_id: Vec<u8> }, // ECH outer / grease
-    ApplicationSettings { protocols: Vec<String>, data: Vec<u8> }, // ALPS
+    BoringPadding, // longitud según el tamaño final del hello (ver `boring_padding_len`)
+    // ECH outer, real o GREASE; `sealed` solo lo pone `ech::encrypt_client_hello` (en el cable no se distinguen)
+    Ech { kdf_id: u16, aead_id: u16, config_id: u8, enc: Vec<u8>, payload: Vec<u8>, #[serde(default)] sealed: bool },
+    EchGrease { kdf_id: u16, aead_id: u16 }, // GREASE ECH: `instantiate` sortea config_id, enc y payload
+    EchInner, // marca del ClientHelloInner
+    ApplicationSettings { protocols: Vec<String>, #[serde(default)] codepoint: AlpsCodepoint }, // ALPS
     PskKeyExchangeModes { modes: Vec<u8> }, // PSK modes
+    QuicTransportParameters { params: Vec<TransportParam> }, // orden exacto, GREASE incluido
+    ExtendedMasterSecret,
+    RenegotiationInfo { renegotiated_connection: Vec<u8> }, // vacío en el primer handshake
+    StatusRequest { status_type: u8, responder_id_list: Vec<u8>, request_extensions: Vec<u8> }, // 1 = OCSP
+    SignedCertificateTimestamp,
+    SessionTicket { ticket: Vec<u8> },
+    CompressCertificate { algorithms: Vec<u16> }, // 1 zlib, 2 brotli, 3 zstd
+    RecordSizeLimit { limit: u16 },
+    DelegatedCredentials { algs: Vec<u16> },
+    EarlyData,
+    PreSharedKey { identities: Vec<PskIdentity>, binders: Vec<Vec<u8>> }, // siempre la última
+    Cookie { cookie: Vec<u8> },
+    PostHandshakeAuth,
+    EncryptThenMac,
+    Grease { typ: u16, bytes: Vec<u8> }, // extensión GREASE (tipo 0x?a?a)
     Unknown { typ: u16, bytes: Vec<u8> },
 }
 
+/// Codepoint of the ALPS (application_settings) extension. Chrome used the draft value
+/// 0x4469 until it switched to 0x44cd; the body is the same list of ALPN names in both.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlpsCodepoint {
+    #[default]
+    Old, // 0x4469
+    New, // 0x44cd
+}
+
+impl AlpsCodepoint {
+    pub const OLD: u16 = 0x4469;
+    pub const NEW: u16 = 0x44cd;
+
+    pub fn value(self) -> u16 {
+        match self {
+            AlpsCodepoint::Old => Self::OLD,
+            AlpsCodepoint::New => Self::NEW,
+        }
+    }
+}
+
+/// One offered PSK identity (RFC 8446 §4.2.11).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PskIdentity {
+    pub identity: Vec<u8>,
+    pub obfuscated_ticket_age: u32,
+}
+
+impl Extension {
+    /// Codepoint on the wire for this extension.
+    pub fn ext_type(&self) -> u16 {
+        match self {
+            Extension::ServerName { .. } => 0x0000,
+            Extension::SupportedVersions { .. } => 0x002b,
+            Extension::SupportedGroups { .. } => 0x000a,
+            Extension::SignatureAlgorithms { .. } => 0x000d,
+            Extension::KeyShare { .. } => 0x0033,
+            Extension::Alpn { .. } => 0x0010,
+            Extension::Padding { .. } | Extension::BoringPadding => 0x0015,
+            Extension::Ech { .. } | Extension::EchGrease { .. } | Extension::EchInner => ech::ECH_VERSION,
+            Extension::ApplicationSettings { codepoint, .. } => codepoint.value(),
+            Extension::PskKeyExchangeModes { .. } => 0x002d,
+            Extension::QuicTransportParameters { .. } => 0x0039,
+            Extension::ExtendedMasterSecret => 0x0017,
+            Extension::RenegotiationInfo { .. } => 0xff01,
+            Extension::StatusRequest { .. } => 0x0005,
+            Extension::SignedCertificateTimestamp => 0x0012,
+            Extension::SessionTicket { .. } => 0x0023,
+            Extension::CompressCertificate { .. } => 0x001b,
+            Extension::RecordSizeLimit { .. } => 0x001c,
+            Extension::DelegatedCredentials { .. } => 0x0022,
+            Extension::EarlyData => 0x002a,
+            Extension::PreSharedKey { .. } => 0x0029,
+            Extension::Cookie { .. } => 0x002c,
+            Extension::PostHandshakeAuth => 0x0031,
+            Extension::EncryptThenMac => 0x0016,
+            Extension::Grease { typ, .. } | Extension::Unknown { typ, .. } => *typ,
+        }
+    }
+}
+
+/// Standard GREASE values (RFC 8701): 0x0a0a, 0x1a1a, ..., 0xfafa.
+pub fn is_grease(v: u16) -> bool {
+    (v & 0x0f0f) == 0x0a0a && (v >> 8) == (v & 0xff)
+}
+
 pub struct EncodedClientHello {
     pub raw_bytes: Vec<u8>,
     pub pcap_bytes: Option<Vec<u8>>,
+    pub key_shares: Vec<key_share::KeySharePrivate>, // solo si salen de Encoder::generate
 }
 
 fn write_u16_be(buf: &mut Vec<u8>, v: u16) {
@@ -48,21 +174,214 @@ fn write_u24_be(buf: &mut Vec<u8>, v: usize) {
     buf.push(((v >> 8) & 0xff) as u8);
     buf.push((v & 0xff) as u8);
 }
-fn write_u32_le(buf: &mut Vec<u8>, v: u32) {
-    buf.extend_from_slice(&v.to_le_bytes());
+
+// Escribe `v` en los grease_slots de la plantilla y en los tipos de las extensiones GREASE
+// (la primera con extension1, las siguientes con extension2).
+fn apply_grease(t: &mut HelloTemplate, v: &grease::GreaseValues) {
+    fn set_slots(list: &mut [u16], slots: &[u16], v: u16) {
+        for i in slots {
+            if let Some(x) = list.get_mut(*i as usize) { *x = v; }
+        }
+    }
+    set_slots(&mut t.cipher_suites, &t.cipher_grease_slots, v.cipher);
+    let mut grease_exts = 0;
+    for ext in &mut t.extensions {
+        match ext {
+            Extension::SupportedVersions { versions, grease_slots } => set_slots(versions, grease_slots, v.version),
+            Extension::SupportedGroups { groups, grease_slots } => set_slots(groups, grease_slots, v.group),
+            Extension::SignatureAlgorithms { algs, grease_slots } => set_slots(algs, grease_slots, v.sig_alg),
+            Extension::KeyShare { shares, grease_slots } => {
+                for i in grease_slots.iter() {
+                    if let Some((g, _)) = shares.get_mut(*i as usize) { *g = v.group; }
+                }
+            }
+            Extension::Grease { typ, .. } => {
+                *typ = if grease_exts == 0 { v.extension1 } else { v.extension2 };
+                grease_exts += 1;
+            }
+            _ => {}
+        }
+    }
 }
-fn write_u16_le(buf: &mut Vec<u8>, v: u16) {
-    buf.extend_from_slice(&v.to_le_bytes());
+
+/// BoringSSL's padding rule (the F5 workaround): a ClientHello whose handshake message would be
+/// 256..=511 bytes without the padding extension is padded up to 512, always with at least one
+/// byte of body. `None` means the extension is omitted.
+pub fn boring_padding_len(unpadded_len: usize) -> Option<usize> {
+    if !(0x100..0x200).contains(&unpadded_len) { return None; }
+    let len = 0x200 - unpadded_len;
+    // la cabecera de la extensión (4 bytes) cuenta dentro de los 512
+    Some(if len > 4 { len - 4 } else { 1 })
 }
 
 pub struct Encoder;
 impl Encoder {
-    pub fn encode_client_hello(t: &HelloTemplate, emit_pcap: bool) -> Result<EncodedClientHello, String> {
+    /// Encodes a single extension (type, length and body) and appends it to `out`.
+    /// `BoringPadding` depends on the whole hello: here it is written empty, `encode_handshake`
+    /// gives it its real length.
+    pub fn encode_extension(ext: &Extension, out: &mut Vec<u8>) {
+        let mut data: Vec<u8> = Vec::new();
+        match ext {
+            Extension::ServerName { host } => {
+                // build server name list: name type 0, length, host
+                let host_bytes = host.as_bytes();
+                let mut names: Vec<u8> = Vec::new();
+                names.push(0u8);
+                write_u16_be(&mut names, host_bytes.len() as u16);
+                names.extend_from_slice(host_bytes);
+                // overall list length
+                write_u16_be(&mut data, names.len() as u16);
+                data.extend_from_slice(&names);
+            }
+            Extension::SupportedVersions { versions, .. } => {
+                // data: uint8 length then list of uint16
+                data.push((versions.len() * 2) as u8);
+                for v in versions { write_u16_be(&mut data, *v); }
+            }
+            Extension::SupportedGroups { groups, .. } => {
+                write_u16_be(&mut data, (groups.len() * 2) as u16);
+                for g in groups { write_u16_be(&mut data, *g); }
+            }
+            Extension::SignatureAlgorithms { algs, .. } => {
+                write_u16_be(&mut data, (algs.len() * 2) as u16);
+                for a in algs { write_u16_be(&mut data, *a); }
+            }
+            Extension::KeyShare { shares, .. } => {
+                let mut list: Vec<u8> = Vec::new();
+                for (group, key) in shares {
+                    write_u16_be(&mut list, *group);
+                    write_u16_be(&mut list, key.len() as u16);
+                    list.extend_from_slice(key);
+                }
+                write_u16_be(&mut data, list.len() as u16);
+                data.extend_from_slice(&list);
+            }
+            Extension::Alpn { protocols } => {
+                // alpn protocols: length-prefixed vector
+                let mut protos: Vec<u8> = Vec::new();
+                for p in protocols {
+                    let pb = p.as_bytes();
+                    protos.push(pb.len() as u8);
+                    protos.extend_from_slice(pb);
+                }
+                write_u16_be(&mut data, protos.len() as u16);
+                data.extend_from_slice(&protos);
+            }
+            Extension::Padding { len } => {
+                data.resize(*len, 0u8);
+            }
+            Extension::Ech { kdf_id, aead_id, config_id, enc, payload, .. } => {
+                data.push(0); // outer
+                write_u16_be(&mut data, *kdf_id);
+                write_u16_be(&mut data, *aead_id);
+                data.push(*config_id);
+                write_u16_be(&mut data, enc.len() as u16);
+                data.extend_from_slice(enc);
+                write_u16_be(&mut data, payload.len() as u16);
+                data.extend_from_slice(payload);
+            }
+            Extension::EchGrease { kdf_id, aead_id } => {
+                // sin instanciar: tamaño de un GREASE mínimo, todo a ceros
+                let placeholder = Extension::Ech { kdf_id: *kdf_id, aead_id: *aead_id, config_id: 0, enc: vec![0; 32], payload: vec![0; 128 + 16], sealed: false };
+                let mut wire = Vec::new();
+                Self::encode_extension(&placeholder, &mut wire);
+                data.extend_from_slice(&wire[4..]);
+            }
+            Extension::EchInner => data.push(1),
+            Extension::ApplicationSettings { protocols, .. } => {
+                // solo la lista de nombres ALPN (u16 de longitud + nombres con u8), sin settings
+                let mut protos: Vec<u8> = Vec::new();
+                for p in protocols { let pb=p.as_bytes(); protos.push(pb.len() as u8); protos.extend_from_slice(pb); }
+                write_u16_be(&mut data, protos.len() as u16);
+                data.extend_from_slice(&protos);
+            }
+            Extension::PskKeyExchangeModes { modes } => {
+                data.push(modes.len() as u8);
+                data.extend_from_slice(modes);
+            }
+            Extension::QuicTransportParameters { params } => {
+                data = transport_params::encode_transport_params(params);
+            }
+            Extension::Grease { bytes, .. } | Extension::Unknown { bytes, .. } => {
+                data.extend_from_slice(bytes);
+            }
+            Extension::ExtendedMasterSecret
+            | Extension::BoringPadding
+            | Extension::SignedCertificateTimestamp
+            | Extension::EarlyData
+            | Extension::PostHandshakeAuth
+            | Extension::EncryptThenMac => {}
+            Extension::RenegotiationInfo { renegotiated_connection } => {
+                data.push(renegotiated_connection.len() as u8);
+                data.extend_from_slice(renegotiated_connection);
+            }
+            Extension::StatusRequest { status_type, responder_id_list, request_extensions } => {
+                data.push(*status_type);
+                write_u16_be(&mut data, responder_id_list.len() as u16);
+                data.extend_from_slice(responder_id_list);
+                write_u16_be(&mut data, request_extensions.len() as u16);
+                data.extend_from_slice(request_extensions);
+            }
+            Extension::SessionTicket { ticket } => {
+                data.extend_from_slice(ticket);
+            }
+            Extension::CompressCertificate { algorithms } => {
+                data.push((algorithms.len() * 2) as u8);
+                for a in algorithms { write_u16_be(&mut data, *a); }
+            }
+            Extension::RecordSizeLimit { limit } => {
+                write_u16_be(&mut data, *limit);
+            }
+            Extension::DelegatedCredentials { algs } => {
+                write_u16_be(&mut data, (algs.len() * 2) as u16);
+                for a in algs { write_u16_be(&mut data, *a); }
+            }
+            Extension::PreSharedKey { identities, binders } => {
+                let mut ids: Vec<u8> = Vec::new();
+                for id in identities {
+                    write_u16_be(&mut ids, id.identity.len() as u16);
+                    ids.extend_from_slice(&id.identity);
+                    ids.extend_from_slice(&id.obfuscated_ticket_age.to_be_bytes());
+                }
+                write_u16_be(&mut data, ids.len() as u16);
+                data.extend_from_slice(&ids);
+                let mut list: Vec<u8> = Vec::new();
+                for b in binders {
+                    list.push(b.len() as u8);
+                    list.extend_from_slice(b);
+                }
+                write_u16_be(&mut data, list.len() as u16);
+                data.extend_from_slice(&list);
+            }
+            Extension::Cookie { cookie } => {
+                write_u16_be(&mut data, cookie.len() as u16);
+                data.extend_from_slice(cookie);
+            }
+        }
+        write_u16_be(out, ext.ext_type());
+        write_u16_be(out, data.len() as u16);
+        out.extend_from_slice(&data);
+    }
+
+    /// Encodes the ClientHello handshake message (type 1 + u24 length + body), without record header.
+    pub fn encode_handshake(t: &HelloTemplate) -> Vec<u8> {
+        // Fixed/PerField: los valores del modo van en los slots; None y Random (sin instanciar)
+        // codifican la plantilla tal cual
+        let greased;
+        let t = match t.grease_mode.fixed_values() {
+            Some(v) => {
+                let mut g = t.clone();
+                apply_grease(&mut g, &v);
+                greased = g;
+                &greased
+            }
+            None => t,
+        };
         // Build handshake body (ClientHello)
         let mut body: Vec<u8> = Vec::new();
 
         // legacy_version (2 bytes) - use 0x0303 for compatibility
-        write_u16_be(&mut body, 0x0303);
+        write_u16_be(&mut body, t.legacy_version.unwrap_or(0x0303));
 
         // random (32 bytes)
         if let Some(r) = &t.client_random {
@@ -90,171 +409,121 @@ impl Encoder {
         body.push(t.compression_methods.len() as u8);
         body.extend_from_slice(&t.compression_methods);
 
-        // extensions - prepare extensions payload first
-        let mut exts: Vec<u8> = Vec::new();
-        for ext in &t.extensions {
-            match ext {
-                Extension::ServerName { host } => {
-                    // ext type 0x0000
-                    write_u16_be(&mut exts, 0x0000);
-                    // build server name list
-                    let mut data: Vec<u8> = Vec::new();
-                    // name type 0, length, host
-                    let host_bytes = host.as_bytes();
-                    let mut names: Vec<u8> = Vec::new();
-                    names.push(0u8);
-                    write_u16_be(&mut names, host_bytes.len() as u16);
-                    names.extend_from_slice(host_bytes);
-                    // overall list length
-                    write_u16_be(&mut data, names.len() as u16);
-                    data.extend_from_slice(&names);
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::SupportedVersions { versions } => {
-                    write_u16_be(&mut exts, 0x002b);
-                    // data: uint8 length then list of uint16
-                    let mut data: Vec<u8> = Vec::new();
-                    data.push((versions.len() * 2) as u8);
-                    for v in versions { write_u16_be(&mut data, *v); }
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::SupportedGroups { groups, .. } => {
-                    write_u16_be(&mut exts, 0x000a);
-                    let mut data: Vec<u8> = Vec::new();
-                    write_u16_be(&mut data, (groups.len() * 2) as u16);
-                    for g in groups { write_u16_be(&mut data, *g); }
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::SignatureAlgorithms { algs } => {
-                    write_u16_be(&mut exts, 0x000d);
-                    let mut data: Vec<u8> = Vec::new();
-                    write_u16_be(&mut data, (algs.len() * 2) as u16);
-                    for a in algs { write_u16_be(&mut data, *a); }
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::KeyShare { shares } => {
-                    write_u16_be(&mut exts, 0x0033);
-                    let mut data: Vec<u8> = Vec::new();
-                    // list length placeholder
-                    let mut list: Vec<u8> = Vec::new();
-                    for (group, key) in shares {
-                        write_u16_be(&mut list, *group);
-                        write_u16_be(&mut list, key.len() as u16);
-                        list.extend_from_slice(key);
-                    }
-                    write_u16_be(&mut data, list.len() as u16);
-                    data.extend_from_slice(&list);
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::Alpn { protocols } => {
-                    write_u16_be(&mut exts, 0x0010);
-                    let mut data: Vec<u8> = Vec::new();
-                    // alpn protocols: length-prefixed vector
-                    let mut protos: Vec<u8> = Vec::new();
-                    for p in protocols {
-                        let pb = p.as_bytes();
-                        protos.push(pb.len() as u8);
-                        protos.extend_from_slice(pb);
-                    }
-                    write_u16_be(&mut data, protos.len() as u16);
-                    data.extend_from_slice(&protos);
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::Padding { len } => {
-                    write_u16_be(&mut exts, 0x0015);
-                    let mut data: Vec<u8> = vec![0u8; *len];
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::EchOuterStub { config_id } => {
-                    write_u16_be(&mut exts, 0xfe0d);
-                    let mut data: Vec<u8> = Vec::new();
-                    data.extend_from_slice(config_id);
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::ApplicationSettings { protocols, data } => {
-                    // Use extension type 0xff01 as placeholder for ALPS (implementation specific)
-                    write_u16_be(&mut exts, 0xff01);
-                    let mut d: Vec<u8> = Vec::new();
-                    // encode protocols as simple list
-                    let mut protos: Vec<u8> = Vec::new();
-                    for p in protocols { let pb=p.as_bytes(); protos.push(pb.len() as u8); protos.extend_from_slice(pb); }
-                    write_u16_be(&mut d, protos.len() as u16);
-                    d.extend_from_slice(&protos);
-                    d.extend_from_slice(data);
-                    write_u16_be(&mut exts, d.len() as u16);
-                    exts.extend_from_slice(&d);
-                }
-                Extension::PskKeyExchangeModes { modes } => {
-                    write_u16_be(&mut exts, 0x002d);
-                    let mut data: Vec<u8> = Vec::new();
-                    data.push(modes.len() as u8);
-                    data.extend_from_slice(modes);
-                    write_u16_be(&mut exts, data.len() as u16);
-                    exts.extend_from_slice(&data);
-                }
-                Extension::Unknown { typ, bytes } => {
-                    write_u16_be(&mut exts, *typ);
-                    write_u16_be(&mut exts, bytes.len() as u16);
-                    exts.extend_from_slice(bytes);
-                }
-            }
+        // extensions - prepare extensions payload first. Con BoringPadding se codifica primero
+        // sin ella para conocer el tamaño sin padding (cabecera 4 + body + u16 de longitud)
+        let mut exts = Self::encode_extensions(&t.extensions, None);
+        if t.extensions.contains(&Extension::BoringPadding) {
+            exts = Self::encode_extensions(&t.extensions, boring_padding_len(4 + body.len() + 2 + exts.len()));
         }
 
-        // write extensions length
-        write_u16_be(&mut body, exts.len() as u16);
-        body.extend_from_slice(&exts);
+        // write extensions length; un hello pre-TLS 1.3 capturado puede no llevar el bloque
+        if !(t.no_extensions_block && t.extensions.is_empty()) {
+            write_u16_be(&mut body, exts.len() as u16);
+            body.extend_from_slice(&exts);
+        }
 
-        // Now wrap body into Handshake record
+        // Now wrap body into Handshake message
         let mut handshake: Vec<u8> = Vec::new();
         // Handshake header: type (1 = ClientHello)
         handshake.push(0x01u8);
         // length 3 bytes
         write_u24_be(&mut handshake, body.len());
         handshake.extend_from_slice(&body);
+        handshake
+    }
+
+    // `BoringPadding` becomes `padding` bytes of padding, or is left out when `None`.
+    fn encode_extensions(exts: &[Extension], padding: Option<usize>) -> Vec<u8> {
+        let mut out = Vec::new();
+        for ext in exts {
+            match (ext, padding) {
+                (Extension::BoringPadding, Some(len)) => Self::encode_extension(&Extension::Padding { len }, &mut out),
+                (Extension::BoringPadding, None) => {}
+                _ => Self::encode_extension(ext, &mut out),
+            }
+        }
+        out
+    }
+
+    /// Encodes the ClientHello as the client's encrypted QUIC Initial datagrams (CRYPTO frames,
+    /// header protection, padding to `opts.datagram_size`).
+    pub fn encode_quic_initial(t: &HelloTemplate, opts: &quic_initial::QuicInitialOptions) -> Result<Vec<Vec<u8>>, String> {
+        quic_initial::encode_quic_initials(&Self::encode_handshake(t), opts)
+    }
+
+    /// Per-connection copy of `t`: fresh random, session ID (same length), key shares and GREASE
+    /// ECH payload (`EchGrease` becomes a concrete `Ech`), and, with `GreaseMode::Random`, newly drawn GREASE values (recorded as `GreaseMode::PerField`).
+    /// Everything else is kept as is. A seeded `rng` makes the result reproducible;
+    /// `HelloRng::from_entropy` gives what a real client would send.
+    pub fn instantiate(t: &HelloTemplate, rng: &mut HelloRng) -> HelloTemplate {
+        Self::instantiate_with_keys(t, rng).0
+    }
+
+    /// `instantiate`, also returning the private halves of the generated key shares (in the
+    /// order of the key_share extension). Groups without key generation keep the template's
+    /// bytes and get no private half.
+    pub fn instantiate_with_keys(t: &HelloTemplate, rng: &mut HelloRng) -> (HelloTemplate, Vec<key_share::KeySharePrivate>) {
+        let mut out = t.clone();
+        let mut random = [0u8; 32];
+        rng.fill(&mut random);
+        out.client_random = Some(random);
+        if let Some(sid) = &mut out.session_id {
+            rng.fill(sid);
+        }
+        let mut privates = Vec::new();
+        for ext in &mut out.extensions {
+            if let Extension::KeyShare { shares, grease_slots } = ext {
+                for (i, (group, key)) in shares.iter_mut().enumerate() {
+                    if grease_slots.contains(&(i as u16)) { continue; }
+                    // grupo sin generación de claves: el share de la plantilla se queda tal cual
+                    if let Ok((public, private)) = key_share::generate_key_share(*group, rng) {
+                        *key = public;
+                        privates.push(private);
+                    }
+                }
+            }
+            if let Extension::EchGrease { kdf_id, aead_id } = *ext {
+                *ext = ech::grease_ech(kdf_id, aead_id, rng);
+            }
+        }
+        if let Some(v) = grease::grease_values(t.grease_mode, rng) {
+            out.grease_mode = GreaseMode::PerField(v);
+            apply_grease(&mut out, &v);
+        }
+        (out, privates)
+    }
+
+    /// `instantiate` + record encoding; the pcap (if any) uses `pcap`'s addresses and clock.
+    pub fn generate(t: &HelloTemplate, rng: &mut HelloRng, pcap: Option<&pcap_writer::PcapOptions>) -> Result<EncodedClientHello, String> {
+        let (hello, key_shares) = Self::instantiate_with_keys(t, rng);
+        let mut enc = Self::encode_client_hello(&hello, false)?;
+        enc.key_shares = key_shares;
+        if let Some(opts) = pcap {
+            enc.pcap_bytes = Some(pcap_writer::tcp_pcap(opts, &[(quic_initial::Direction::Client, &enc.raw_bytes)])?);
+        }
+        Ok(enc)
+    }
+
+    pub fn encode_client_hello(t: &HelloTemplate, emit_pcap: bool) -> Result<EncodedClientHello, String> {
+        let handshake = Self::encode_handshake(t);
 
         // TLS record header
         let mut record: Vec<u8> = Vec::new();
         record.push(22u8); // Handshake
-        write_u16_be(&mut record, 0x0303); // version
+        write_u16_be(&mut record, t.record_version.unwrap_or(0x0303)); // version
         write_u16_be(&mut record, handshake.len() as u16);
         record.extend_from_slice(&handshake);
 
         let encoded_bytes = record;
 
-        // optionally produce pcap manually (little-endian pcap global header + packets)
-        let mut pcap_bytes: Option<Vec<u8>> = None;
-        if emit_pcap {
-            let mut pcap: Vec<u8> = Vec::new();
-            // pcap global header (little-endian: 0xd4c3b2a1)
-            write_u32_le(&mut pcap, 0xd4c3b2a1);
-            write_u16_le(&mut pcap, 2); // version major
-            write_u16_le(&mut pcap, 4); // version minor
-            write_u32_le(&mut pcap, 0); // thiszone
-            write_u32_le(&mut pcap, 0); // sigfigs
-            write_u32_le(&mut pcap, 65535); // snaplen
-            write_u32_le(&mut pcap, 1); // network (LINKTYPE_ETHERNET)
-
-            let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_err(|e| format!("ts failed: {}", e))?;
-            let sec = now.as_secs() as u32;
-            let usec = now.subsec_micros() as u32;
-            write_u32_le(&mut pcap, sec);
-            write_u32_le(&mut pcap, usec);
-            write_u32_le(&mut pcap, encoded_bytes.len() as u32);
-            write_u32_le(&mut pcap, encoded_bytes.len() as u32);
-            pcap.extend_from_slice(&encoded_bytes);
-
-            pcap_bytes = Some(pcap);
-        }
+        // opcionalmente, una conexión TCP sintética (handshake + ClientHello) con direcciones y reloj fijos
+        let pcap_bytes = if emit_pcap {
+            Some(pcap_writer::tcp_pcap(&pcap_writer::PcapOptions::default(), &[(quic_initial::Direction::Client, &encoded_bytes)])?)
+        } else {
+            None
+        };
 
-        Ok(EncodedClientHello { raw_bytes: encoded_bytes, pcap_bytes })
+        Ok(EncodedClientHello { raw_bytes: encoded_bytes, pcap_bytes, key_shares: Vec::new() })
     }
 }
 
@@ -267,12 +536,8 @@ mod tests {
         let template = HelloTemplate {
             tls_version: 0x0303,
             client_random: Some([0; 32]),
-            session_id: None,
             cipher_suites: vec![0x1301], // TLS_AES_128_GCM_SHA256
-            compression_methods: vec![0],
-            extensions: vec![],
-            grease_mode: GreaseMode::None,
-            psk_key_exchange_modes: None,
+            ..Default::default()
         };
 
         let result = Encoder::encode_client_hello(&template, false);
@@ -280,4 +545,59 @@ mod tests {
         let encoded = result.unwrap();
         assert!(!encoded.raw_bytes.is_empty());
     }
+
+    #[test]
+    fn test_boring_padding_follows_hello_size() {
+        let hello = |host: &str| HelloTemplate {
+            record_version: Some(0x0301),
+            client_random: Some([0; 32]),
+            session_id: Some(vec![0; 32]),
+            cipher_suites: vec![0x1301; 100],
+            extensions: vec![Extension::ServerName { host: host.into() }, Extension::BoringPadding],
+            ..Default::default()
+        };
+        // sin padding: 4 + 2 + 32 + 33 + 202 + 2 + 2 + (9 + host); dentro del rango siempre 512
+        for host in ["a.example", "a-much-longer-hostname.example.com"] {
+            let hs = Encoder::encode_handshake(&hello(host));
+            assert_eq!(hs.len(), 512);
+            assert_eq!(decode::decode_client_hello(&hs).unwrap().extensions[1], Extension::BoringPadding);
+        }
+        // 508 sin padding: cuerpo mínimo de 1 byte
+        assert_eq!(Encoder::encode_handshake(&hello(&"a".repeat(222))).len(), 513);
+        // fuera de 256..512 la extensión no se envía
+        assert_eq!(Encoder::encode_handshake(&hello(&"a".repeat(300))).len(), 586);
+        assert_eq!(boring_padding_len(255), None);
+        assert_eq!(boring_padding_len(256), Some(252));
+    }
+
+    #[test]
+    fn test_instantiate_is_seeded() {
+        let template = HelloTemplate {
+            session_id: Some(vec![0; 32]),
+            cipher_suites: vec![0x0a0a, 0x1301],
+            cipher_grease_slots: vec![0],
+            extensions: vec![
+                Extension::Grease { typ: 0x0a0a, bytes: vec![] },
+                Extension::KeyShare { shares: vec![(0x0a0a, vec![0]), (0x001d, vec![0; 32]), (0x0101, vec![0xff; 8])], grease_slots: vec![0] },
+                Extension::Grease { typ: 0x0a0a, bytes: vec![0] },
+            ],
+            grease_mode: GreaseMode::Random,
+            ..Default::default()
+        };
+        let a = Encoder::instantiate(&template, &mut HelloRng::seeded(1));
+        assert_eq!(a, Encoder::instantiate(&template, &mut HelloRng::seeded(1)));
+        assert_ne!(a, Encoder::instantiate(&template, &mut HelloRng::seeded(2)));
+        assert_ne!(a.session_id, template.session_id);
+        let (Extension::Grease { typ: first, .. }, Extension::KeyShare { shares, .. }, Extension::Grease { typ: second, .. }) = (&a.extensions[0], &a.extensions[1], &a.extensions[2]) else { panic!() };
+        assert!(is_grease(*first) && is_grease(*second) && first != second);
+        let GreaseMode::PerField(v) = a.grease_mode else { panic!() };
+        assert_eq!((shares[0].0, shares[0].1.len(), shares[1].1.len()), (v.group, 1, 32));
+        // grupo sin generación de claves (ffdhe3072): los bytes de la plantilla, sin clave privada
+        assert_eq!(shares[2].1, vec![0xff; 8]);
+        assert_eq!(Encoder::instantiate_with_keys(&template, &mut HelloRng::seeded(1)).1.len(), 1);
+        assert_eq!((a.cipher_suites[0], *first, *second), (v.cipher, v.extension1, v.extension2));
+        // la plantilla instanciada se codifica igual que la vuelta por el decoder
+        let raw = Encoder::encode_handshake(&a);
+        assert_eq!(Encoder::encode_handshake(&decode::decode_client_hello(&raw).unwrap()), raw);
+    }
 }
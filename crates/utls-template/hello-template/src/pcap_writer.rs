@@ -0,0 +1,317 @@
+// Writer de pcap (formato clásico, LINKTYPE_ETHERNET) con tramas completas: Ethernet + IPv4/IPv6 +
+// TCP (three-way handshake, números de secuencia y checksums) o UDP. Los timestamps salen de
+// `start_us` + `step_us` por paquete, así que la salida es reproducible byte a byte.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use serde::{Serialize, Deserialize};
+
+pub use crate::quic_initial::Direction;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+/// Addresses and clock of a synthetic capture.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PcapOptions {
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    pub client_mac: [u8; 6],
+    pub server_mac: [u8; 6],
+    pub start_us: u64,   // timestamp del primer paquete (µs desde epoch)
+    pub step_us: u64,    // separación entre paquetes consecutivos
+    pub client_isn: u32, // números de secuencia iniciales
+    pub server_isn: u32,
+    pub mss: u16,        // tamaño máximo de segmento TCP
+}
+
+impl Default for PcapOptions {
+    fn default() -> Self {
+        PcapOptions {
+            client: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 50000),
+            server: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 443),
+            client_mac: [0x02, 0x00, 0x00, 0x00, 0x00, 0x01],
+            server_mac: [0x02, 0x00, 0x00, 0x00, 0x00, 0x02],
+            start_us: 1_700_000_000_000_000,
+            step_us: 1_000,
+            client_isn: 0x1000_0000,
+            server_isn: 0x2000_0000,
+            mss: 1460,
+        }
+    }
+}
+
+/// Incremental pcap builder for one client/server conversation.
+pub struct PcapWriter {
+    opts: PcapOptions,
+    out: Vec<u8>,
+    packets: u64,
+    ip_id: u16,
+    client_seq: u32, // siguiente byte a enviar por cada lado
+    server_seq: u32,
+}
+
+// Suma de complemento a uno (RFC 1071).
+fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    for chunk in chunks {
+        let mut it = chunk.chunks_exact(2);
+        for w in &mut it { sum += u16::from_be_bytes([w[0], w[1]]) as u32; }
+        if let [b] = it.remainder() { sum += (*b as u32) << 8; }
+    }
+    while sum >> 16 != 0 { sum = (sum & 0xffff) + (sum >> 16); }
+    !(sum as u16)
+}
+
+impl PcapWriter {
+    pub fn new(opts: PcapOptions) -> Result<Self, String> {
+        if opts.client.is_ipv4() != opts.server.is_ipv4() { return Err("client and server must use the same IP version".into()); }
+        if opts.mss == 0 { return Err("mss must be positive".into()); }
+        let mut out = Vec::new();
+        out.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes()); // magic (µs)
+        out.extend_from_slice(&2u16.to_le_bytes()); // version major
+        out.extend_from_slice(&4u16.to_le_bytes()); // version minor
+        out.extend_from_slice(&0u32.to_le_bytes()); // thiszone
+        out.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+        out.extend_from_slice(&65535u32.to_le_bytes()); // snaplen
+        out.extend_from_slice(&1u32.to_le_bytes()); // LINKTYPE_ETHERNET
+        Ok(PcapWriter { client_seq: opts.client_isn, server_seq: opts.server_isn, opts, out, packets: 0, ip_id: 1 })
+    }
+
+    fn endpoints(&self, dir: Direction) -> (SocketAddr, SocketAddr, [u8; 6], [u8; 6]) {
+        match dir {
+            Direction::Client => (self.opts.client, self.opts.server, self.opts.client_mac, self.opts.server_mac),
+            Direction::Server => (self.opts.server, self.opts.client, self.opts.server_mac, self.opts.client_mac),
+        }
+    }
+
+    fn pseudo_header(src: IpAddr, dst: IpAddr, proto: u8, len: usize) -> Vec<u8> {
+        let mut p = Vec::with_capacity(40);
+        match (src, dst) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                p.extend_from_slice(&s.octets());
+                p.extend_from_slice(&d.octets());
+                p.extend_from_slice(&[0, proto]);
+                p.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            (IpAddr::V6(s), IpAddr::V6(d)) => {
+                p.extend_from_slice(&s.octets());
+                p.extend_from_slice(&d.octets());
+                p.extend_from_slice(&(len as u32).to_be_bytes());
+                p.extend_from_slice(&[0, 0, 0, proto]);
+            }
+            _ => unreachable!("IP versions checked in PcapWriter::new"),
+        }
+        p
+    }
+
+    // Ethernet + IP alrededor de un segmento de transporte con el checksum en `csum_at`.
+    fn emit(&mut self, dir: Direction, proto: u8, mut l4: Vec<u8>, csum_at: usize) -> Result<(), String> {
+        let (src, dst, src_mac, dst_mac) = self.endpoints(dir);
+        // el campo de longitud IP es de 16 bits: total (IPv4) o payload (IPv6)
+        let ip_len = if src.is_ipv4() { 20 + l4.len() } else { l4.len() };
+        if ip_len > 0xffff { return Err(format!("IP datagram too large ({ip_len} bytes)")); }
+        let csum = checksum(&[&Self::pseudo_header(src.ip(), dst.ip(), proto, l4.len()), &l4]);
+        // UDP: un checksum 0 significa "sin checksum", se transmite como 0xffff
+        let csum = if proto == PROTO_UDP && csum == 0 { 0xffff } else { csum };
+        l4[csum_at..csum_at + 2].copy_from_slice(&csum.to_be_bytes());
+
+        let mut frame = Vec::with_capacity(14 + 40 + l4.len());
+        frame.extend_from_slice(&dst_mac);
+        frame.extend_from_slice(&src_mac);
+        match (src.ip(), dst.ip()) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+                let mut ip = vec![0x45, 0];
+                ip.extend_from_slice(&((20 + l4.len()) as u16).to_be_bytes());
+                ip.extend_from_slice(&self.ip_id.to_be_bytes());
+                ip.extend_from_slice(&[0x40, 0x00, 64, proto, 0, 0]); // DF, TTL 64
+                ip.extend_from_slice(&s.octets());
+                ip.extend_from_slice(&d.octets());
+                let c = checksum(&[&ip]);
+                ip[10..12].copy_from_slice(&c.to_be_bytes());
+                frame.extend_from_slice(&ip);
+                self.ip_id = self.ip_id.wrapping_add(1);
+            }
+            (IpAddr::V6(s), IpAddr::V6(d)) => {
+                frame.extend_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+                frame.extend_from_slice(&[0x60, 0, 0, 0]);
+                frame.extend_from_slice(&(l4.len() as u16).to_be_bytes());
+                frame.extend_from_slice(&[proto, 64]);
+                frame.extend_from_slice(&s.octets());
+                frame.extend_from_slice(&d.octets());
+            }
+            _ => unreachable!("IP versions checked in PcapWriter::new"),
+        }
+        frame.extend_from_slice(&l4);
+
+        let ts = self.opts.start_us + self.packets * self.opts.step_us;
+        self.out.extend_from_slice(&((ts / 1_000_000) as u32).to_le_bytes());
+        self.out.extend_from_slice(&((ts % 1_000_000) as u32).to_le_bytes());
+        self.out.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.out.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.out.extend_from_slice(&frame);
+        self.packets += 1;
+        Ok(())
+    }
+
+    fn tcp_segment(&mut self, dir: Direction, flags: u8, payload: &[u8]) -> Result<(), String> {
+        let (src, dst, _, _) = self.endpoints(dir);
+        let (seq, ack) = match dir {
+            Direction::Client => (self.client_seq, self.server_seq),
+            Direction::Server => (self.server_seq, self.client_seq),
+        };
+        let syn = flags & TCP_SYN != 0;
+        let mut tcp = Vec::with_capacity(24 + payload.len());
+        tcp.extend_from_slice(&src.port().to_be_bytes());
+        tcp.extend_from_slice(&dst.port().to_be_bytes());
+        tcp.extend_from_slice(&seq.to_be_bytes());
+        tcp.extend_from_slice(&if flags & TCP_ACK != 0 { ack } else { 0 }.to_be_bytes());
+        tcp.push(if syn { 0x60 } else { 0x50 }); // data offset: 24 bytes con la opción MSS
+        tcp.push(flags);
+        tcp.extend_from_slice(&[0xff, 0xff, 0, 0, 0, 0]); // ventana, checksum, urgent
+        if syn {
+            tcp.extend_from_slice(&[0x02, 0x04]);
+            tcp.extend_from_slice(&self.opts.mss.to_be_bytes());
+        }
+        tcp.extend_from_slice(payload);
+        self.emit(dir, PROTO_TCP, tcp, 16)?;
+
+        // SYN y FIN consumen un número de secuencia
+        let used = payload.len() as u32 + (flags & (TCP_SYN | TCP_FIN) != 0) as u32;
+        match dir {
+            Direction::Client => self.client_seq = self.client_seq.wrapping_add(used),
+            Direction::Server => self.server_seq = self.server_seq.wrapping_add(used),
+        }
+        Ok(())
+    }
+
+    /// SYN, SYN-ACK, ACK.
+    pub fn tcp_handshake(&mut self) -> Result<(), String> {
+        self.tcp_segment(Direction::Client, TCP_SYN, &[])?;
+        self.tcp_segment(Direction::Server, TCP_SYN | TCP_ACK, &[])?;
+        self.tcp_segment(Direction::Client, TCP_ACK, &[])
+    }
+
+    /// Sends `data` from `dir` in MSS-sized PSH/ACK segments.
+    pub fn tcp_data(&mut self, dir: Direction, data: &[u8]) -> Result<(), String> {
+        for chunk in data.chunks(self.opts.mss as usize) {
+            self.tcp_segment(dir, TCP_PSH | TCP_ACK, chunk)?;
+        }
+        Ok(())
+    }
+
+    pub fn udp_datagram(&mut self, dir: Direction, data: &[u8]) -> Result<(), String> {
+        if data.len() > 0xffff - 8 { return Err("UDP payload too large".into()); }
+        let (src, dst, _, _) = self.endpoints(dir);
+        let mut udp = Vec::with_capacity(8 + data.len());
+        udp.extend_from_slice(&src.port().to_be_bytes());
+        udp.extend_from_slice(&dst.port().to_be_bytes());
+        udp.extend_from_slice(&((8 + data.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(data);
+        self.emit(dir, PROTO_UDP, udp, 6)
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+/// TCP conversation: three-way handshake followed by each flight in order.
+pub fn tcp_pcap(opts: &PcapOptions, flights: &[(Direction, &[u8])]) -> Result<Vec<u8>, String> {
+    let mut w = PcapWriter::new(opts.clone())?;
+    w.tcp_handshake()?;
+    for (dir, data) in flights { w.tcp_data(*dir, data)?; }
+    Ok(w.finish())
+}
+
+/// UDP conversation: one datagram per entry (e.g. QUIC Initials).
+pub fn udp_pcap(opts: &PcapOptions, datagrams: &[(Direction, &[u8])]) -> Result<Vec<u8>, String> {
+    let mut w = PcapWriter::new(opts.clone())?;
+    for (dir, data) in datagrams { w.udp_datagram(*dir, data)?; }
+    Ok(w.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet_packet::ethernet::EthernetPacket;
+    use pnet_packet::ipv4::{self, Ipv4Packet};
+    use pnet_packet::ipv6::Ipv6Packet;
+    use pnet_packet::tcp::{self, TcpPacket};
+    use pnet_packet::udp::{self, UdpPacket};
+    use pnet_packet::Packet;
+
+    // (timestamp µs, trama) de cada paquete
+    fn packets(pcap: &[u8]) -> Vec<(u64, Vec<u8>)> {
+        let mut out = Vec::new();
+        let mut pos = 24;
+        while pos < pcap.len() {
+            let w = |i: usize| u32::from_le_bytes(pcap[pos + i..pos + i + 4].try_into().unwrap()) as u64;
+            let (ts, len) = (w(0) * 1_000_000 + w(4), w(8) as usize);
+            out.push((ts, pcap[pos + 16..pos + 16 + len].to_vec()));
+            pos += 16 + len;
+        }
+        out
+    }
+
+    #[test]
+    fn test_tcp_and_udp_frames_are_valid() {
+        let opts = PcapOptions { mss: 100, ..Default::default() };
+        let hello = vec![0x16u8; 250];
+        let pcap = tcp_pcap(&opts, &[(Direction::Client, &hello), (Direction::Server, &[0x16; 10])]).unwrap();
+        assert_eq!(pcap, tcp_pcap(&opts, &[(Direction::Client, &hello), (Direction::Server, &[0x16; 10])]).unwrap());
+        let pkts = packets(&pcap);
+        assert_eq!(pkts.len(), 3 + 3 + 1);
+        assert_eq!(pkts[6].0, opts.start_us + 6 * opts.step_us);
+
+        let mut next_seq = opts.client_isn + 1;
+        for (i, (_, frame)) in pkts.iter().enumerate() {
+            let eth = EthernetPacket::new(frame).unwrap();
+            let ip = Ipv4Packet::new(eth.payload()).unwrap();
+            assert_eq!(ip.get_checksum(), ipv4::checksum(&ip));
+            let seg = TcpPacket::new(ip.payload()).unwrap();
+            assert_eq!(seg.get_checksum(), tcp::ipv4_checksum(&seg, &ip.get_source(), &ip.get_destination()));
+            if (3..6).contains(&i) {
+                assert_eq!((seg.get_sequence(), seg.get_acknowledgement()), (next_seq, opts.server_isn + 1));
+                next_seq += seg.payload().len() as u32;
+            }
+        }
+        assert_eq!(TcpPacket::new(Ipv4Packet::new(EthernetPacket::new(&pkts[6].1).unwrap().payload()).unwrap().payload()).unwrap().get_acknowledgement(), next_seq);
+
+        let v6 = PcapOptions { client: "[2001:db8::1]:40000".parse().unwrap(), server: "[2001:db8::2]:443".parse().unwrap(), ..Default::default() };
+        let pkts = packets(&udp_pcap(&v6, &[(Direction::Client, &[0xc0; 1200])]).unwrap());
+        let eth = EthernetPacket::new(&pkts[0].1).unwrap();
+        let ip = Ipv6Packet::new(eth.payload()).unwrap();
+        let dg = UdpPacket::new(ip.payload()).unwrap();
+        assert_eq!((dg.get_length(), dg.payload().len()), (1208, 1200));
+        assert_eq!(dg.get_checksum(), udp::ipv6_checksum(&dg, &ip.get_source(), &ip.get_destination()));
+
+        let mixed = PcapOptions { server: "[2001:db8::2]:443".parse().unwrap(), ..Default::default() };
+        assert!(PcapWriter::new(mixed).is_err());
+    }
+
+    #[test]
+    fn test_ip_length_limits() {
+        // IPv4: 20 (IP) + 8 (UDP) + payload ≤ 65535
+        let v4 = PcapOptions::default();
+        assert!(udp_pcap(&v4, &[(Direction::Client, &vec![0; 65507])]).is_ok());
+        assert!(udp_pcap(&v4, &[(Direction::Client, &vec![0; 65508])]).is_err());
+        // IPv6: la cabecera fija no cuenta en la longitud del payload
+        let v6 = PcapOptions { client: "[2001:db8::1]:40000".parse().unwrap(), server: "[2001:db8::2]:443".parse().unwrap(), ..Default::default() };
+        assert!(udp_pcap(&v6, &[(Direction::Client, &vec![0; 65527])]).is_ok());
+        // TCP: 20 (IP) + 20 (TCP) + mss ≤ 65535
+        let data = vec![0x16; 70_000];
+        assert!(tcp_pcap(&PcapOptions { mss: 65495, ..Default::default() }, &[(Direction::Client, &data)]).is_ok());
+        assert!(tcp_pcap(&PcapOptions { mss: 65496, ..Default::default() }, &[(Direction::Client, &data)]).is_err());
+    }
+}
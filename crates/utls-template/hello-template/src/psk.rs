@@ -0,0 +1,244 @@
+// Reanudación TLS 1.3 con PSK (RFC 8446 §4.2.11): identidades a partir de tickets, binders sobre
+// el ClientHello truncado y, con early_data, el secreto de tráfico 0-RTT del cliente.
+
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::{Encoder, Extension, HelloTemplate, PskIdentity};
+
+/// A session ticket kept from an earlier connection.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ResumptionTicket {
+    /// Opaque ticket from NewSessionTicket, sent as the PSK identity.
+    pub ticket: Vec<u8>,
+    /// resumption_master_secret of the connection that issued the ticket.
+    pub resumption_secret: Vec<u8>,
+    #[serde(default)]
+    pub ticket_nonce: Vec<u8>,
+    #[serde(default)]
+    pub ticket_age_add: u32,
+    /// Milliseconds since the ticket was received.
+    #[serde(default)]
+    pub ticket_age_ms: u32,
+    /// Cipher suite of the issuing connection; it fixes the hash (SHA-384 for 0x1302).
+    pub cipher_suite: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HashAlg {
+    Sha256,
+    Sha384,
+}
+
+impl HashAlg {
+    fn for_suite(suite: u16) -> Result<Self, String> {
+        match suite {
+            0x1301 | 0x1303 | 0x1304 | 0x1305 => Ok(HashAlg::Sha256),
+            0x1302 => Ok(HashAlg::Sha384),
+            s => Err(format!("not a TLS 1.3 cipher suite: {:#06x}", s)),
+        }
+    }
+
+    fn len(self) -> usize {
+        match self { HashAlg::Sha256 => 32, HashAlg::Sha384 => 48 }
+    }
+
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlg::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlg::Sha384 => Sha384::digest(data).to_vec(),
+        }
+    }
+
+    fn hmac(self, key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(match self {
+            HashAlg::Sha256 => {
+                let mut m = Hmac::<Sha256>::new_from_slice(key).map_err(|e| e.to_string())?;
+                m.update(data);
+                m.finalize().into_bytes().to_vec()
+            }
+            HashAlg::Sha384 => {
+                let mut m = Hmac::<Sha384>::new_from_slice(key).map_err(|e| e.to_string())?;
+                m.update(data);
+                m.finalize().into_bytes().to_vec()
+            }
+        })
+    }
+
+    // HKDF-Extract con salt a ceros, como el Early Secret
+    fn extract(self, ikm: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlg::Sha256 => Hkdf::<Sha256>::extract(None, ikm).0.to_vec(),
+            HashAlg::Sha384 => Hkdf::<Sha384>::extract(None, ikm).0.to_vec(),
+        }
+    }
+
+    // HKDF-Expand-Label (RFC 8446 §7.1)
+    fn expand_label(self, secret: &[u8], label: &str, context: &[u8]) -> Result<Vec<u8>, String> {
+        let full_label = format!("tls13 {}", label);
+        let mut info = (self.len() as u16).to_be_bytes().to_vec();
+        info.push(full_label.len() as u8);
+        info.extend_from_slice(full_label.as_bytes());
+        info.push(context.len() as u8);
+        info.extend_from_slice(context);
+        let mut out = vec![0u8; self.len()];
+        match self {
+            HashAlg::Sha256 => Hkdf::<Sha256>::from_prk(secret).map_err(|_| "hkdf prk too short")?.expand(&info, &mut out),
+            HashAlg::Sha384 => Hkdf::<Sha384>::from_prk(secret).map_err(|_| "hkdf prk too short")?.expand(&info, &mut out),
+        }
+        .map_err(|_| "hkdf expand error")?;
+        Ok(out)
+    }
+
+    fn derive_secret(self, secret: &[u8], label: &str, messages: &[u8]) -> Result<Vec<u8>, String> {
+        self.expand_label(secret, label, &self.digest(messages))
+    }
+}
+
+impl ResumptionTicket {
+    fn hash(&self) -> Result<HashAlg, String> {
+        HashAlg::for_suite(self.cipher_suite)
+    }
+
+    /// The PSK: HKDF-Expand-Label(resumption_secret, "resumption", ticket_nonce, Hash.length).
+    pub fn psk(&self) -> Result<Vec<u8>, String> {
+        self.hash()?.expand_label(&self.resumption_secret, "resumption", &self.ticket_nonce)
+    }
+
+    pub fn obfuscated_ticket_age(&self) -> u32 {
+        self.ticket_age_ms.wrapping_add(self.ticket_age_add)
+    }
+
+    fn early_secret(&self) -> Result<Vec<u8>, String> {
+        Ok(self.hash()?.extract(&self.psk()?))
+    }
+
+    // HMAC(finished_key de "res binder", Transcript-Hash(ClientHello truncado))
+    fn binder(&self, truncated_hello: &[u8]) -> Result<Vec<u8>, String> {
+        let h = self.hash()?;
+        let binder_key = h.derive_secret(&self.early_secret()?, "res binder", b"")?;
+        let finished_key = h.expand_label(&binder_key, "finished", b"")?;
+        h.hmac(&finished_key, &h.digest(truncated_hello))
+    }
+}
+
+/// A resumption ClientHello.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResumedHello {
+    pub hello: HelloTemplate,
+    /// client_early_traffic_secret of the first ticket, when early_data is offered.
+    pub client_early_traffic_secret: Option<Vec<u8>>,
+}
+
+/// Turns an instantiated `hello` into a resumption hello offering `tickets`: pre_shared_key is
+/// (re)written as the last extension, early_data is added ahead of any padding or removed, and
+/// the binders are computed over the hello truncated before the binder list. Fails if `hello`
+/// has no psk_key_exchange_modes extension (servers must reject a PSK without it).
+pub fn resume(hello: &HelloTemplate, tickets: &[ResumptionTicket], early_data: bool) -> Result<ResumedHello, String> {
+    if tickets.is_empty() { return Err("no tickets to resume with".into()); }
+    if !hello.extensions.iter().any(|e| matches!(e, Extension::PskKeyExchangeModes { .. })) {
+        return Err("pre_shared_key needs a psk_key_exchange_modes extension".into());
+    }
+    let hashes = tickets.iter().map(|t| t.hash()).collect::<Result<Vec<_>, _>>()?;
+
+    let mut out = hello.clone();
+    out.extensions.retain(|e| !matches!(e, Extension::PreSharedKey { .. }));
+    if !early_data {
+        out.extensions.retain(|e| *e != Extension::EarlyData);
+    } else if !out.extensions.contains(&Extension::EarlyData) {
+        // early_data antes del padding: el padding queda siempre justo antes del PSK
+        let at = out.extensions.iter().position(|e| matches!(e, Extension::Padding { .. } | Extension::BoringPadding))
+            .unwrap_or(out.extensions.len());
+        out.extensions.insert(at, Extension::EarlyData);
+    }
+    // binders a ceros con su longitud final, para que el truncado tenga las longitudes buenas
+    out.extensions.push(Extension::PreSharedKey {
+        identities: tickets.iter().map(|t| PskIdentity { identity: t.ticket.clone(), obfuscated_ticket_age: t.obfuscated_ticket_age() }).collect(),
+        binders: hashes.iter().map(|h| vec![0u8; h.len()]).collect(),
+    });
+
+    let handshake = Encoder::encode_handshake(&out);
+    let binders_len = 2 + hashes.iter().map(|h| 1 + h.len()).sum::<usize>();
+    let truncated = &handshake[..handshake.len() - binders_len];
+    let binders = tickets.iter().map(|t| t.binder(truncated)).collect::<Result<Vec<_>, _>>()?;
+    if let Some(Extension::PreSharedKey { binders: b, .. }) = out.extensions.last_mut() { *b = binders; }
+
+    let client_early_traffic_secret = match early_data {
+        true => Some(hashes[0].derive_secret(&tickets[0].early_secret()?, "c e traffic", &Encoder::encode_handshake(&out))?),
+        false => None,
+    };
+    Ok(ResumedHello { hello: out, client_early_traffic_secret })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode_client_hello;
+
+    fn ticket(cipher_suite: u16) -> ResumptionTicket {
+        ResumptionTicket {
+            ticket: vec![0x7c; 48],
+            resumption_secret: vec![0x11; if cipher_suite == 0x1302 { 48 } else { 32 }],
+            ticket_nonce: vec![0, 0, 0, 1],
+            ticket_age_add: u32::MAX - 10,
+            ticket_age_ms: 5000,
+            cipher_suite,
+        }
+    }
+
+    #[test]
+    fn test_binders_cover_truncated_hello() {
+        let hello = HelloTemplate {
+            record_version: Some(0x0301),
+            client_random: Some([3; 32]),
+            session_id: Some(vec![4; 32]),
+            cipher_suites: vec![0x1301, 0x1302],
+            extensions: vec![
+                Extension::PreSharedKey { identities: vec![], binders: vec![] },
+                Extension::ServerName { host: "resume.example".into() },
+                Extension::PskKeyExchangeModes { modes: vec![1] },
+                Extension::BoringPadding,
+            ],
+            psk_key_exchange_modes: Some(vec![1]),
+            ..Default::default()
+        };
+        let t = ticket(0x1301);
+        let resumed = resume(&hello, &[t.clone(), ticket(0x1302)], true).unwrap();
+        let exts = &resumed.hello.extensions;
+        assert_eq!(exts.len(), 5);
+        assert_eq!(exts[2..4], [Extension::EarlyData, Extension::BoringPadding]);
+        let Some(Extension::PreSharedKey { identities, binders }) = exts.last() else { panic!("pre_shared_key not last") };
+        assert_eq!(identities[0].obfuscated_ticket_age, 4989);
+        assert_eq!(binders.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![32, 48]);
+
+        // binder recalculado a mano: RFC 8446 §4.2.11.2 y §7.1 con SHA-256
+        let label = |l: &str, ctx: &[u8]| {
+            let l = format!("tls13 {}", l);
+            [&[0u8, 32, l.len() as u8][..], l.as_bytes(), &[ctx.len() as u8], ctx].concat()
+        };
+        let mut psk = [0u8; 32];
+        Hkdf::<Sha256>::from_prk(&t.resumption_secret).unwrap().expand(&label("resumption", &[0, 0, 0, 1]), &mut psk).unwrap();
+        let early = Hkdf::<Sha256>::new(None, &psk);
+        let mut binder_key = [0u8; 32];
+        early.expand(&label("res binder", &Sha256::digest(b"")), &mut binder_key).unwrap();
+        let mut finished_key = [0u8; 32];
+        Hkdf::<Sha256>::from_prk(&binder_key).unwrap().expand(&label("finished", b""), &mut finished_key).unwrap();
+        let hs = Encoder::encode_handshake(&resumed.hello);
+        let truncated = &hs[..hs.len() - (2 + 33 + 49)];
+        let mut mac = Hmac::<Sha256>::new_from_slice(&finished_key).unwrap();
+        mac.update(&Sha256::digest(truncated));
+        assert_eq!(binders[0], mac.finalize().into_bytes().to_vec());
+        assert_eq!(resumed.client_early_traffic_secret.as_ref().map(|s| s.len()), Some(32));
+
+        // el padding cuenta el PSK y el hello decodificado lo mantiene al final
+        assert_eq!(hs.len(), 512);
+        assert!(matches!(decode_client_hello(&hs).unwrap().extensions.last(), Some(Extension::PreSharedKey { .. })));
+
+        assert!(!resume(&hello, std::slice::from_ref(&t), false).unwrap().hello.extensions.contains(&Extension::EarlyData));
+        let mut no_modes = hello.clone();
+        no_modes.extensions.remove(2);
+        assert!(resume(&no_modes, &[t], false).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+// Paquetes Initial de QUIC (RFC 9000 §17.2.2, RFC 9001 §5, RFC 9369 §3.3)
+// - initial_secret = HKDF-Extract(salt de la versión, DCID del primer Initial del cliente)
+// - client/server initial secret = HKDF-Expand-Label(initial_secret, "client in"/"server in")
+// - key/iv/hp con etiquetas "quic key"/"quic iv"/"quic hp" (v2: "quicv2 ...")
+// - AES-128-GCM para la carga, AES-128-ECB sobre una muestra para la protección de cabecera
+//
+// El encoder envuelve un ClientHello en datagramas Initial del cliente: frames CRYPTO (en el
+// orden y con los cortes que indique la plantilla), PING/PADDING y relleno hasta 1200 bytes.
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+use aes::Aes128;
+use aes::cipher::BlockEncrypt;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use serde::{Serialize, Deserialize};
+
+use crate::transport_params::write_varint;
+
+pub const QUIC_V1: u32 = 0x0000_0001;
+pub const QUIC_V2: u32 = 0x6b33_43cf;
+
+const INITIAL_SALT_V1: &[u8] = &[
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3,
+    0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+
+const INITIAL_SALT_V2: &[u8] = &[
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb,
+    0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+
+// draft-ietf-quic-tls-29 .. -32 (Chrome antiguo)
+const INITIAL_SALT_DRAFT29: &[u8] = &[
+    0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c,
+    0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61, 0x11, 0xe0,
+    0x43, 0x90, 0xa8, 0x99,
+];
+
+/// Tamaño mínimo de un datagrama con un Initial del cliente (RFC 9000 §14.1).
+pub const MIN_INITIAL_DATAGRAM: usize = 1200;
+
+const AEAD_TAG_LEN: usize = 16;
+
+/// Which endpoint protected the packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction { Client, Server }
+
+/// AEAD key, IV and header-protection key for one direction of the Initial space.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InitialKeys {
+    pub key: [u8; 16],
+    pub iv: [u8; 12],
+    pub hp: [u8; 16],
+}
+
+impl InitialKeys {
+    /// AEAD nonce for packet number `pn`: the IV XORed with the left-padded packet number.
+    pub fn nonce(&self, pn: u64) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (n, p) in nonce[4..].iter_mut().zip(pn.to_be_bytes()) { *n ^= p; }
+        nonce
+    }
+}
+
+// derive key/iv using HKDF-Expand-Label syntax (RFC 8446 §7.1) con contexto vacío
+fn hkdf_expand_label(secret: &[u8], label: &str, out: &mut [u8]) -> Result<(), String> {
+    let full_label = format!("tls13 {}", label);
+    let mut info: Vec<u8> = Vec::with_capacity(4 + full_label.len());
+    info.extend_from_slice(&(out.len() as u16).to_be_bytes());
+    info.push(full_label.len() as u8);
+    info.extend_from_slice(full_label.as_bytes());
+    info.push(0u8);
+    let hk = Hkdf::<Sha256>::from_prk(secret).map_err(|_| "hkdf prk too short".to_string())?;
+    hk.expand(&info, out).map_err(|_| "hkdf expand error".to_string())
+}
+
+fn version_params(version: u32) -> Result<(&'static [u8], &'static str), String> {
+    match version {
+        QUIC_V1 => Ok((INITIAL_SALT_V1, "quic")),
+        QUIC_V2 => Ok((INITIAL_SALT_V2, "quicv2")),
+        v if (0xff00_001d..=0xff00_0020).contains(&v) => Ok((INITIAL_SALT_DRAFT29, "quic")),
+        v => Err(format!("unsupported QUIC version {:#010x}", v)),
+    }
+}
+
+/// Long-header type bits of an Initial packet for `version` (v2 permutes the codepoints).
+pub fn initial_type_bits(version: u32) -> u8 {
+    if version == QUIC_V2 { 0x01 } else { 0x00 }
+}
+
+/// Derives the Initial keys of `direction` from the client's original Destination Connection ID.
+pub fn initial_keys(version: u32, client_dcid: &[u8], direction: Direction) -> Result<InitialKeys, String> {
+    let (salt, prefix) = version_params(version)?;
+    let (initial_secret, _) = Hkdf::<Sha256>::extract(Some(salt), client_dcid);
+    let mut secret = [0u8; 32];
+    let label = match direction { Direction::Client => "client in", Direction::Server => "server in" };
+    hkdf_expand_label(&initial_secret, label, &mut secret)?;
+    let mut keys = InitialKeys { key: [0; 16], iv: [0; 12], hp: [0; 16] };
+    hkdf_expand_label(&secret, &format!("{} key", prefix), &mut keys.key)?;
+    hkdf_expand_label(&secret, &format!("{} iv", prefix), &mut keys.iv)?;
+    hkdf_expand_label(&secret, &format!("{} hp", prefix), &mut keys.hp)?;
+    Ok(keys)
+}
+
+/// AES-based header protection mask over a 16-byte sample (RFC 9001 §5.4.3).
+pub fn header_protection_mask(hp: &[u8; 16], sample: &[u8]) -> Result<[u8; 5], String> {
+    let mut block = aes::Block::clone_from_slice(sample.get(..16).ok_or("hp sample too short")?);
+    Aes128::new_from_slice(hp).map_err(|e| format!("hp init: {:?}", e))?.encrypt_block(&mut block);
+    let mut mask = [0u8; 5];
+    mask.copy_from_slice(&block[..5]);
+    Ok(mask)
+}
+
+/// One frame of a templated Initial packet. CRYPTO ranges refer to the ClientHello bytes, so a
+/// layout can cut the message anywhere and send the pieces in any order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum QuicFrameSpec {
+    Crypto { offset: usize, len: usize },
+    Ping,
+    Padding { len: usize },
+}
+
+/// Header fields and packet layout for the client's Initial datagrams.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuicInitialOptions {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    pub dcid: Vec<u8>,
+    pub scid: Vec<u8>,
+    #[serde(default)]
+    pub token: Vec<u8>,
+    #[serde(default)]
+    pub first_packet_number: u64,
+    #[serde(default = "default_pn_len")]
+    pub pn_len: u8,                         // 1..=4 bytes
+    #[serde(default = "default_datagram_size")]
+    pub datagram_size: usize,               // tamaño mínimo de cada datagrama (relleno con PADDING)
+    #[serde(default)]
+    pub packets: Vec<Vec<QuicFrameSpec>>,   // un paquete (y datagrama) por entrada; vacío → reparto secuencial
+}
+
+fn default_version() -> u32 { QUIC_V1 }
+fn default_pn_len() -> u8 { 1 }
+fn default_datagram_size() -> usize { MIN_INITIAL_DATAGRAM }
+
+impl QuicInitialOptions {
+    pub fn new(dcid: Vec<u8>, scid: Vec<u8>) -> Self {
+        QuicInitialOptions {
+            version: QUIC_V1, dcid, scid, token: Vec::new(), first_packet_number: 0,
+            pn_len: 1, datagram_size: MIN_INITIAL_DATAGRAM, packets: Vec::new(),
+        }
+    }
+
+    fn header_len(&self) -> usize {
+        // primer byte + versión + CIDs + token + Length (siempre 2 bytes)
+        let mut tok = Vec::new();
+        write_varint(&mut tok, self.token.len() as u64);
+        1 + 4 + 1 + self.dcid.len() + 1 + self.scid.len() + tok.len() + self.token.len() + 2
+    }
+
+    /// Sequential layout: as many CRYPTO bytes per packet as fit in `datagram_size`.
+    fn sequential_layout(&self, crypto_len: usize) -> Result<Vec<Vec<QuicFrameSpec>>, String> {
+        // tipo + offset (≤4) + longitud (2)
+        let overhead = self.header_len() + self.pn_len as usize + AEAD_TAG_LEN + 7;
+        let room = self.datagram_size.checked_sub(overhead).filter(|r| *r > 0).ok_or("datagram_size too small for an Initial packet")?;
+        let mut packets = Vec::new();
+        let mut offset = 0;
+        while offset < crypto_len {
+            let len = room.min(crypto_len - offset);
+            packets.push(vec![QuicFrameSpec::Crypto { offset, len }]);
+            offset += len;
+        }
+        Ok(packets)
+    }
+}
+
+fn encode_frames(frames: &[QuicFrameSpec], crypto: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    for f in frames {
+        match f {
+            QuicFrameSpec::Crypto { offset, len } => {
+                let data = crypto.get(*offset..*offset + *len).ok_or("CRYPTO range outside the ClientHello")?;
+                out.push(0x06);
+                write_varint(&mut out, *offset as u64);
+                write_varint(&mut out, *len as u64);
+                out.extend_from_slice(data);
+            }
+            QuicFrameSpec::Ping => out.push(0x01),
+            QuicFrameSpec::Padding { len } => out.resize(out.len() + len, 0),
+        }
+    }
+    Ok(out)
+}
+
+/// Builds one protected Initial packet carrying `frames` (already encoded), padded so the packet
+/// is at least `min_len` bytes long.
+fn protect_initial(opts: &QuicInitialOptions, keys: &InitialKeys, pn: u64, mut frames: Vec<u8>, min_len: usize) -> Result<Vec<u8>, String> {
+    let pn_len = opts.pn_len as usize;
+    if !(1..=4).contains(&pn_len) { return Err("pn_len must be 1..=4".into()); }
+    let header_len = opts.header_len();
+    // la muestra de hp empieza 4 bytes tras el packet number y necesita 16 bytes
+    let min_payload = (4usize.saturating_sub(pn_len)).max(min_len.saturating_sub(header_len + pn_len + AEAD_TAG_LEN));
+    if frames.len() < min_payload { frames.resize(min_payload, 0); }
+    let length = pn_len + frames.len() + AEAD_TAG_LEN;
+    if length >= 1 << 14 { return Err("Initial packet too large".into()); }
+
+    let mut header = vec![0xc0 | (initial_type_bits(opts.version) << 4) | (pn_len as u8 - 1)];
+    header.extend_from_slice(&opts.version.to_be_bytes());
+    header.push(opts.dcid.len() as u8);
+    header.extend_from_slice(&opts.dcid);
+    header.push(opts.scid.len() as u8);
+    header.extend_from_slice(&opts.scid);
+    write_varint(&mut header, opts.token.len() as u64);
+    header.extend_from_slice(&opts.token);
+    header.extend_from_slice(&(0x4000 | length as u16).to_be_bytes());
+    let pn_offset = header.len();
+    header.extend_from_slice(&pn.to_be_bytes()[8 - pn_len..]);
+
+    let cipher = Aes128Gcm::new_from_slice(&keys.key).map_err(|e| format!("cipher init: {:?}", e))?;
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&keys.nonce(pn)), Payload { msg: &frames, aad: &header })
+        .map_err(|_| "Initial packet encrypt failed".to_string())?;
+    let mut packet = header;
+    packet.extend_from_slice(&sealed);
+
+    let mask = header_protection_mask(&keys.hp, &packet[pn_offset + 4..pn_offset + 20])?;
+    packet[0] ^= mask[0] & 0x0f;
+    for i in 0..pn_len { packet[pn_offset + i] ^= mask[1 + i]; }
+    Ok(packet)
+}
+
+/// Encodes `crypto` (the bare ClientHello handshake message) as the client's Initial datagrams,
+/// one packet per datagram, following `opts.packets` or a sequential split when it is empty.
+pub fn encode_quic_initials(crypto: &[u8], opts: &QuicInitialOptions) -> Result<Vec<Vec<u8>>, String> {
+    let keys = initial_keys(opts.version, &opts.dcid, Direction::Client)?;
+    let layout = if opts.packets.is_empty() { opts.sequential_layout(crypto.len())? } else { opts.packets.clone() };
+    let mut datagrams = Vec::with_capacity(layout.len());
+    for (i, frames) in layout.iter().enumerate() {
+        let payload = encode_frames(frames, crypto)?;
+        let pn = opts.first_packet_number + i as u64;
+        let packet = protect_initial(opts, &keys, pn, payload, opts.datagram_size)?;
+        datagrams.push(packet);
+    }
+    Ok(datagrams)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_layout_and_padding() {
+        let ch: Vec<u8> = (0..2000u32).map(|i| i as u8).collect();
+        let opts = QuicInitialOptions::new(vec![0x83; 8], vec![]);
+        let dgs = encode_quic_initials(&ch, &opts).unwrap();
+        assert_eq!(dgs.len(), 2);
+        assert!(dgs.iter().all(|d| d.len() == MIN_INITIAL_DATAGRAM));
+        // cabecera larga, bit fijo, Initial v1; la versión y los CIDs van en claro
+        assert_eq!(dgs[0][0] & 0xf0, 0xc0);
+        assert_eq!(&dgs[0][1..7], &[0, 0, 0, 1, 8, 0x83]);
+
+        // disposición explícita: trozos fuera de orden con PING intercalado
+        let mut chrome = opts.clone();
+        chrome.packets = vec![vec![
+            QuicFrameSpec::Crypto { offset: 1000, len: 1000 },
+            QuicFrameSpec::Ping,
+            QuicFrameSpec::Padding { len: 3 },
+        ], vec![QuicFrameSpec::Crypto { offset: 0, len: 1000 }]];
+        let dgs = encode_quic_initials(&ch, &chrome).unwrap();
+        assert_eq!(dgs.iter().map(|d| d.len()).collect::<Vec<_>>(), vec![1200, 1200]);
+        chrome.packets[1].push(QuicFrameSpec::Crypto { offset: 1990, len: 20 });
+        assert!(encode_quic_initials(&ch, &chrome).is_err());
+    }
+}
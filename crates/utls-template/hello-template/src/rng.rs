@@ -0,0 +1,62 @@
+// Fuente de los valores que cambian en cada conexión (random, session ID, key shares, GREASE).
+// Con semilla la salida es reproducible byte a byte (goldens, tests); sin ella se siembra del SO.
+
+use rand_chacha::ChaCha20Rng;
+use rand_core::{CryptoRng, RngCore, SeedableRng};
+
+/// ChaCha20-based RNG for per-connection hello values.
+pub struct HelloRng(ChaCha20Rng);
+
+impl HelloRng {
+    /// Deterministic stream: the same seed always yields the same hellos.
+    pub fn seeded(seed: u64) -> Self {
+        HelloRng(ChaCha20Rng::seed_from_u64(seed))
+    }
+
+    /// Seeded from the operating system; what a real client does.
+    pub fn from_entropy() -> Self {
+        HelloRng(ChaCha20Rng::from_entropy())
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        self.0.fill_bytes(buf);
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut v = vec![0u8; len];
+        self.fill(&mut v);
+        v
+    }
+
+    /// Uniform value in `0..n` (`n > 0`), without modulo bias.
+    pub fn below(&mut self, n: u32) -> u32 {
+        let zone = u32::MAX - (u32::MAX % n);
+        loop {
+            let v = self.0.next_u32();
+            if v < zone { return v % n; }
+        }
+    }
+}
+
+// Para pasar el mismo flujo a la generación de claves (x25519-dalek, p256, ml-kem)
+impl RngCore for HelloRng {
+    fn next_u32(&mut self) -> u32 { self.0.next_u32() }
+    fn next_u64(&mut self) -> u64 { self.0.next_u64() }
+    fn fill_bytes(&mut self, dest: &mut [u8]) { self.0.fill_bytes(dest) }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> { self.0.try_fill_bytes(dest) }
+}
+
+impl CryptoRng for HelloRng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_is_reproducible() {
+        let (mut a, mut b) = (HelloRng::seeded(7), HelloRng::seeded(7));
+        assert_eq!(a.bytes(32), b.bytes(32));
+        assert_ne!(a.bytes(32), HelloRng::seeded(8).bytes(32));
+        assert!((0..1000).all(|_| a.below(16) < 16));
+    }
+}
@@ -0,0 +1,216 @@
+// quic_transport_parameters (extensión 0x39, RFC 9000 §18): lista ordenada de (id varint,
+// longitud varint, valor). Se decodifican a variantes tipadas solo cuando el valor se vuelve a
+// codificar byte a byte igual; si no, quedan como `Unknown` para no alterar el ClientHello.
+
+use serde::{Serialize, Deserialize};
+
+/// One transport parameter as sent by the client, in wire order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TransportParam {
+    MaxIdleTimeout(u64),
+    MaxUdpPayloadSize(u64),
+    InitialMaxData(u64),
+    InitialMaxStreamDataBidiLocal(u64),
+    InitialMaxStreamDataBidiRemote(u64),
+    InitialMaxStreamDataUni(u64),
+    InitialMaxStreamsBidi(u64),
+    InitialMaxStreamsUni(u64),
+    AckDelayExponent(u64),
+    MaxAckDelay(u64),
+    DisableActiveMigration,
+    ActiveConnectionIdLimit(u64),
+    InitialSourceConnectionId(Vec<u8>),
+    VersionInformation { chosen: u32, available: Vec<u32> }, // RFC 9368
+    MaxDatagramFrameSize(u64),                               // RFC 9221
+    GreaseQuicBit,                                           // RFC 9287
+    MinAckDelay(u64),                                        // draft-ietf-quic-ack-frequency
+    Grease { id: u64, value: Vec<u8> },                      // id = 31 * N + 27
+    Unknown { id: u64, value: Vec<u8> },
+}
+
+impl TransportParam {
+    /// Codepoint on the wire for this parameter.
+    pub fn id(&self) -> u64 {
+        match self {
+            TransportParam::MaxIdleTimeout(_) => 0x01,
+            TransportParam::MaxUdpPayloadSize(_) => 0x03,
+            TransportParam::InitialMaxData(_) => 0x04,
+            TransportParam::InitialMaxStreamDataBidiLocal(_) => 0x05,
+            TransportParam::InitialMaxStreamDataBidiRemote(_) => 0x06,
+            TransportParam::InitialMaxStreamDataUni(_) => 0x07,
+            TransportParam::InitialMaxStreamsBidi(_) => 0x08,
+            TransportParam::InitialMaxStreamsUni(_) => 0x09,
+            TransportParam::AckDelayExponent(_) => 0x0a,
+            TransportParam::MaxAckDelay(_) => 0x0b,
+            TransportParam::DisableActiveMigration => 0x0c,
+            TransportParam::ActiveConnectionIdLimit(_) => 0x0e,
+            TransportParam::InitialSourceConnectionId(_) => 0x0f,
+            TransportParam::VersionInformation { .. } => 0x11,
+            TransportParam::MaxDatagramFrameSize(_) => 0x20,
+            TransportParam::GreaseQuicBit => 0x2ab2,
+            TransportParam::MinAckDelay(_) => 0xff04_de1b,
+            TransportParam::Grease { id, .. } | TransportParam::Unknown { id, .. } => *id,
+        }
+    }
+
+    /// Encoded value (without id and length).
+    pub fn value(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_value(&mut out);
+        out
+    }
+
+    fn encode_value(&self, out: &mut Vec<u8>) {
+        match self {
+            TransportParam::MaxIdleTimeout(v)
+            | TransportParam::MaxUdpPayloadSize(v)
+            | TransportParam::InitialMaxData(v)
+            | TransportParam::InitialMaxStreamDataBidiLocal(v)
+            | TransportParam::InitialMaxStreamDataBidiRemote(v)
+            | TransportParam::InitialMaxStreamDataUni(v)
+            | TransportParam::InitialMaxStreamsBidi(v)
+            | TransportParam::InitialMaxStreamsUni(v)
+            | TransportParam::AckDelayExponent(v)
+            | TransportParam::MaxAckDelay(v)
+            | TransportParam::ActiveConnectionIdLimit(v)
+            | TransportParam::MaxDatagramFrameSize(v)
+            | TransportParam::MinAckDelay(v) => write_varint(out, *v),
+            TransportParam::DisableActiveMigration | TransportParam::GreaseQuicBit => {}
+            TransportParam::InitialSourceConnectionId(cid) => out.extend_from_slice(cid),
+            TransportParam::VersionInformation { chosen, available } => {
+                out.extend_from_slice(&chosen.to_be_bytes());
+                for v in available { out.extend_from_slice(&v.to_be_bytes()); }
+            }
+            TransportParam::Grease { value, .. } | TransportParam::Unknown { value, .. } => out.extend_from_slice(value),
+        }
+    }
+}
+
+/// Reserved (GREASE) transport parameter ids: `31 * N + 27` (RFC 9000 §18.1).
+pub fn is_grease_param(id: u64) -> bool {
+    id >= 27 && (id - 27).is_multiple_of(31)
+}
+
+/// Minimal-length QUIC variable-length integer.
+pub fn write_varint(out: &mut Vec<u8>, v: u64) {
+    if v < 1 << 6 {
+        out.push(v as u8);
+    } else if v < 1 << 14 {
+        out.extend_from_slice(&((v as u16) | 0x4000).to_be_bytes());
+    } else if v < 1 << 30 {
+        out.extend_from_slice(&((v as u32) | 0x8000_0000).to_be_bytes());
+    } else {
+        out.extend_from_slice(&(v | 0xc000_0000_0000_0000).to_be_bytes());
+    }
+}
+
+pub fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let first = *buf.get(*pos).ok_or("truncated varint")?;
+    let len = 1usize << (first >> 6);
+    let bytes = buf.get(*pos..*pos + len).ok_or("truncated varint")?;
+    let mut v = (first & 0x3f) as u64;
+    for b in &bytes[1..] { v = (v << 8) | *b as u64; }
+    *pos += len;
+    Ok(v)
+}
+
+fn decode_typed(id: u64, value: &[u8]) -> Result<TransportParam, String> {
+    let int = || -> Result<u64, String> {
+        let mut pos = 0;
+        let v = read_varint(value, &mut pos)?;
+        if pos != value.len() { return Err("trailing bytes in integer parameter".into()); }
+        Ok(v)
+    };
+    Ok(match id {
+        0x01 => TransportParam::MaxIdleTimeout(int()?),
+        0x03 => TransportParam::MaxUdpPayloadSize(int()?),
+        0x04 => TransportParam::InitialMaxData(int()?),
+        0x05 => TransportParam::InitialMaxStreamDataBidiLocal(int()?),
+        0x06 => TransportParam::InitialMaxStreamDataBidiRemote(int()?),
+        0x07 => TransportParam::InitialMaxStreamDataUni(int()?),
+        0x08 => TransportParam::InitialMaxStreamsBidi(int()?),
+        0x09 => TransportParam::InitialMaxStreamsUni(int()?),
+        0x0a => TransportParam::AckDelayExponent(int()?),
+        0x0b => TransportParam::MaxAckDelay(int()?),
+        0x0c if value.is_empty() => TransportParam::DisableActiveMigration,
+        0x0e => TransportParam::ActiveConnectionIdLimit(int()?),
+        0x0f => TransportParam::InitialSourceConnectionId(value.to_vec()),
+        0x11 if value.len() >= 4 && value.len().is_multiple_of(4) => {
+            let mut words = value.chunks(4).map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]));
+            let chosen = words.next().unwrap_or_default();
+            TransportParam::VersionInformation { chosen, available: words.collect() }
+        }
+        0x20 => TransportParam::MaxDatagramFrameSize(int()?),
+        0x2ab2 if value.is_empty() => TransportParam::GreaseQuicBit,
+        0xff04_de1b => TransportParam::MinAckDelay(int()?),
+        id if is_grease_param(id) => TransportParam::Grease { id, value: value.to_vec() },
+        id => TransportParam::Unknown { id, value: value.to_vec() },
+    })
+}
+
+/// Decodes the body of a quic_transport_parameters extension, keeping wire order.
+pub fn decode_transport_params(data: &[u8]) -> Result<Vec<TransportParam>, String> {
+    let mut params = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let start = pos;
+        let id = read_varint(data, &mut pos)?;
+        let len = read_varint(data, &mut pos)? as usize;
+        let value = data.get(pos..pos + len).ok_or("transport parameter value truncated")?;
+        pos += len;
+        // solo se acepta la forma tipada si reproduce exactamente los bytes originales
+        let param = match decode_typed(id, value) {
+            Ok(p) if encode_param(&p) == data[start..pos] => p,
+            _ if is_grease_param(id) => TransportParam::Grease { id, value: value.to_vec() },
+            _ => TransportParam::Unknown { id, value: value.to_vec() },
+        };
+        if encode_param(&param) != data[start..pos] {
+            return Err(format!("transport parameter {:#x} uses a non-minimal encoding", id));
+        }
+        params.push(param);
+    }
+    Ok(params)
+}
+
+fn encode_param(p: &TransportParam) -> Vec<u8> {
+    let value = p.value();
+    let mut out = Vec::with_capacity(value.len() + 4);
+    write_varint(&mut out, p.id());
+    write_varint(&mut out, value.len() as u64);
+    out.extend_from_slice(&value);
+    out
+}
+
+/// Encodes the body of a quic_transport_parameters extension.
+pub fn encode_transport_params(params: &[TransportParam]) -> Vec<u8> {
+    params.iter().flat_map(encode_param).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_params_round_trip() {
+        // orden y valores típicos de Chrome, con un parámetro GREASE y google_version (desconocido)
+        let params = vec![
+            TransportParam::InitialMaxStreamDataUni(6_291_456),
+            TransportParam::Grease { id: 31 * 1234 + 27, value: vec![0x42, 0x99] },
+            TransportParam::MaxIdleTimeout(30_000),
+            TransportParam::InitialSourceConnectionId(vec![]),
+            TransportParam::Unknown { id: 0x4752, value: vec![0, 0, 0, 1] },
+            TransportParam::VersionInformation { chosen: 1, available: vec![0x6b33_43cf, 1] },
+            TransportParam::DisableActiveMigration,
+            TransportParam::MaxDatagramFrameSize(65536),
+            TransportParam::GreaseQuicBit,
+        ];
+        let wire = encode_transport_params(&params);
+        assert_eq!(decode_transport_params(&wire).unwrap(), params);
+        assert_eq!(&wire[..6], &[0x07, 0x04, 0x80, 0x60, 0x00, 0x00]);
+
+        // varint no mínimo: el parámetro se conserva opaco pero idéntico en bytes
+        let odd = [0x01, 0x02, 0x40, 0x05];
+        assert_eq!(decode_transport_params(&odd).unwrap(), vec![TransportParam::Unknown { id: 1, value: vec![0x40, 0x05] }]);
+        assert_eq!(encode_transport_params(&decode_transport_params(&odd).unwrap()), odd);
+    }
+}
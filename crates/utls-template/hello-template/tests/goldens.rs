@@ -0,0 +1,26 @@
+// Compara la salida determinista del generador con los bundles de tests/goldens/<nombre>/.
+// Para regenerarlos tras un cambio intencionado: `cargo run -p utls-cli -- golden`
+// (o UPDATE_GOLDENS=1 cargo test -p hello-template --test goldens).
+
+use std::path::Path;
+
+use hello_snapshot::golden::{build_golden_dir, check_golden, write_golden, GOLDEN_SEED, GOLDEN_TEMPLATE};
+
+#[test]
+fn goldens_match() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/goldens");
+    let update = std::env::var_os("UPDATE_GOLDENS").is_some();
+    let mut dirs: Vec<_> = std::fs::read_dir(&root).unwrap().map(|e| e.unwrap().path()).filter(|p| p.join(GOLDEN_TEMPLATE).is_file()).collect();
+    dirs.sort();
+    assert!(!dirs.is_empty(), "no goldens under {}", root.display());
+
+    let failures: Vec<String> = dirs
+        .iter()
+        .filter_map(|d| {
+            let bundle = build_golden_dir(d, GOLDEN_SEED).unwrap();
+            if update { write_golden(d, &bundle).unwrap(); }
+            check_golden(d, &bundle).err()
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,49 @@
+package templates
+
+import "github.com/refraction-networking/utls"
+
+var Spec = &utls.ClientHelloSpec{
+  TLSVersMin: 0x0303, TLSVersMax: 0x0304,
+  CipherSuites: []uint16{
+    uint16(utls.GREASE_PLACEHOLDER),
+    0x1301,
+    0x1302,
+    0x1303,
+    0xc02b,
+    0xc02f,
+    0xc02c,
+    0xc030,
+    0xcca9,
+    0xcca8,
+    0xc013,
+    0xc014,
+    0x009c,
+    0x009d,
+    0x002f,
+    0x0035,
+  },
+  Extensions: []utls.TLSExtension{
+    &utls.UtlsGREASEExtension{},
+    &utls.SNIExtension{ServerName: "example.com"},
+    &utls.ExtendedMasterSecretExtension{},
+    &utls.RenegotiationInfoExtension{Renegotiation: utls.RenegotiateOnceAsClient},
+    &utls.SupportedCurvesExtension{Curves: []utls.CurveID{utls.CurveID(utls.GREASE_PLACEHOLDER), 0x11ec, 0x001d, 0x0017, 0x0018}},
+    &utls.GenericExtension{Id: 0x000b, Data: []byte{0x01, 0x00}},
+    &utls.SessionTicketExtension{Session: nil, Ticket: []byte{}},
+    &utls.ALPNExtension{AlpnProtocols: []string{
+      "h2",
+      "http/1.1",
+    }},
+    &utls.StatusRequestExtension{},
+    &utls.SignatureAlgorithmsExtension{SupportedSignatureAlgorithms: []utls.SignatureScheme{0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601}},
+    &utls.SCTExtension{},
+    &utls.KeyShareExtension{KeyShares: []utls.KeyShare{{Group: utls.CurveID(utls.GREASE_PLACEHOLDER), Data: []byte{0}}, {Group: utls.CurveID(0x11ec)}, {Group: utls.CurveID(0x001d)}}},
+    &utls.PSKKeyExchangeModesExtension{Modes: []uint8{1}},
+    &utls.SupportedVersionsExtension{Versions: []uint16{uint16(utls.GREASE_PLACEHOLDER), 0x0304, 0x0303}},
+    &utls.UtlsCompressCertExtension{Algorithms: []utls.CertCompressionAlgo{utls.CertCompressionAlgo(0x0002)}},
+    &utls.ApplicationSettingsExtensionNew{SupportedProtocols: []string{"h2"}},
+    &utls.GREASEEncryptedClientHelloExtension{CandidateCipherSuites: []utls.HPKESymmetricCipherSuite{{KdfId: 0x0001, AeadId: 0x0001}}, CandidatePayloadLens: []uint16{160}},
+    &utls.UtlsGREASEExtension{},
+    &utls.UtlsPaddingExtension{GetPaddingLen: utls.BoringPaddingStyle},
+  },
+}
@@ -0,0 +1,44 @@
+package templates
+
+import "github.com/refraction-networking/utls"
+
+var Spec = &utls.ClientHelloSpec{
+  TLSVersMin: 0x0303, TLSVersMax: 0x0304,
+  CipherSuites: []uint16{
+    0x1301,
+    0x1303,
+    0x1302,
+    0xc02b,
+    0xc02f,
+    0xcca9,
+    0xcca8,
+    0xc02c,
+    0xc030,
+    0xc00a,
+    0xc009,
+    0xc013,
+    0xc014,
+    0x009c,
+    0x009d,
+    0x002f,
+    0x0035,
+  },
+  Extensions: []utls.TLSExtension{
+    &utls.SNIExtension{ServerName: "example.com"},
+    &utls.ExtendedMasterSecretExtension{},
+    &utls.RenegotiationInfoExtension{Renegotiation: utls.RenegotiateOnceAsClient},
+    &utls.SupportedCurvesExtension{Curves: []utls.CurveID{0x001d, 0x0017, 0x0018, 0x0019, 0x0100, 0x0101}},
+    &utls.GenericExtension{Id: 0x000b, Data: []byte{0x01, 0x00}},
+    &utls.SessionTicketExtension{Session: nil, Ticket: []byte{}},
+    &utls.ALPNExtension{AlpnProtocols: []string{
+      "h2",
+      "http/1.1",
+    }},
+    &utls.StatusRequestExtension{},
+    &utls.KeyShareExtension{KeyShares: []utls.KeyShare{{Group: utls.CurveID(0x001d)}, {Group: utls.CurveID(0x0017)}}},
+    &utls.SupportedVersionsExtension{Versions: []uint16{0x0304, 0x0303}},
+    &utls.SignatureAlgorithmsExtension{SupportedSignatureAlgorithms: []utls.SignatureScheme{0x0403, 0x0503, 0x0603, 0x0804, 0x0805, 0x0806, 0x0401, 0x0501, 0x0601, 0x0203, 0x0201}},
+    &utls.PSKKeyExchangeModesExtension{Modes: []uint8{1}},
+    &utls.FakeRecordSizeLimitExtension{Limit: 0x4001},
+  },
+}